@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE barber_schedule_overrides;
+DROP TABLE barber_working_hours;
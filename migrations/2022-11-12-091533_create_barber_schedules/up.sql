@@ -0,0 +1,51 @@
+-- Your SQL goes here
+
+CREATE TABLE barber_working_hours (
+    id BIGSERIAL PRIMARY KEY,
+    working_hour_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    barber_id UUID NOT NULL,
+    weekday INT NOT NULL, -- 1 周一 ... 7 周日
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+    kind VARCHAR NOT NULL, -- work / break
+    enabled BOOLEAN NOT NULL, 
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX barber_working_hours_working_hour_id_key ON barber_working_hours
+(working_hour_id);
+
+CREATE INDEX barber_working_hours_barber_id_idx ON barber_working_hours
+(barber_id);
+
+CREATE INDEX barber_working_hours_enabled_idx ON barber_working_hours
+(enabled);
+
+-- 指定日期的排班调整（请假、加班等），覆盖当天的每周工作时间
+CREATE TABLE barber_schedule_overrides (
+    id BIGSERIAL PRIMARY KEY,
+    override_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    barber_id UUID NOT NULL,
+    override_date DATE NOT NULL,
+    is_day_off BOOLEAN NOT NULL,
+    start_time TIME NULL,
+    end_time TIME NULL,
+    remark TEXT NULL,
+    enabled BOOLEAN NOT NULL, 
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX barber_schedule_overrides_override_id_key ON barber_schedule_overrides
+(override_id);
+
+CREATE INDEX barber_schedule_overrides_barber_id_date_idx ON barber_schedule_overrides
+(barber_id, override_date);
+
+CREATE INDEX barber_schedule_overrides_enabled_idx ON barber_schedule_overrides
+(enabled);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE merchant_closures;
+DROP TABLE merchant_business_hours;
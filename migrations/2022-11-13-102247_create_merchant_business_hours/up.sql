@@ -0,0 +1,46 @@
+-- Your SQL goes here
+
+CREATE TABLE merchant_business_hours (
+    id BIGSERIAL PRIMARY KEY,
+    business_hour_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    weekday INT NOT NULL, -- 1 周一 ... 7 周日
+    open_time TIME NOT NULL,
+    close_time TIME NOT NULL, -- 不晚于 open_time 时表示营业到次日
+    enabled BOOLEAN NOT NULL, 
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX merchant_business_hours_business_hour_id_key ON merchant_business_hours
+(business_hour_id);
+
+CREATE INDEX merchant_business_hours_merchant_id_idx ON merchant_business_hours
+(merchant_id);
+
+CREATE INDEX merchant_business_hours_enabled_idx ON merchant_business_hours
+(enabled);
+
+-- 节假日及临时停业
+CREATE TABLE merchant_closures (
+    id BIGSERIAL PRIMARY KEY,
+    closure_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    closure_date DATE NOT NULL,
+    kind VARCHAR NOT NULL, -- holiday / closure
+    name VARCHAR NULL,
+    enabled BOOLEAN NOT NULL, 
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX merchant_closures_closure_id_key ON merchant_closures
+(closure_id);
+
+CREATE INDEX merchant_closures_merchant_id_date_idx ON merchant_closures
+(merchant_id, closure_date);
+
+CREATE INDEX merchant_closures_enabled_idx ON merchant_closures
+(enabled);
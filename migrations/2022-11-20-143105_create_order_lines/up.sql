@@ -0,0 +1,34 @@
+-- Your SQL goes here
+
+-- 订单明细，一个订单可包含多项服务，每项服务可由不同理发师完成
+CREATE TABLE order_lines (
+    id BIGSERIAL PRIMARY KEY,
+    order_line_id UUID NOT NULL,
+    order_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    service_type_id UUID NOT NULL,
+    barber_id UUID NOT NULL,
+    amount NUMERIC NOT NULL,
+    duration INT NOT NULL, -- 分钟
+    enabled BOOLEAN NOT NULL, 
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX order_lines_order_line_id_key ON order_lines
+(order_line_id);
+
+CREATE INDEX order_lines_order_id_idx ON order_lines
+(order_id);
+
+CREATE INDEX order_lines_barber_id_idx ON order_lines
+(barber_id);
+
+CREATE INDEX order_lines_enabled_idx ON order_lines
+(enabled);
+
+-- 已有订单生成一条明细
+INSERT INTO order_lines (order_line_id,order_id,merchant_id,service_type_id,barber_id,amount,duration,enabled,create_time,update_time,data)
+SELECT uuid_generate_v4(),order_id,merchant_id,service_type_id,barber_id,amount,CAST(EXTRACT(EPOCH FROM end_time-start_time)/60 AS INT),enabled,create_time,update_time,NULL
+FROM orders;
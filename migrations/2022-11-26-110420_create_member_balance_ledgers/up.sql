@@ -0,0 +1,31 @@
+-- Your SQL goes here
+
+-- 会员储值流水，只追加，不修改
+CREATE TABLE member_balance_ledgers (
+    id BIGSERIAL PRIMARY KEY,
+    ledger_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    entry_type VARCHAR NOT NULL, -- opening / recharge / order_payment / refund / adjustment
+    amount NUMERIC NOT NULL, -- 正数为增加，负数为减少
+    balance NUMERIC NOT NULL, -- 本条流水后的余额
+    reference_id UUID NULL, -- 订单或充值记录
+    barber_id UUID NULL, -- 操作者，期初余额为空
+    remark TEXT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX member_balance_ledgers_ledger_id_key ON member_balance_ledgers
+(ledger_id);
+
+CREATE INDEX member_balance_ledgers_merchant_id_idx ON member_balance_ledgers
+(merchant_id);
+
+CREATE INDEX member_balance_ledgers_member_id_idx ON member_balance_ledgers
+(member_id);
+
+-- 已有会员的余额作为期初余额
+INSERT INTO member_balance_ledgers (ledger_id,merchant_id,member_id,entry_type,amount,balance,reference_id,barber_id,remark,create_time,data)
+SELECT uuid_generate_v4(),merchant_id,member_id,'opening',balance,balance,NULL,NULL,'期初余额',now(),NULL
+FROM merchant_members;
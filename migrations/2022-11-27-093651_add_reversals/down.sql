@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX recharge_records_reversal_of_key;
+ALTER TABLE recharge_records DROP reversal_reason;
+ALTER TABLE recharge_records DROP reversal_of;
+
+DROP INDEX orders_reversal_of_key;
+ALTER TABLE orders DROP reversal_reason;
+ALTER TABLE orders DROP reversal_of;
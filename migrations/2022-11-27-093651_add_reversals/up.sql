@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- 退款/撤销记录与原记录存放在同一张表，金额为负数，通过 reversal_of 关联原记录
+ALTER TABLE orders ADD reversal_of UUID NULL;
+ALTER TABLE orders ADD reversal_reason TEXT NULL;
+
+CREATE UNIQUE INDEX orders_reversal_of_key ON orders
+(reversal_of) WHERE reversal_of IS NOT NULL;
+
+ALTER TABLE recharge_records ADD reversal_of UUID NULL;
+ALTER TABLE recharge_records ADD reversal_reason TEXT NULL;
+
+CREATE UNIQUE INDEX recharge_records_reversal_of_key ON recharge_records
+(reversal_of) WHERE reversal_of IS NOT NULL;
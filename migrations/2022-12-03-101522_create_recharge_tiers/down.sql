@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE recharge_records DROP recharge_tier_id;
+ALTER TABLE recharge_records DROP bonus;
+
+DROP TABLE recharge_tiers;
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+-- 充值优惠档位：充值金额达到 threshold 时赠送 bonus
+CREATE TABLE recharge_tiers (
+    id BIGSERIAL PRIMARY KEY,
+    recharge_tier_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    name VARCHAR NOT NULL,
+    threshold NUMERIC NOT NULL,
+    bonus NUMERIC NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX recharge_tiers_recharge_tier_id_key ON recharge_tiers
+(recharge_tier_id);
+
+CREATE INDEX recharge_tiers_merchant_id_idx ON recharge_tiers
+(merchant_id);
+
+-- 充值记录中 amount 为实付本金，bonus 为赠送金额
+ALTER TABLE recharge_records ADD bonus NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE recharge_records ADD recharge_tier_id UUID NULL;
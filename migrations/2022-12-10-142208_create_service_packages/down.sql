@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE orders DROP member_package_id;
+
+DROP TABLE member_packages;
+DROP TABLE service_package_items;
+DROP TABLE service_packages;
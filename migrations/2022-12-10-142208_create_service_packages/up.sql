@@ -0,0 +1,64 @@
+-- Your SQL goes here
+
+-- 服务套餐（次卡），可限定次数或有效期
+CREATE TABLE service_packages (
+    id BIGSERIAL PRIMARY KEY,
+    package_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    name VARCHAR NOT NULL,
+    price NUMERIC NOT NULL,
+    total_uses INTEGER NULL, -- 可使用次数，为空表示不限次数
+    valid_days INTEGER NULL, -- 购买后有效天数，为空表示长期有效
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX service_packages_package_id_key ON service_packages
+(package_id);
+
+CREATE INDEX service_packages_merchant_id_idx ON service_packages
+(merchant_id);
+
+-- 套餐包含的服务类型
+CREATE TABLE service_package_items (
+    id BIGSERIAL PRIMARY KEY,
+    package_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    service_type_id UUID NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE INDEX service_package_items_package_id_idx ON service_package_items
+(package_id);
+
+-- 会员购买的套餐
+CREATE TABLE member_packages (
+    id BIGSERIAL PRIMARY KEY,
+    member_package_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    package_id UUID NOT NULL,
+    remaining_uses INTEGER NULL, -- 剩余次数，为空表示不限次数
+    expire_time TIMESTAMPTZ NULL, -- 为空表示长期有效
+    price NUMERIC NOT NULL, -- 购买时的价格
+    payment_type VARCHAR NOT NULL, -- member / cash
+    barber_id UUID NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX member_packages_member_package_id_key ON member_packages
+(member_package_id);
+
+CREATE INDEX member_packages_member_id_idx ON member_packages
+(member_id);
+
+-- 使用套餐支付的订单记录所用的套餐
+ALTER TABLE orders ADD member_package_id UUID NULL;
@@ -0,0 +1,31 @@
+-- Your SQL goes here
+
+-- 订单的各项支付，一个订单可由会员余额、现金、银行卡等多种方式组合支付
+CREATE TABLE order_payments (
+    id BIGSERIAL PRIMARY KEY,
+    payment_id UUID NOT NULL,
+    order_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    tender VARCHAR NOT NULL, -- member / cash / card / wechat / alipay / package
+    amount NUMERIC NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX order_payments_payment_id_key ON order_payments
+(payment_id);
+
+CREATE INDEX order_payments_order_id_idx ON order_payments
+(order_id);
+
+CREATE INDEX order_payments_merchant_id_idx ON order_payments
+(merchant_id);
+
+-- 已有订单按原支付方式生成一条支付记录，非会员支付均视为现金
+INSERT INTO order_payments (payment_id,order_id,merchant_id,tender,amount,enabled,create_time,update_time,data)
+SELECT uuid_generate_v4(),order_id,merchant_id,
+    CASE WHEN payment_type IN ('member','package') THEN payment_type ELSE 'cash' END,
+    amount,true,create_time,update_time,NULL
+FROM orders;
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+UPDATE users SET permissions=(permissions::jsonb - (SELECT permission_id::text FROM permissions WHERE permission_code='PriceOverride'))::text
+WHERE permissions::jsonb ? (SELECT permission_id::text FROM permissions WHERE permission_code='PriceOverride');
+
+DELETE FROM permissions WHERE permission_code='PriceOverride';
+
+ALTER TABLE order_lines DROP price_override_reason;
+ALTER TABLE order_lines DROP list_price;
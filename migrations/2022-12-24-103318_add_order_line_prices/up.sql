@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- 下单时服务类型的标价（会员价或普通价），amount 为实收金额，二者不同时需记录改价原因
+ALTER TABLE order_lines ADD list_price NUMERIC NULL;
+ALTER TABLE order_lines ADD price_override_reason TEXT NULL;
+
+-- 改价权限
+INSERT INTO permissions (permission_id,permission_code,permission_name,description,enabled,create_time,update_time,data) VALUES(uuid_generate_v4(),'PriceOverride','手工改价','预约时修改服务价格',true,now(),now(),null);
+
+-- 已有的商户所有者默认拥有改价权限
+UPDATE users SET permissions=(permissions::jsonb || to_jsonb((SELECT permission_id::text FROM permissions WHERE permission_code='PriceOverride')))::text
+WHERE permissions::jsonb ? (SELECT permission_id::text FROM permissions WHERE permission_code='MerchantAdministrator');
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE merchant_members DROP member_level_id;
+
+DROP TABLE member_level_prices;
+DROP TABLE member_levels;
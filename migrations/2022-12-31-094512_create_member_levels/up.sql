@@ -0,0 +1,36 @@
+-- Your SQL goes here
+
+-- 会员等级：rank 越大等级越高；累计充值或累计消费达到门槛时自动升级
+-- 会员价按 discount_rate 对原价打折，member_level_prices 中设置了价格的服务项目优先使用该价格
+CREATE TABLE member_levels (
+    id BIGSERIAL PRIMARY KEY,
+    member_level_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    name VARCHAR NOT NULL,
+    rank INTEGER NOT NULL,
+    discount_rate NUMERIC NOT NULL,
+    recharge_threshold NUMERIC NULL,
+    spend_threshold NUMERIC NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX member_levels_member_level_id_key ON member_levels
+(member_level_id);
+
+CREATE INDEX member_levels_merchant_id_idx ON member_levels
+(merchant_id);
+
+CREATE TABLE member_level_prices (
+    id BIGSERIAL PRIMARY KEY,
+    member_level_id UUID NOT NULL,
+    service_type_id UUID NOT NULL,
+    price NUMERIC NOT NULL
+);
+
+CREATE UNIQUE INDEX member_level_prices_level_service_key ON member_level_prices
+(member_level_id, service_type_id);
+
+ALTER TABLE merchant_members ADD member_level_id UUID NULL;
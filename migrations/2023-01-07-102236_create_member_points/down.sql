@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE merchant_members DROP points;
+
+DROP TABLE member_points_ledgers;
+DROP TABLE merchant_points_settings;
@@ -0,0 +1,46 @@
+-- Your SQL goes here
+
+-- 商户积分设置：每消费 1 元获得 earn_rate 积分，每积分抵扣 redeem_rate 元，valid_days 为空则永久有效
+CREATE TABLE merchant_points_settings (
+    id BIGSERIAL PRIMARY KEY,
+    merchant_id UUID NOT NULL,
+    earn_rate NUMERIC NOT NULL,
+    redeem_rate NUMERIC NOT NULL,
+    valid_days INTEGER NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX merchant_points_settings_merchant_id_key ON merchant_points_settings
+(merchant_id);
+
+-- 会员积分流水，只追加；获得积分的流水通过 remaining 记录未使用的积分，按过期时间先后扣减
+CREATE TABLE member_points_ledgers (
+    id BIGSERIAL PRIMARY KEY,
+    ledger_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    entry_type VARCHAR NOT NULL, -- earn / redeem / conversion / expire / refund / earn_reversal
+    points INTEGER NOT NULL, -- 正数为增加，负数为减少
+    balance INTEGER NOT NULL, -- 本条流水后的积分
+    remaining INTEGER NOT NULL, -- 增加积分的流水中尚未使用的积分
+    expire_time TIMESTAMPTZ NULL,
+    reference_id UUID NULL, -- 订单
+    barber_id UUID NULL,
+    remark TEXT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX member_points_ledgers_ledger_id_key ON member_points_ledgers
+(ledger_id);
+
+CREATE INDEX member_points_ledgers_member_id_idx ON member_points_ledgers
+(member_id);
+
+CREATE INDEX member_points_ledgers_reference_id_idx ON member_points_ledgers
+(reference_id);
+
+ALTER TABLE merchant_members ADD points INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE coupon_redemptions;
+DROP TABLE member_coupons;
+DROP TABLE coupon_service_types;
+DROP TABLE coupons;
@@ -0,0 +1,80 @@
+-- Your SQL goes here
+
+-- 优惠券：discount_type 为 percentage 时 discount_value 为折扣百分比，为 fixed 时为减免金额
+-- is_public 为 true 时凭券码即可使用，否则需先发放给会员
+CREATE TABLE coupons (
+    id BIGSERIAL PRIMARY KEY,
+    coupon_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    name VARCHAR NOT NULL,
+    code VARCHAR NOT NULL,
+    discount_type VARCHAR NOT NULL,
+    discount_value NUMERIC NOT NULL,
+    start_time TIMESTAMPTZ NULL,
+    end_time TIMESTAMPTZ NULL,
+    per_member_limit INTEGER NULL, -- 每位会员可使用次数，为空则不限
+    is_public BOOLEAN NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX coupons_coupon_id_key ON coupons
+(coupon_id);
+
+CREATE UNIQUE INDEX coupons_merchant_id_code_key ON coupons
+(merchant_id, code) WHERE enabled;
+
+-- 优惠券限定的服务项目，没有记录则不限
+CREATE TABLE coupon_service_types (
+    id BIGSERIAL PRIMARY KEY,
+    coupon_id UUID NOT NULL,
+    service_type_id UUID NOT NULL
+);
+
+CREATE INDEX coupon_service_types_coupon_id_idx ON coupon_service_types
+(coupon_id);
+
+-- 发放给会员的优惠券
+CREATE TABLE member_coupons (
+    id BIGSERIAL PRIMARY KEY,
+    member_coupon_id UUID NOT NULL,
+    coupon_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX member_coupons_member_coupon_id_key ON member_coupons
+(member_coupon_id);
+
+CREATE INDEX member_coupons_member_id_idx ON member_coupons
+(member_id);
+
+-- 优惠券使用记录，预约取消后 enabled 为 false，不计入使用次数
+CREATE TABLE coupon_redemptions (
+    id BIGSERIAL PRIMARY KEY,
+    redemption_id UUID NOT NULL,
+    coupon_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    order_id UUID NOT NULL,
+    member_id UUID NULL,
+    discount NUMERIC NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX coupon_redemptions_redemption_id_key ON coupon_redemptions
+(redemption_id);
+
+CREATE INDEX coupon_redemptions_coupon_id_idx ON coupon_redemptions
+(coupon_id);
+
+CREATE INDEX coupon_redemptions_order_id_idx ON coupon_redemptions
+(order_id);
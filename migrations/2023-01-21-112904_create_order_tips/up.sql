@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+-- 小费单独记录，归属服务的理发师，不计入订单金额
+CREATE TABLE order_tips (
+    id BIGSERIAL PRIMARY KEY,
+    tip_id UUID NOT NULL,
+    order_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    barber_id UUID NOT NULL,
+    tender VARCHAR NOT NULL,
+    amount NUMERIC NOT NULL, -- 退款记录中为负数
+    enabled BOOLEAN NOT NULL, -- 预约取消后为 false
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX order_tips_tip_id_key ON order_tips
+(tip_id);
+
+CREATE INDEX order_tips_order_id_idx ON order_tips
+(order_id);
+
+CREATE INDEX order_tips_merchant_id_idx ON order_tips
+(merchant_id);
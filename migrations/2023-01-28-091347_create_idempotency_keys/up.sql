@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+-- 幂等请求记录：status_code 为空表示请求处理中，处理完成后保存响应用于重放
+CREATE TABLE idempotency_keys (
+    id BIGSERIAL PRIMARY KEY,
+    merchant_id UUID NOT NULL,
+    idempotency_key VARCHAR NOT NULL,
+    request_method VARCHAR NOT NULL,
+    request_path VARCHAR NOT NULL,
+    request_body TEXT NOT NULL,
+    status_code INTEGER NULL,
+    response_content_type VARCHAR NULL,
+    response_body TEXT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    expire_time TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX idempotency_keys_merchant_id_key_key ON idempotency_keys
+(merchant_id, idempotency_key);
+
+CREATE INDEX idempotency_keys_expire_time_idx ON idempotency_keys
+(expire_time);
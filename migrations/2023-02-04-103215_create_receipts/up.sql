@@ -0,0 +1,24 @@
+-- Your SQL goes here
+
+-- 小票编号，按商户连续编号，订单或充值记录首次打印时分配，重复打印使用同一编号
+CREATE TABLE receipts (
+    id BIGSERIAL PRIMARY KEY,
+    receipt_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    receipt_no BIGINT NOT NULL,
+    receipt_type VARCHAR NOT NULL, -- order / recharge
+    reference_id UUID NOT NULL, -- 订单ID或充值记录ID
+    print_count INT NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX receipts_receipt_id_key ON receipts
+(receipt_id);
+
+CREATE UNIQUE INDEX receipts_merchant_id_receipt_no_key ON receipts
+(merchant_id, receipt_no);
+
+CREATE UNIQUE INDEX receipts_merchant_id_reference_key ON receipts
+(merchant_id, receipt_type, reference_id);
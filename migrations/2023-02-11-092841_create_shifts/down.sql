@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE shift_cash_movements;
+DROP TABLE shifts;
+ALTER TABLE recharge_records DROP COLUMN tender;
@@ -0,0 +1,55 @@
+-- Your SQL goes here
+
+-- 充值的收款方式，历史记录按现金处理
+ALTER TABLE recharge_records ADD COLUMN tender VARCHAR NOT NULL DEFAULT 'cash';
+
+-- 班次，开班时登记备用金，结班时清点现金并与应有现金比较
+CREATE TABLE shifts (
+    id BIGSERIAL PRIMARY KEY,
+    shift_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    status VARCHAR NOT NULL, -- open / closed
+    opening_float NUMERIC NOT NULL, -- 备用金
+    opened_by UUID NULL,
+    open_time TIMESTAMPTZ NOT NULL,
+    closed_by UUID NULL,
+    close_time TIMESTAMPTZ NULL,
+    expected_cash NUMERIC NULL, -- 结班时计算的应有现金
+    counted_cash NUMERIC NULL, -- 结班时清点的实际现金
+    cash_difference NUMERIC NULL, -- 实际现金 - 应有现金
+    remark TEXT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX shifts_shift_id_key ON shifts
+(shift_id);
+
+-- 每个商户同时只能有一个未结班次
+CREATE UNIQUE INDEX shifts_merchant_id_open_key ON shifts
+(merchant_id) WHERE status='open';
+
+CREATE INDEX shifts_merchant_id_open_time_idx ON shifts
+(merchant_id, open_time);
+
+-- 班次内的现金存取，如补充零钱、支付杂费
+CREATE TABLE shift_cash_movements (
+    id BIGSERIAL PRIMARY KEY,
+    movement_id UUID NOT NULL,
+    shift_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    movement_type VARCHAR NOT NULL, -- in / out
+    amount NUMERIC NOT NULL,
+    reason TEXT NOT NULL,
+    barber_id UUID NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX shift_cash_movements_movement_id_key ON shift_cash_movements
+(movement_id);
+
+CREATE INDEX shift_cash_movements_shift_id_idx ON shift_cash_movements
+(shift_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE payment_refunds;
+DROP TABLE payment_charges;
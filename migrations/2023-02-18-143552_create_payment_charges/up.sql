@@ -0,0 +1,51 @@
+-- Your SQL goes here
+
+-- 通过支付渠道(刷卡、扫码)发起的收款，渠道回调后更新状态
+CREATE TABLE payment_charges (
+    id BIGSERIAL PRIMARY KEY,
+    charge_id UUID NOT NULL, -- 作为渠道的商户订单号
+    merchant_id UUID NOT NULL,
+    provider VARCHAR NOT NULL,
+    provider_charge_id VARCHAR NULL, -- 渠道交易号
+    tender VARCHAR NOT NULL,
+    amount NUMERIC NOT NULL,
+    refunded_amount NUMERIC NOT NULL, -- 已退款及退款中的金额
+    status VARCHAR NOT NULL, -- pending / succeeded / failed
+    failure_reason TEXT NULL,
+    order_id UUID NULL, -- 使用该笔收款支付的订单
+    barber_id UUID NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX payment_charges_charge_id_key ON payment_charges
+(charge_id);
+
+CREATE INDEX payment_charges_merchant_id_idx ON payment_charges
+(merchant_id);
+
+CREATE INDEX payment_charges_order_id_idx ON payment_charges
+(order_id);
+
+CREATE TABLE payment_refunds (
+    id BIGSERIAL PRIMARY KEY,
+    refund_id UUID NOT NULL,
+    charge_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    provider_refund_id VARCHAR NULL,
+    amount NUMERIC NOT NULL,
+    reason TEXT NOT NULL,
+    status VARCHAR NOT NULL, -- pending / succeeded / failed
+    failure_reason TEXT NULL,
+    barber_id UUID NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX payment_refunds_refund_id_key ON payment_refunds
+(refund_id);
+
+CREATE INDEX payment_refunds_charge_id_idx ON payment_refunds
+(charge_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE barber_pay_settings;
+DROP TABLE commission_rules;
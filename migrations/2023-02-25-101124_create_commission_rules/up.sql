@@ -0,0 +1,40 @@
+-- Your SQL goes here
+
+-- 服务提成规则，barber_id/service_type_id 为空时适用于全部理发师/服务，按最具体的规则计算
+CREATE TABLE commission_rules (
+    id BIGSERIAL PRIMARY KEY,
+    rule_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    barber_id UUID NULL,
+    service_type_id UUID NULL,
+    rule_type VARCHAR NOT NULL, -- percentage / fixed
+    value NUMERIC NOT NULL, -- 百分比(0-100)或每项服务的固定金额
+    enabled BOOLEAN NOT NULL,
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX commission_rules_rule_id_key ON commission_rules
+(rule_id);
+
+CREATE UNIQUE INDEX commission_rules_scope_key ON commission_rules
+(merchant_id, COALESCE(barber_id,'00000000-0000-0000-0000-000000000000'), COALESCE(service_type_id,'00000000-0000-0000-0000-000000000000')) WHERE enabled;
+
+-- 理发师底薪及充值提成比例
+CREATE TABLE barber_pay_settings (
+    id BIGSERIAL PRIMARY KEY,
+    barber_id UUID NOT NULL,
+    merchant_id UUID NOT NULL,
+    base_wage NUMERIC NOT NULL, -- 每月底薪，工资报表按统计区间覆盖的天数折算
+    recharge_rate NUMERIC NOT NULL, -- 充值提成百分比(0-100)，按实付本金计算
+    create_time TIMESTAMPTZ NOT NULL,
+    update_time TIMESTAMPTZ NOT NULL,
+    data TEXT NULL
+);
+
+CREATE UNIQUE INDEX barber_pay_settings_barber_id_key ON barber_pay_settings
+(barber_id);
+
+CREATE INDEX barber_pay_settings_merchant_id_idx ON barber_pay_settings
+(merchant_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX orders_merchant_id_complete_time_idx;
+ALTER TABLE order_lines DROP COLUMN commission;
+ALTER TABLE orders DROP COLUMN complete_time;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- 订单完成时间，已完成订单的退款记录取退款时间，工资和营业额按此统计
+ALTER TABLE orders ADD COLUMN complete_time TIMESTAMPTZ NULL;
+
+-- 订单完成时按当时的提成规则计算的服务提成，退款记录为负数
+ALTER TABLE order_lines ADD COLUMN commission NUMERIC NULL;
+
+-- 历史订单以最后修改时间作为完成时间
+UPDATE orders SET complete_time = update_time WHERE status = 'Completed';
+UPDATE orders r SET complete_time = r.create_time
+FROM orders o
+WHERE r.reversal_of = o.order_id AND o.status = 'Completed';
+
+CREATE INDEX orders_merchant_id_complete_time_idx ON orders
+(merchant_id, complete_time);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE member_packages ALTER COLUMN barber_id SET NOT NULL;
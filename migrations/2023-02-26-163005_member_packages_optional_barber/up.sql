@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- 没有理发师档案的管理员也可以售卖套餐
+ALTER TABLE member_packages ALTER COLUMN barber_id DROP NOT NULL;
@@ -12,7 +12,9 @@ pub const MEMBER:&str="Member";
 pub const SERVICE_TYPE:&str="ServiceType";
 pub const BARBER:&str="Barber";
 pub const STATISTIC:&str="Statistic";
+//预约时手工修改服务价格
+pub const PRICE_OVERRIDE:&str="PriceOverride";
 
-pub const ADMINISTRATOR_PERMISSIONS_OF_MERCHANT_BARBER: &'static [&'static str] = &["Canlendar", "Member","ServiceType","Barber","Statistic"];
+pub const ADMINISTRATOR_PERMISSIONS_OF_MERCHANT_BARBER: &'static [&'static str] = &["Canlendar", "Member","ServiceType","Barber","Statistic","PriceOverride"];
 pub const DEFAULT_PERMISSIONS_OF_MERCHANT_BARBER: &'static [&'static str] = &["Canlendar", "Member"];
 pub const DEFAULT_PERMISSIONS_OF_MEMBER: &'static [&'static str] = &[]; //TODO 预约、个人消费/充值记录
\ No newline at end of file
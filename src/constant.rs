@@ -1,2 +1,178 @@
 
 pub static MERCHANT_ID:&str="MerchantId";
+
+//订单状态
+pub mod order_status{
+    pub const SCHEDULED:&str="Scheduled";
+    pub const CHECKED_IN:&str="CheckedIn";
+    pub const COMPLETED:&str="Completed";
+    pub const CANCELLED:&str="Cancelled";
+    pub const NO_SHOW:&str="NoShow";
+    pub const REVERSAL:&str="Reversal"; // 退款记录，金额为负数，关联原订单
+
+    // 预约状态流转：Scheduled -> CheckedIn -> Completed，未完成前可取消，未到店则标记为 NoShow
+    pub fn can_transition(from:&str,to:&str)->bool{
+        matches!((from,to),
+            (SCHEDULED,CHECKED_IN)
+            | (SCHEDULED,COMPLETED)
+            | (SCHEDULED,CANCELLED)
+            | (SCHEDULED,NO_SHOW)
+            | (CHECKED_IN,COMPLETED)
+            | (CHECKED_IN,CANCELLED)
+        )
+    }
+
+    // 已完成、已取消、未到店的订单及退款记录不能再修改
+    pub fn is_final(status:&str)->bool{
+        matches!(status,COMPLETED|CANCELLED|NO_SHOW|REVERSAL)
+    }
+
+    pub fn display_name(status:&str)->&'static str{
+        match status {
+            SCHEDULED=>"已预约",
+            CHECKED_IN=>"已到店",
+            COMPLETED=>"已完成",
+            CANCELLED=>"已取消",
+            NO_SHOW=>"未到店",
+            REVERSAL=>"退款",
+            _=>"-",
+        }
+    }
+}
+
+//支付方式，订单使用多种支付方式时 payment_type 为 mixed，各项支付记录在 order_payments
+pub mod payment_type{
+    pub const MEMBER:&str="member";
+    pub const CASH:&str="cash";
+    pub const CARD:&str="card";
+    pub const WECHAT:&str="wechat";
+    pub const ALIPAY:&str="alipay";
+    pub const PACKAGE:&str="package";
+    pub const POINTS:&str="points";
+    pub const MIXED:&str="mixed";
+
+    pub fn is_tender(tender:&str)->bool{
+        matches!(tender,MEMBER|CASH|CARD|WECHAT|ALIPAY|PACKAGE|POINTS)
+    }
+
+    pub fn display_name(payment_type:&str)->&'static str{
+        match payment_type {
+            MEMBER=>"会员充值",
+            CARD=>"银行卡",
+            WECHAT=>"微信",
+            ALIPAY=>"支付宝",
+            PACKAGE=>"套餐",
+            POINTS=>"积分抵扣",
+            MIXED=>"组合支付",
+            _=>"现金",
+        }
+    }
+}
+
+//会员储值流水类型
+pub mod ledger_type{
+    pub const OPENING:&str="opening";
+    pub const RECHARGE:&str="recharge";
+    pub const ORDER_PAYMENT:&str="order_payment";
+    pub const REFUND:&str="refund";
+    pub const ADJUSTMENT:&str="adjustment";
+    pub const RECHARGE_REVERSAL:&str="recharge_reversal";
+    pub const RECHARGE_BONUS:&str="recharge_bonus";
+    pub const BONUS_REVERSAL:&str="bonus_reversal";
+    pub const PACKAGE_PURCHASE:&str="package_purchase";
+    pub const POINTS_CONVERSION:&str="points_conversion";
+    pub const TIP:&str="tip";
+
+    pub fn display_name(entry_type:&str)->&'static str{
+        match entry_type {
+            OPENING=>"期初余额",
+            RECHARGE=>"充值",
+            ORDER_PAYMENT=>"消费",
+            REFUND=>"退款",
+            ADJUSTMENT=>"手工调整",
+            RECHARGE_REVERSAL=>"充值撤销",
+            RECHARGE_BONUS=>"充值赠送",
+            BONUS_REVERSAL=>"赠送撤销",
+            PACKAGE_PURCHASE=>"购买套餐",
+            POINTS_CONVERSION=>"积分兑换",
+            TIP=>"小费",
+            _=>"-",
+        }
+    }
+}
+
+//优惠券类型
+pub mod discount_type{
+    pub const PERCENTAGE:&str="percentage"; // 按百分比减免
+    pub const FIXED:&str="fixed"; // 减免固定金额
+}
+
+//小票类型
+pub mod receipt_type{
+    pub const ORDER:&str="order";
+    pub const RECHARGE:&str="recharge";
+}
+
+//班次状态
+pub mod shift_status{
+    pub const OPEN:&str="open";
+    pub const CLOSED:&str="closed";
+}
+
+//班次现金存取类型
+pub mod cash_movement_type{
+    pub const CASH_IN:&str="in";
+    pub const CASH_OUT:&str="out";
+}
+
+//支付渠道收款及退款状态
+pub mod charge_status{
+    pub const PENDING:&str="pending";
+    pub const SUCCEEDED:&str="succeeded";
+    pub const FAILED:&str="failed";
+}
+
+//提成规则类型
+pub mod commission_type{
+    pub const PERCENTAGE:&str="percentage"; // 按服务实收金额的百分比
+    pub const FIXED:&str="fixed"; // 每项服务固定金额
+}
+
+//会员积分流水类型
+pub mod points_type{
+    pub const EARN:&str="earn";
+    pub const REDEEM:&str="redeem";
+    pub const CONVERSION:&str="conversion";
+    pub const EXPIRE:&str="expire";
+    pub const REFUND:&str="refund";
+    pub const EARN_REVERSAL:&str="earn_reversal";
+
+    pub fn display_name(entry_type:&str)->&'static str{
+        match entry_type {
+            EARN=>"消费获得",
+            REDEEM=>"积分抵扣",
+            CONVERSION=>"兑换余额",
+            EXPIRE=>"积分过期",
+            REFUND=>"抵扣退回",
+            EARN_REVERSAL=>"退款扣回",
+            _=>"-",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::order_status::*;
+
+    #[test]
+    fn test_order_status_transition(){
+        assert!(can_transition(SCHEDULED, CHECKED_IN));
+        assert!(can_transition(CHECKED_IN, COMPLETED));
+        assert!(can_transition(SCHEDULED, NO_SHOW));
+        assert!(!can_transition(CHECKED_IN, NO_SHOW));
+        assert!(!can_transition(COMPLETED, CANCELLED));
+        assert!(!can_transition(CANCELLED, SCHEDULED));
+        assert!(!can_transition(COMPLETED, REVERSAL));
+        assert!(is_final(REVERSAL));
+    }
+}
@@ -1,7 +1,7 @@
-use axum::{http::StatusCode, Json, extract::{Query, Path, State}};
+use axum::{http::StatusCode, Json, extract::{Query, Path, State}, response::{IntoResponse, Response}};
 use axum_session_authentication_middleware::session::AuthSession;
-use bigdecimal::BigDecimal;
-use chrono::{Local, DateTime};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Local, DateTime, Duration};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use uuid::Uuid;
@@ -9,12 +9,14 @@ use crate::{
     schema::*,
     models::*, 
     authorization_policy, 
-    constant
+    constant::{self, order_status, ledger_type, payment_type, points_type},
+    my_date_format,
+    my_option_date_format
 };
-use diesel::prelude::*;
+use diesel::{prelude::*, select, dsl::exists, pg::Pg, sql_types::Bool};
 use crate::{models::User, axum_pg::AxumPg};
 
-use super::Search;
+use super::{Search, TransactionError, balance_ledger::{change_balance, current_barber_id}, business_hour::BusinessCalendar, commission::settle_order_commissions, coupon::{allocate_discount, find_coupon, redeem_coupon, release_coupon}, member_level::{MemberLevelResponse, load_member_level, promote_member}, payment::{reserve_order_refunds, submit_refund, use_charge}, points::{award_order_points, change_points, load_points_setting, points_for_amount, refund_order_points, reverse_order_points}, service_package::{consume_package, restore_package_uses}, shift::check_payments_unlocked, statistic::{load_order_lines, load_order_payments}, tip::{cancel_order_tips, load_order_tips, reverse_order_tips}};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -29,10 +31,45 @@ pub struct AppointmentRequest{
 
     pub member_id:Option<Uuid>,
 
-    pub payment_type:String, // member/cash
+    #[serde(default)]
+    pub payment_type:String, // member/cash/card/wechat/alipay/package，组合支付时使用 payments
 
-    pub amount:BigDecimal,
+    pub member_package_id:Option<Uuid>, // 使用套餐支付时必填
+
+    pub amount:Option<BigDecimal>, // 未填写明细时使用，为空则按服务类型定价
     pub remark:Option<String>,
+
+    pub price_override_reason:Option<String>, // 金额与标价不同时必填，且需有改价权限
+
+    pub coupon_code:Option<String>, // 优惠券券码
+
+    #[serde(default)]
+    pub lines:Vec<OrderLineRequest>, // 多项服务明细，为空时按 serviceTypeId/barberId/amount 生成一条明细
+
+    #[serde(default)]
+    pub payments:Vec<PaymentRequest>, // 组合支付，为空时按 paymentType 支付全部金额
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequest{
+    pub tender:String, // member/cash/card/wechat/alipay
+
+    pub amount:BigDecimal,
+
+    pub charge_id:Option<Uuid>, // 通过支付渠道收款时对应的收款记录
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderLineRequest{
+    pub service_type_id:Uuid,
+
+    pub barber_id:Uuid,
+
+    pub amount:Option<BigDecimal>, // 为空则按服务类型定价
+
+    pub duration:Option<i32>, // 分钟，默认为服务类型的预计时长
 }
 
 #[derive(Deserialize)]
@@ -60,8 +97,15 @@ pub struct Event{
 
     pub extended_props:Value,//需为json对象
 
+    //背景事件（如停业时段）没有订单，使用 start/end 表示时段
+    #[serde(rename="start", with = "my_option_date_format", skip_serializing_if = "Option::is_none")]
+    pub start_time:Option<DateTime<Local>>,
+
+    #[serde(rename="end", with = "my_option_date_format", skip_serializing_if = "Option::is_none")]
+    pub end_time:Option<DateTime<Local>>,
+
     #[serde(flatten)]
-    pub order:Order,
+    pub order:Option<Order>,
 }
 
 pub async fn get_appointments(
@@ -83,6 +127,7 @@ pub async fn get_appointments(
         .left_join(service_types::table.on(orders::service_type_id.eq(service_types::service_type_id)))
         .filter(orders::enabled.eq(true))
         .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::reversal_of.is_null()) // 退款记录不在日历中显示
         .filter(orders::end_time.ge(params.start_date).and(orders::start_time.lt(params.end_date)))
         .into_boxed();
 
@@ -90,13 +135,15 @@ pub async fn get_appointments(
         query=query.filter(orders::barber_id.eq(barber_id))
     }
 
-    let data= query.order(orders::create_time.desc())
+    let rows=query.order(orders::create_time.desc())
         .get_results::<(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>)>(&mut *conn)
-        .map(|v|v.into_iter().map(|t|Event{
+        .unwrap();
+    let mut lines=load_order_lines(&mut conn, &rows.iter().map(|t|t.0.order_id).collect::<Vec<_>>());
+    let mut data=rows.into_iter().map(|t|Event{
             all_day:false,
             editable:false,
             start_editable:false,
-            background_color:"rgb(56, 189, 248)".to_string(),
+            background_color:status_color(&t.0.status).to_string(),
             display:"auto".into(),
             title: "".into(),
             extended_props:json!({
@@ -108,10 +155,43 @@ pub async fn get_appointments(
                     },
                 "serviceName": if t.3.as_ref().unwrap().enabled {t.3.as_ref().unwrap().name.clone()} else {"".into() }, // 已删除
                 "barberName":if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone()} else {"".into() }, // 已删除
+                "status":t.0.status,
+                "lines":lines.remove(&t.0.order_id).unwrap_or_default(),
             }),
-            order:t.0,
-        }).collect())
+            start_time:None,
+            end_time:None,
+            order:Some(t.0),
+        }).collect::<Vec<_>>();
+
+    // 停业时段以背景事件显示
+    let start_date=params.start_date.naive_local().date();
+    let end_date=params.end_date.naive_local().date();
+    let calendar=BusinessCalendar::load(&mut conn, merchant_id, start_date, end_date)
         .unwrap();
+    let mut date=start_date;
+    while date<=end_date {
+        let title=calendar.closure(date)
+            .map(|c|c.name.clone().unwrap_or_else(||if c.kind=="holiday" {"节假日".into()} else {"停业".into()}))
+            .unwrap_or_default();
+        let kind=calendar.closure(date).map(|c|c.kind.as_str()).unwrap_or("closed");
+        data.extend(calendar.closed_periods(date).into_iter()
+            .filter(|(start_time,end_time)|*end_time>params.start_date && *start_time<params.end_date)
+            .map(|(start_time,end_time)|Event{
+                all_day:false,
+                editable:false,
+                start_editable:false,
+                background_color:"rgb(229, 231, 235)".to_string(),
+                display:"background".into(),
+                title:title.clone(),
+                extended_props:json!({
+                    "kind":kind,
+                }),
+                start_time:Some(start_time),
+                end_time:Some(end_time),
+                order:None,
+            }));
+        date+=Duration::days(1);
+    }
     
     Ok(Json(data))
 }
@@ -120,7 +200,7 @@ pub async fn add_appointment(
     State(pg):State<AxumPg>,
     auth: AuthSession<AxumPg, AxumPg,User>,
     Json(req): Json<AppointmentRequest>
-)->Result<Json<Event>,(StatusCode,String)>{
+)->Result<Json<Event>,AppointmentError>{
     //检查登录&权限
     auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
     
@@ -128,67 +208,262 @@ pub async fn add_appointment(
 
     let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
 
-    let new_appointment=NewOrder{
-        order_id: &Uuid::new_v4(),
-        start_time:req.start_time,
-        end_time:req.end_time,
-        merchant_id:&merchant_id,
-        consumer_type:if req.member_id.is_none() { "walk-in" } else { "member" },
-        member_id:req.member_id.as_ref(),
-        barber_id:&req.barber_id,
-        service_type_id:&req.service_type_id,
-        status:"Completed",
-        payment_type:&req.payment_type,
-        amount:&req.amount,
-        remark:req.remark.as_deref(),
-       
-        enabled:true,
-        create_time: Local::now(),
-        update_time: Local::now(),
-        data: None,
+    if req.end_time<=req.start_time {
+        return Err((StatusCode::BAD_REQUEST,"结束时间必须晚于开始时间".to_string()).into());
+    }
+
+    check_business_hours(&mut conn, merchant_id, req.start_time, req.end_time)?;
+
+    let is_package=req.payment_type==payment_type::PACKAGE;
+    let lines=if req.lines.is_empty() {
+        vec![OrderLineRequest{
+            service_type_id:req.service_type_id,
+            barber_id:req.barber_id,
+            amount:req.amount.clone(),
+            duration:Some((req.end_time-req.start_time).num_minutes() as i32),
+        }]
+    } else {
+        req.lines
     };
-    diesel::insert_into(orders::table)
-        .values(&new_appointment)
-        .execute(&mut *conn)
+    if lines.iter().any(|l|l.amount.as_ref().map(|a|*a<BigDecimal::zero()).unwrap_or(false)) {
+        return Err((StatusCode::BAD_REQUEST,"金额不能为负数".to_string()).into());
+    }
+    if lines.iter().any(|l|l.duration.map(|d|d<=0).unwrap_or(false)) {
+        return Err((StatusCode::BAD_REQUEST,"服务时长必须大于0".to_string()).into());
+    }
+    if is_package && (req.member_id.is_none() || req.member_package_id.is_none()) {
+        return Err((StatusCode::BAD_REQUEST,"使用套餐支付需选择会员及套餐".to_string()).into());
+    }
+
+    let service_type_ids=lines.iter().map(|l|l.service_type_id).collect::<Vec<_>>();
+    let services=service_types::table
+        .filter(service_types::enabled.eq(true))
+        .filter(service_types::merchant_id.eq(merchant_id))
+        .filter(service_types::service_type_id.eq_any(&service_type_ids))
+        .get_results::<ServiceType>(&mut *conn)
         .unwrap();
 
-    let event=orders::table
-        .left_join(merchant_members::table.on(merchant_members::member_id.nullable().eq(orders::member_id)))
-        .left_join(barbers::table.on(orders::barber_id.eq(barbers::barber_id)))
-        .left_join(service_types::table.on(orders::service_type_id.eq(service_types::service_type_id)))
-        .filter(orders::enabled.eq(true))
-        .filter(orders::merchant_id.eq(merchant_id))
-        .filter(orders::order_id.eq(new_appointment.order_id))
-        .get_result::<(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>)>(&mut *conn)
-        .map(|t|Event{
-            all_day:false,
-            editable:false,
-            start_editable:false,
-            background_color: "rgb(56, 189, 248)".to_string(),
-            display:"auto".into(),
-            title: "".into(),
-            extended_props:json!({
-                "id":t.0.id,
-                "memberId": if t.0.consumer_type =="member" {
-                        if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().member_id.to_string()} else {"".into() } // 已删除
-                    } else {
-                        "".into()
-                    },
-                "customer": if t.0.consumer_type =="member" {
-                        if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().real_name.clone()} else {"".into() } // 已删除
-                    } else {
-                        t.0.consumer_type.clone()
-                    },
-                "serviceName": if t.3.as_ref().unwrap().enabled {t.3.as_ref().unwrap().name.clone()} else {"".into() }, // 已删除
-                "barberName":if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone()} else {"".into() }, // 已删除
-                "startTime":t.0.start_time,
-                "endTime":t.0.end_time,
-                "remark":t.0.remark,
-                "amount":t.0.amount,
-                "totalMinutes":(t.0.end_time-t.0.start_time).num_minutes(),
-            }),
-            order:t.0,
-        })
+    // 按服务类型定价，会员按等级价目表或折扣计价，未设置等级的会员使用会员价；金额与标价不同视为改价，需有改价权限并填写原因
+    let member_level=match req.member_id {
+        Some(member_id)=>load_member_level(&mut conn, merchant_id, member_id).unwrap(),
+        None=>None,
+    };
+    let can_override_price=auth.require_permissions(vec![authorization_policy::PRICE_OVERRIDE]).is_ok();
+    let price_override_reason=req.price_override_reason.as_deref().map(str::trim).filter(|r|!r.is_empty());
+    let mut durations=Vec::new();
+    let mut prices=Vec::new(); // (标价, 实收金额, 是否改价)
+    for line in lines.iter() {
+        let service=services.iter().find(|s|s.service_type_id==line.service_type_id)
+            .ok_or((StatusCode::BAD_REQUEST,"服务类型不存在".to_string()))?;
+        durations.push(line.duration.unwrap_or(service.estimated_duration));
+
+        let list_price=list_price(service, req.member_id.is_some(), member_level.as_ref());
+        // 套餐按次扣减，不再收取金额
+        let line_amount=if is_package { BigDecimal::zero() } else { line.amount.clone().unwrap_or_else(||list_price.clone()) };
+        let overridden=!is_package && line_amount!=list_price;
+        if overridden && !can_override_price {
+            return Err((StatusCode::FORBIDDEN,format!("没有改价权限，{} 的价格应为 {}",service.name,list_price)).into());
+        }
+        if overridden && price_override_reason.is_none() {
+            return Err((StatusCode::BAD_REQUEST,"修改价格需填写原因".to_string()).into());
+        }
+        prices.push((list_price,line_amount,overridden));
+    }
+
+    // 使用优惠券时按明细分摊优惠金额，优惠后的金额记入明细
+    let coupon=match req.coupon_code.as_deref().map(str::trim) {
+        Some(code) if !code.is_empty()=>{
+            if is_package {
+                return Err((StatusCode::BAD_REQUEST,"套餐支付不能使用优惠券".to_string()).into());
+            }
+            Some(find_coupon(&mut conn, merchant_id, code, req.member_id, Local::now())?)
+        },
+        _=>None,
+    };
+    let mut coupon_discount=BigDecimal::zero();
+    if let Some(coupon)=coupon.as_ref() {
+        let eligible=lines.iter().zip(prices.iter())
+            .map(|(line,price)|(coupon.service_type_ids.is_empty() || coupon.service_type_ids.contains(&line.service_type_id),price.1.clone()))
+            .collect::<Vec<_>>();
+        if !eligible.iter().any(|e|e.0) {
+            return Err((StatusCode::BAD_REQUEST,"该优惠券不适用于所选服务".to_string()).into());
+        }
+        let discounts=allocate_discount(&coupon.coupon.discount_type, &coupon.coupon.discount_value, &eligible);
+        for (price,discount) in prices.iter_mut().zip(discounts.iter()) {
+            price.1-=discount;
+            coupon_discount+=discount;
+        }
+    }
+    let amount=prices.iter().map(|p|&p.1).fold(BigDecimal::zero(),|sum,a|sum+a);
+
+    let payments=if req.payments.is_empty() {
+        vec![PaymentRequest{
+            tender:req.payment_type.clone(),
+            amount:amount.clone(),
+            charge_id:None,
+        }]
+    } else if is_package {
+        return Err((StatusCode::BAD_REQUEST,"套餐支付不能与其他支付方式组合".to_string()).into());
+    } else {
+        req.payments
+    };
+    for (i,payment) in payments.iter().enumerate() {
+        if !payment_type::is_tender(&payment.tender) || (payment.tender==payment_type::PACKAGE && !is_package) {
+            return Err((StatusCode::BAD_REQUEST,format!("不支持的支付方式 {}",payment.tender)).into());
+        }
+        if payment.amount<BigDecimal::zero() {
+            return Err((StatusCode::BAD_REQUEST,"支付金额不能为负数".to_string()).into());
+        }
+        if payment.charge_id.is_some() && payment.tender!=payment_type::CARD && payment.tender!=payment_type::WECHAT && payment.tender!=payment_type::ALIPAY {
+            return Err((StatusCode::BAD_REQUEST,format!("支付方式 {} 不能使用渠道收款",payment.tender)).into());
+        }
+        if payments[..i].iter().any(|p|p.tender==payment.tender) {
+            return Err((StatusCode::BAD_REQUEST,"支付方式不能重复".to_string()).into());
+        }
+    }
+    if payments.iter().map(|p|&p.amount).fold(BigDecimal::zero(),|sum,a|sum+a)!=amount {
+        return Err((StatusCode::BAD_REQUEST,"各支付方式金额之和必须等于订单金额".to_string()).into());
+    }
+    let member_amount=payments.iter()
+        .find(|p|p.tender==payment_type::MEMBER)
+        .map(|p|p.amount.clone());
+    if member_amount.is_some() && req.member_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST,"非会员不能使用会员余额支付".to_string()).into());
+    }
+    // 积分抵扣部分按商户设置换算为积分
+    let redeem_points=match payments.iter().find(|p|p.tender==payment_type::POINTS) {
+        Some(payment)=>{
+            if req.member_id.is_none() {
+                return Err((StatusCode::BAD_REQUEST,"非会员不能使用积分抵扣".to_string()).into());
+            }
+            let setting=load_points_setting(&mut conn, merchant_id)
+                .unwrap()
+                .ok_or((StatusCode::BAD_REQUEST,"商户未开启积分".to_string()))?;
+            points_for_amount(&payment.amount, &setting.redeem_rate)
+                .ok_or((StatusCode::BAD_REQUEST,format!("积分抵扣金额必须是 {} 的整数倍",setting.redeem_rate)))?
+        },
+        None=>0,
+    };
+    let order_payment_type=if payments.len()==1 { payments[0].tender.as_str() } else { payment_type::MIXED };
+
+    // 按固定顺序锁定理发师，避免并发预约时死锁
+    let mut barber_ids=lines.iter().map(|l|l.barber_id).collect::<Vec<_>>();
+    barber_ids.push(req.barber_id);
+    barber_ids.sort();
+    barber_ids.dedup();
+
+    // 当前操作的理发师，记录在余额流水中
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let order_id=Uuid::new_v4();
+    conn.transaction::<_,AppointmentError,_>(|conn|{
+        for barber_id in barber_ids.iter() {
+            check_barber_available(conn, merchant_id, *barber_id, req.start_time, req.end_time, None)?;
+        }
+
+        if let Some(member_id)=req.member_id {
+            let member_existed=select(exists(
+                merchant_members::table
+                .filter(merchant_members::enabled.eq(true))
+                .filter(merchant_members::merchant_id.eq(merchant_id))
+                .filter(merchant_members::member_id.eq(member_id))
+                ))
+                .get_result::<bool>(conn)?;
+            if !member_existed {
+                return Err((StatusCode::BAD_REQUEST,"会员不存在".to_string()).into());
+            }
+
+            if is_package {
+                consume_package(conn, merchant_id, member_id, req.member_package_id.unwrap(), &service_type_ids, req.start_time)?;
+            }
+            // 会员余额支付部分，与订单在同一事务中扣款
+            if let Some(member_amount)=member_amount.as_ref() {
+                change_balance(conn, merchant_id, member_id, &-member_amount, ledger_type::ORDER_PAYMENT, Some(order_id), operator_id, None)?;
+            }
+            if redeem_points>0 {
+                change_points(conn, merchant_id, member_id, -redeem_points, points_type::REDEEM, Some(order_id), operator_id, None)?;
+            }
+        }
+
+        let new_appointment=NewOrder{
+            order_id: &order_id,
+            start_time:req.start_time,
+            end_time:req.end_time,
+            merchant_id:&merchant_id,
+            consumer_type:if req.member_id.is_none() { "walk-in" } else { "member" },
+            member_id:req.member_id.as_ref(),
+            barber_id:&req.barber_id,
+            service_type_id:&req.service_type_id,
+            status:order_status::SCHEDULED,
+            payment_type:order_payment_type,
+            amount:&amount,
+            remark:req.remark.as_deref(),
+        
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+            reversal_of: None,
+            reversal_reason: None,
+            member_package_id: if is_package { req.member_package_id.as_ref() } else { None },
+            complete_time: None,
+        };
+        diesel::insert_into(orders::table)
+            .values(&new_appointment)
+            .execute(conn)?;
+
+        if let Some(coupon)=coupon.as_ref() {
+            redeem_coupon(conn, merchant_id, &coupon.coupon, order_id, req.member_id, &coupon_discount)?;
+        }
+
+        for ((line,duration),(list_price,line_amount,overridden)) in lines.iter().zip(durations.iter()).zip(prices.iter()) {
+            let new_line=NewOrderLine{
+                order_line_id:&Uuid::new_v4(),
+                order_id:&order_id,
+                merchant_id:&merchant_id,
+                service_type_id:&line.service_type_id,
+                barber_id:&line.barber_id,
+                amount:line_amount,
+                duration:*duration,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+                list_price:Some(list_price),
+                price_override_reason:if *overridden { price_override_reason } else { None },
+                commission:None,
+            };
+            diesel::insert_into(order_lines::table)
+                .values(&new_line)
+                .execute(conn)?;
+        }
+
+        for payment in payments.iter() {
+            if let Some(charge_id)=payment.charge_id {
+                use_charge(conn, merchant_id, charge_id, &payment.tender, &payment.amount, order_id)?;
+            }
+            let new_payment=NewOrderPayment{
+                payment_id:&Uuid::new_v4(),
+                order_id:&order_id,
+                merchant_id:&merchant_id,
+                tender:&payment.tender,
+                amount:&payment.amount,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(order_payments::table)
+                .values(&new_payment)
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })?;
+
+    let event=load_event(&mut conn,merchant_id,order_id)
         .unwrap();
 
     Ok(Json(event))
@@ -206,19 +481,704 @@ pub async fn get_appointment(
     
     let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
 
-    let event=orders::table
+    let event=load_event(&mut conn,merchant_id,appointment_id)
+        .map_err(|_|(StatusCode::NOT_FOUND,"预约不存在".to_string()))?;
+        
+    Ok(Json(event))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateAppointmentRequest{
+    pub start_time:DateTime<Local>,
+
+    pub end_time:DateTime<Local>,
+
+    pub service_type_id:Uuid,
+
+    pub barber_id:Uuid,
+
+    pub remark:Option<String>,
+
+    pub status:Option<String>, // CheckedIn/Completed/NoShow，取消请使用取消接口
+}
+
+pub async fn update_appointment(
+    State(pg):State<AxumPg>,
+    Path(appointment_id):Path<Uuid>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<UpdateAppointmentRequest>
+)->Result<Json<Event>,AppointmentError>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+    
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let order=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::order_id.eq(appointment_id))
+        .get_result::<Order>(&mut *conn)
+        .map_err(|_|(StatusCode::NOT_FOUND,"预约不存在".to_string()))?;
+
+    if order_status::is_final(&order.status){
+        return Err((StatusCode::BAD_REQUEST,"该预约已结束，无法修改".to_string()).into());
+    }
+
+    let status=req.status.unwrap_or_else(||order.status.clone());
+    let is_completed=status==order_status::COMPLETED && order.status!=order_status::COMPLETED;
+    if status==order_status::CANCELLED {
+        return Err((StatusCode::BAD_REQUEST,"取消预约需填写原因".to_string()).into());
+    }
+    if status!=order.status && !order_status::can_transition(&order.status, &status){
+        return Err((StatusCode::BAD_REQUEST,format!("预约状态无法从 {} 变更为 {}",order.status,status)).into());
+    }
+
+    if req.end_time<=req.start_time {
+        return Err((StatusCode::BAD_REQUEST,"结束时间必须晚于开始时间".to_string()).into());
+    }
+    if order.member_package_id.is_some() && req.service_type_id!=order.service_type_id {
+        return Err((StatusCode::BAD_REQUEST,"套餐支付的预约不能更换服务项目".to_string()).into());
+    }
+
+    // 仅在改期或更换理发师时检查营业时间和理发师是否空闲
+    let is_rescheduled=req.start_time!=order.start_time || req.end_time!=order.end_time || req.barber_id!=order.barber_id;
+    if is_rescheduled {
+        check_business_hours(&mut conn, merchant_id, req.start_time, req.end_time)?;
+    }
+
+    let lines=order_lines::table
+        .filter(order_lines::enabled.eq(true))
+        .filter(order_lines::order_id.eq(appointment_id))
+        .get_results::<OrderLine>(&mut *conn)
+        .unwrap();
+    let is_single_line=lines.len()==1;
+
+    // 多项服务的订单按明细计价和计时，只能改期或更换理发师
+    if !is_single_line {
+        if req.service_type_id!=order.service_type_id {
+            return Err((StatusCode::BAD_REQUEST,"多项服务的预约不能更换服务项目，请取消后重新预约".to_string()).into());
+        }
+        if req.end_time-req.start_time!=order.end_time-order.start_time {
+            return Err((StatusCode::BAD_REQUEST,"多项服务的预约不能修改服务时长，请取消后重新预约".to_string()).into());
+        }
+    }
+
+    // 更换服务项目时按新服务类型和会员等级重新定价，支付金额随之调整；仅支持单一支付方式且未使用优惠券、渠道收款的订单
+    let repriced=if req.service_type_id!=order.service_type_id {
+        let service=service_types::table
+            .filter(service_types::enabled.eq(true))
+            .filter(service_types::merchant_id.eq(merchant_id))
+            .filter(service_types::service_type_id.eq(req.service_type_id))
+            .get_result::<ServiceType>(&mut *conn)
+            .optional()
+            .unwrap()
+            .ok_or((StatusCode::BAD_REQUEST,"服务类型不存在".to_string()))?;
+        let member_level=match order.member_id {
+            Some(member_id)=>load_member_level(&mut conn, merchant_id, member_id).unwrap(),
+            None=>None,
+        };
+        let mut payments=order_payments::table
+            .filter(order_payments::enabled.eq(true))
+            .filter(order_payments::order_id.eq(appointment_id))
+            .get_results::<OrderPayment>(&mut *conn)
+            .unwrap();
+        let coupon_used=select(exists(
+            coupon_redemptions::table
+            .filter(coupon_redemptions::enabled.eq(true))
+            .filter(coupon_redemptions::order_id.eq(appointment_id))
+            ))
+            .get_result::<bool>(&mut *conn)
+            .unwrap();
+        let charge_used=select(exists(
+            payment_charges::table
+            .filter(payment_charges::merchant_id.eq(merchant_id))
+            .filter(payment_charges::order_id.eq(appointment_id))
+            ))
+            .get_result::<bool>(&mut *conn)
+            .unwrap();
+        if payments.len()!=1 || payments[0].tender==payment_type::POINTS || coupon_used || charge_used {
+            return Err((StatusCode::BAD_REQUEST,"组合支付、积分抵扣、使用优惠券或渠道收款的预约不能更换服务项目，请取消后重新预约".to_string()).into());
+        }
+        Some((list_price(&service, order.member_id.is_some(), member_level.as_ref()),payments.remove(0)))
+    } else {
+        None
+    };
+
+    // 单项服务的订单，明细随订单一起修改；多项服务的订单，原理发师负责的明细转给新理发师，并检查各明细的理发师
+    let mut barber_ids=lines.iter()
+        .map(|l|if l.barber_id==order.barber_id { req.barber_id } else { l.barber_id })
+        .collect::<Vec<_>>();
+    barber_ids.push(req.barber_id);
+    barber_ids.sort();
+    barber_ids.dedup();
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    conn.transaction::<_,AppointmentError,_>(|conn|{
+        // 只有改价会修改已收取的款项，状态和时间的调整不受班次限制
+        if repriced.is_some() {
+            check_payments_unlocked(conn, merchant_id, appointment_id)?;
+        }
+
+        if is_rescheduled {
+            for barber_id in barber_ids.iter() {
+                check_barber_available(conn, merchant_id, *barber_id, req.start_time, req.end_time, Some(appointment_id))?;
+            }
+        }
+
+        diesel::update(
+            orders::table
+            .filter(orders::order_id.eq(appointment_id))
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::enabled.eq(true))
+        )
+        .set((
+            orders::start_time.eq(req.start_time),
+            orders::end_time.eq(req.end_time),
+            orders::service_type_id.eq(req.service_type_id),
+            orders::barber_id.eq(req.barber_id),
+            orders::remark.eq(req.remark),
+            orders::status.eq(status),
+            orders::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        if is_single_line {
+            diesel::update(
+                order_lines::table
+                .filter(order_lines::order_line_id.eq(lines[0].order_line_id))
+            )
+            .set((
+                order_lines::service_type_id.eq(req.service_type_id),
+                order_lines::barber_id.eq(req.barber_id),
+                order_lines::duration.eq((req.end_time-req.start_time).num_minutes() as i32),
+                order_lines::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+
+            // 按新价格更新订单、明细和支付金额，会员余额支付的补扣或退回差额
+            if let Some((price,payment))=repriced.as_ref() {
+                diesel::update(
+                    orders::table
+                    .filter(orders::order_id.eq(appointment_id))
+                )
+                .set(orders::amount.eq(price))
+                .execute(conn)?;
+                diesel::update(
+                    order_lines::table
+                    .filter(order_lines::order_line_id.eq(lines[0].order_line_id))
+                )
+                .set((
+                    order_lines::amount.eq(price),
+                    order_lines::list_price.eq(Some(price)),
+                    order_lines::price_override_reason.eq(None::<&str>)
+                ))
+                .execute(conn)?;
+                diesel::update(
+                    order_payments::table
+                    .filter(order_payments::payment_id.eq(payment.payment_id))
+                )
+                .set((
+                    order_payments::amount.eq(price),
+                    order_payments::update_time.eq(Local::now())
+                ))
+                .execute(conn)?;
+
+                let difference=price-&payment.amount;
+                if let (true,Some(member_id))=(payment.tender==payment_type::MEMBER && !difference.is_zero(),order.member_id) {
+                    let entry_type=if difference>BigDecimal::zero() { ledger_type::ORDER_PAYMENT } else { ledger_type::REFUND };
+                    change_balance(conn, merchant_id, member_id, &-&difference, entry_type, Some(appointment_id), operator_id, Some("更换服务项目"))?;
+                }
+            }
+        } else if req.barber_id!=order.barber_id {
+            diesel::update(
+                order_lines::table
+                .filter(order_lines::order_id.eq(appointment_id))
+                .filter(order_lines::enabled.eq(true))
+                .filter(order_lines::barber_id.eq(order.barber_id))
+            )
+            .set((
+                order_lines::barber_id.eq(req.barber_id),
+                order_lines::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+        }
+
+        // 完成订单时记录完成时间，并按当前的提成规则计算各明细的提成
+        if is_completed {
+            diesel::update(
+                orders::table
+                .filter(orders::order_id.eq(appointment_id))
+            )
+            .set(orders::complete_time.eq(Local::now()))
+            .execute(conn)?;
+            settle_order_commissions(conn, merchant_id, appointment_id)?;
+        }
+
+        // 完成订单后发放积分，并按累计消费检查会员升级
+        if let (true,Some(member_id))=(is_completed,order.member_id) {
+            award_order_points(conn, merchant_id, member_id, appointment_id, operator_id)?;
+            promote_member(conn, merchant_id, member_id)?;
+        }
+
+        Ok(())
+    })?;
+
+    let event=load_event(&mut conn,merchant_id,appointment_id)
+        .unwrap();
+
+    Ok(Json(event))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelAppointmentRequest{
+    pub reason:String,
+}
+
+pub async fn cancel_appointment(
+    State(pg):State<AxumPg>,
+    Path(appointment_id):Path<Uuid>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<CancelAppointmentRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+    
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.reason.trim().is_empty(){
+        return Err((StatusCode::BAD_REQUEST,"取消原因不能为空".to_string()));
+    }
+
+    let order=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::order_id.eq(appointment_id))
+        .get_result::<Order>(&mut *conn)
+        .map_err(|_|(StatusCode::NOT_FOUND,"预约不存在".to_string()))?;
+
+    if !order_status::can_transition(&order.status, order_status::CANCELLED){
+        return Err((StatusCode::BAD_REQUEST,format!("预约状态无法从 {} 变更为 {}",order.status,order_status::CANCELLED)));
+    }
+
+    // 当前操作的理发师，记录在余额流水中
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let refunds=conn.transaction::<_,TransactionError,_>(|conn|{
+        // 以原状态为条件更新，避免重复取消时重复退款
+        let count=diesel::update(
+            orders::table
+            .filter(orders::order_id.eq(appointment_id))
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::enabled.eq(true))
+            .filter(orders::status.eq(&order.status))
+        )
+        .set((
+            orders::status.eq(order_status::CANCELLED),
+            orders::cancel_reason.eq(&req.reason),
+            orders::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+        if count==0 {
+            return Err(TransactionError(StatusCode::CONFLICT,"预约状态已变更，请刷新后重试".to_string()));
+        }
+
+        // 已收取的款项在原订单下按负数冲销，退款记录在取消时所在的班次，会员余额支付的部分退回余额
+        let payments=order_payments::table
+            .filter(order_payments::enabled.eq(true))
+            .filter(order_payments::order_id.eq(order.order_id))
+            .order(order_payments::id.asc())
+            .get_results::<OrderPayment>(conn)?;
+        for payment in payments.iter() {
+            let new_payment=NewOrderPayment{
+                payment_id:&Uuid::new_v4(),
+                order_id:&order.order_id,
+                merchant_id:&merchant_id,
+                tender:&payment.tender,
+                amount:&-&payment.amount,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(order_payments::table)
+                .values(&new_payment)
+                .execute(conn)?;
+
+            if let (true,Some(member_id))=(payment.tender==payment_type::MEMBER,order.member_id) {
+                change_balance(conn, merchant_id, member_id, &payment.amount, ledger_type::REFUND, Some(order.order_id), operator_id, Some(&req.reason))?;
+            }
+        }
+        if let Some(member_id)=order.member_id {
+            refund_order_points(conn, merchant_id, member_id, order.order_id, order.order_id, operator_id, &req.reason)?;
+        }
+        release_coupon(conn, order.order_id)?;
+        cancel_order_tips(conn, merchant_id, &order, operator_id, &req.reason)?;
+
+        // 套餐支付的预约取消后退回次数
+        if let Some(member_package_id)=order.member_package_id {
+            let uses=order_lines::table
+                .filter(order_lines::enabled.eq(true))
+                .filter(order_lines::order_id.eq(order.order_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            restore_package_uses(conn, merchant_id, member_package_id, uses as i32)?;
+        }
+
+        let refunds=reserve_order_refunds(conn, merchant_id, order.order_id, operator_id, &req.reason)?;
+        Ok(refunds)
+    })?;
+
+    // 渠道收款在事务提交后原路退回
+    for refund in refunds.iter() {
+        submit_refund(&mut conn, refund).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ReverseOrderRequest{
+    pub reason:String,
+}
+
+// 订单退款：保留原订单，新增一条金额为负数的退款记录并关联原订单，会员余额支付的退回余额
+pub async fn reverse_order(
+    State(pg):State<AxumPg>,
+    Path(order_id):Path<Uuid>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<ReverseOrderRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+    
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.reason.trim().is_empty(){
+        return Err((StatusCode::BAD_REQUEST,"退款原因不能为空".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let reversal_id=Uuid::new_v4();
+    let refunds=conn.transaction::<_,TransactionError,_>(|conn|{
+        // 锁定原订单，避免重复退款
+        let order=orders::table
+            .filter(orders::enabled.eq(true))
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::order_id.eq(order_id))
+            .for_update()
+            .get_result::<Order>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"订单不存在".to_string()))?;
+
+        if order.reversal_of.is_some() {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"退款记录不能再退款".to_string()));
+        }
+        if order.status==order_status::CANCELLED {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"已取消的预约无需退款".to_string()));
+        }
+        if !order_status::is_final(&order.status) {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"未完成的预约请直接取消".to_string()));
+        }
+
+        let reversed=select(exists(
+            orders::table
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::reversal_of.eq(order_id))
+            ))
+            .get_result::<bool>(conn)?;
+        if reversed {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"该订单已退款".to_string()));
+        }
+
+        let new_reversal=NewOrder{
+            order_id: &reversal_id,
+            start_time:order.start_time,
+            end_time:order.end_time,
+            merchant_id:&merchant_id,
+            consumer_type:&order.consumer_type,
+            member_id:order.member_id.as_ref(),
+            barber_id:&order.barber_id,
+            service_type_id:&order.service_type_id,
+            status:order_status::REVERSAL,
+            payment_type:&order.payment_type,
+            amount:&-&order.amount,
+            remark:None,
+        
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+            reversal_of: Some(&order_id),
+            reversal_reason: Some(&req.reason),
+            member_package_id: order.member_package_id.as_ref(),
+            // 已完成订单的退款在退款当期冲减营业额和提成
+            complete_time: if order.status==order_status::COMPLETED { Some(Local::now()) } else { None },
+        };
+        diesel::insert_into(orders::table)
+            .values(&new_reversal)
+            .execute(conn)?;
+
+        // 明细同样按负数冲销，便于按服务和理发师统计
+        let lines=order_lines::table
+            .filter(order_lines::enabled.eq(true))
+            .filter(order_lines::order_id.eq(order_id))
+            .order(order_lines::id.asc())
+            .get_results::<OrderLine>(conn)?;
+        for line in lines.iter() {
+            let commission=line.commission.as_ref().map(|c|-c);
+            let new_line=NewOrderLine{
+                order_line_id:&Uuid::new_v4(),
+                order_id:&reversal_id,
+                merchant_id:&merchant_id,
+                service_type_id:&line.service_type_id,
+                barber_id:&line.barber_id,
+                amount:&-&line.amount,
+                duration:line.duration,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+                list_price:line.list_price.as_ref(),
+                price_override_reason:line.price_override_reason.as_deref(),
+                commission:commission.as_ref(),
+            };
+            diesel::insert_into(order_lines::table)
+                .values(&new_line)
+                .execute(conn)?;
+        }
+
+        // 各项支付按负数冲销，会员余额支付的部分退回余额
+        let payments=order_payments::table
+            .filter(order_payments::enabled.eq(true))
+            .filter(order_payments::order_id.eq(order_id))
+            .order(order_payments::id.asc())
+            .get_results::<OrderPayment>(conn)?;
+        for payment in payments.iter() {
+            let new_payment=NewOrderPayment{
+                payment_id:&Uuid::new_v4(),
+                order_id:&reversal_id,
+                merchant_id:&merchant_id,
+                tender:&payment.tender,
+                amount:&-&payment.amount,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(order_payments::table)
+                .values(&new_payment)
+                .execute(conn)?;
+
+            if let (true,Some(member_id))=(payment.tender==payment_type::MEMBER,order.member_id) {
+                change_balance(conn, merchant_id, member_id, &payment.amount, ledger_type::REFUND, Some(reversal_id), operator_id, Some(&req.reason))?;
+            }
+        }
+        if let Some(member_package_id)=order.member_package_id {
+            restore_package_uses(conn, merchant_id, member_package_id, lines.len() as i32)?;
+        }
+        reverse_order_tips(conn, merchant_id, &order, reversal_id, operator_id, &req.reason)?;
+        // 退款后优惠券可再次使用
+        release_coupon(conn, order_id)?;
+
+        // 退回抵扣的积分，扣回该订单获得的积分
+        if let Some(member_id)=order.member_id {
+            refund_order_points(conn, merchant_id, member_id, order_id, reversal_id, operator_id, &req.reason)?;
+            reverse_order_points(conn, merchant_id, member_id, order_id, reversal_id, operator_id, &req.reason)?;
+        }
+
+        let refunds=reserve_order_refunds(conn, merchant_id, order_id, operator_id, &req.reason)?;
+        Ok(refunds)
+    })?;
+
+    // 渠道收款在事务提交后原路退回
+    for refund in refunds.iter() {
+        submit_refund(&mut conn, refund).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictAppointment{
+    #[serde(rename="id")]
+    pub order_id:Uuid,
+
+    #[serde(rename="start", with = "my_date_format")]
+    pub start_time:DateTime<Local>,
+
+    #[serde(rename="end", with = "my_date_format")]
+    pub end_time:DateTime<Local>,
+
+    pub status:String,
+}
+
+// 预约接口的错误，理发师时间冲突时以 JSON 返回冲突的预约
+pub enum AppointmentError{
+    Message(StatusCode,String),
+    Conflict(Vec<ConflictAppointment>),
+}
+
+impl From<(StatusCode,String)> for AppointmentError{
+    fn from(e: (StatusCode,String)) -> Self {
+        AppointmentError::Message(e.0,e.1)
+    }
+}
+
+impl From<TransactionError> for AppointmentError{
+    fn from(e: TransactionError) -> Self {
+        AppointmentError::Message(e.0,e.1)
+    }
+}
+
+impl From<diesel::result::Error> for AppointmentError{
+    fn from(e: diesel::result::Error) -> Self {
+        AppointmentError::Message(StatusCode::INTERNAL_SERVER_ERROR,e.to_string())
+    }
+}
+
+impl IntoResponse for AppointmentError{
+    fn into_response(self) -> Response {
+        match self {
+            AppointmentError::Message(status,message)=>(status,message).into_response(),
+            AppointmentError::Conflict(conflicts)=>(StatusCode::CONFLICT,Json(json!({
+                "message":"该理发师在此时间段已有预约",
+                "conflicts":conflicts,
+            }))).into_response(),
+        }
+    }
+}
+
+fn check_business_hours(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    start_time:DateTime<Local>,
+    end_time:DateTime<Local>,
+)->Result<(),(StatusCode,String)>{
+    let calendar=BusinessCalendar::load(conn, merchant_id, start_time.naive_local().date(), end_time.naive_local().date())
+        .unwrap();
+    // 停业当天凌晨仍可能在前一天营业到次日的时段内
+    if !calendar.is_open(start_time, end_time) {
+        if let Some(closure)=calendar.closure(start_time.naive_local().date()) {
+            return Err((StatusCode::BAD_REQUEST,format!("{} 门店停业{}",closure.closure_date,closure.name.as_ref().map(|n|format!("（{n}）")).unwrap_or_default())));
+        }
+        return Err((StatusCode::BAD_REQUEST,"预约时间不在营业时间内".to_string()));
+    }
+
+    Ok(())
+}
+
+// 占用理发师时间的订单：已取消和未到店的预约不占用，退款记录不计
+pub fn occupies_barber()->Box<dyn BoxableExpression<orders::table,Pg,SqlType=Bool>>{
+    Box::new(orders::status.ne_all([order_status::CANCELLED,order_status::NO_SHOW]).and(orders::reversal_of.is_null()))
+}
+
+// 锁定理发师所在行，保证并发预约时检查与写入之间不会插入其他预约
+fn check_barber_available(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    barber_id:Uuid,
+    start_time:DateTime<Local>,
+    end_time:DateTime<Local>,
+    exclude_order_id:Option<Uuid>,
+)->Result<(),AppointmentError>{
+    barbers::table
+        .filter(barbers::enabled.eq(true))
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .filter(barbers::barber_id.eq(barber_id))
+        .for_update()
+        .get_result::<Barber>(conn)
+        .optional()?
+        .ok_or_else(||AppointmentError::Message(StatusCode::BAD_REQUEST,"理发师不存在".to_string()))?;
+
+    let mut query=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::barber_id.eq(barber_id).or(orders::order_id.eq_any(
+            order_lines::table
+            .filter(order_lines::enabled.eq(true))
+            .filter(order_lines::barber_id.eq(barber_id))
+            .select(order_lines::order_id)
+        )))
+        .filter(occupies_barber())
+        .filter(orders::start_time.lt(end_time).and(orders::end_time.gt(start_time)))
+        .into_boxed();
+    if let Some(order_id)=exclude_order_id {
+        query=query.filter(orders::order_id.ne(order_id));
+    }
+
+    let conflicts=query
+        .order(orders::start_time.asc())
+        .get_results::<Order>(conn)?
+        .into_iter()
+        .map(|o|ConflictAppointment{
+            order_id:o.order_id,
+            start_time:o.start_time,
+            end_time:o.end_time,
+            status:o.status,
+        })
+        .collect::<Vec<_>>();
+
+    if !conflicts.is_empty() {
+        return Err(AppointmentError::Conflict(conflicts));
+    }
+
+    Ok(())
+}
+
+fn status_color(status:&str)->&'static str{
+    match status {
+        order_status::CHECKED_IN=>"rgb(251, 191, 36)",
+        order_status::COMPLETED=>"rgb(74, 222, 128)",
+        order_status::CANCELLED|order_status::NO_SHOW=>"rgb(156, 163, 175)",
+        _=>"rgb(56, 189, 248)",
+    }
+}
+
+// 服务类型的标价，有等级的会员按等级计价，其他会员使用会员价
+fn list_price(service:&ServiceType,is_member:bool,member_level:Option<&MemberLevelResponse>)->BigDecimal{
+    match member_level {
+        Some(level)=>level.price_of(service),
+        None if is_member=>service.member_prize.clone(),
+        None=>service.normal_prize.clone(),
+    }
+}
+
+// 加载单个预约及其服务明细、支付和小费，用于日历事件
+fn load_event(conn:&mut PgConnection,merchant_id:Uuid,order_id:Uuid)->QueryResult<Event>{
+    let mut lines=load_order_lines(conn, &[order_id]);
+    let mut payments=load_order_payments(conn, &[order_id]);
+    let mut tips=load_order_tips(conn, &[order_id]);
+
+    orders::table
         .left_join(merchant_members::table.on(merchant_members::member_id.nullable().eq(orders::member_id)))
         .left_join(barbers::table.on(orders::barber_id.eq(barbers::barber_id)))
         .left_join(service_types::table.on(orders::service_type_id.eq(service_types::service_type_id)))
         .filter(orders::enabled.eq(true))
         .filter(orders::merchant_id.eq(merchant_id))
-        .filter(orders::order_id.eq(appointment_id))
-        .get_result::<(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>)>(&mut *conn)
+        .filter(orders::order_id.eq(order_id))
+        .get_result::<(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>)>(conn)
         .map(|t|Event{
             all_day:false,
             editable:false,
             start_editable:false,
-            background_color:"rgb(56, 189, 248)".to_string(),
+            background_color:status_color(&t.0.status).to_string(),
             display:"auto".into(),
             title: "".into(),
             extended_props:json!({
@@ -240,10 +1200,16 @@ pub async fn get_appointment(
                 "remark":t.0.remark,
                 "amount":t.0.amount,
                 "totalMinutes":(t.0.end_time-t.0.start_time).num_minutes(),
+                "status":t.0.status,
+                "cancelReason":t.0.cancel_reason,
+                "lines":lines.remove(&t.0.order_id).unwrap_or_default(),
+                "paymentType":t.0.payment_type,
+                "payments":payments.remove(&t.0.order_id).unwrap_or_default(),
+                "tips":tips.remove(&t.0.order_id).unwrap_or_default(),
+                "memberBalance":t.1.as_ref().filter(|m|m.enabled).map(|m|m.balance.clone()), // 会员剩余余额
             }),
-            order:t.0,
+            start_time:None,
+            end_time:None,
+            order:Some(t.0),
         })
-        .unwrap();
-        
-    Ok(Json(event))
 }
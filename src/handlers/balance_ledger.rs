@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, Json, extract::{Query, Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, ledger_type},
+    my_date_format
+};
+use diesel::prelude::*;
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{PaginatedListRequest, PaginatedListResponse, TransactionError};
+
+// 变更会员余额并记录流水，需在事务中调用；amount 为负数时余额不足则返回错误
+#[allow(clippy::too_many_arguments)]
+pub fn change_balance(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    member_id:Uuid,
+    amount:&BigDecimal,
+    entry_type:&str,
+    reference_id:Option<Uuid>,
+    barber_id:Option<Uuid>,
+    remark:Option<&str>,
+)->Result<BigDecimal,TransactionError>{
+    let mut query=diesel::update(merchant_members::table)
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::enabled.eq(true))
+        .into_boxed();
+    if *amount<BigDecimal::zero() {
+        query=query.filter(merchant_members::balance.ge(-amount));
+    }
+
+    let balance=query
+        .set((
+            merchant_members::balance.eq(merchant_members::balance + amount),
+            merchant_members::update_time.eq(Local::now())
+        ))
+        .returning(merchant_members::balance)
+        .get_result::<BigDecimal>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,if *amount<BigDecimal::zero() {"会员余额不足".to_string()} else {"会员不存在".to_string()}))?;
+
+    let new_ledger=NewMemberBalanceLedger{
+        ledger_id:&Uuid::new_v4(),
+        merchant_id:&merchant_id,
+        member_id:&member_id,
+        entry_type,
+        amount,
+        balance:&balance,
+        reference_id:reference_id.as_ref(),
+        barber_id:barber_id.as_ref(),
+        remark,
+        create_time: Local::now(),
+        data: None,
+    };
+    diesel::insert_into(member_balance_ledgers::table)
+        .values(&new_ledger)
+        .execute(conn)?;
+
+    Ok(balance)
+}
+
+// 当前登录用户对应的理发师，作为流水的操作者
+pub fn current_barber_id(conn:&mut PgConnection,merchant_id:Uuid,user_id:Uuid)->QueryResult<Uuid>{
+    barbers::table
+        .filter(barbers::enabled.eq(true))
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .filter(barbers::user_id.eq(user_id))
+        .select(barbers::barber_id)
+        .get_result::<Uuid>(conn)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceLedgerResponse{
+    #[serde(rename="id")]
+    pub ledger_id:Uuid,
+
+    pub entry_type:String,
+
+    pub entry_type_name:String,
+
+    pub amount:BigDecimal,
+
+    pub balance:BigDecimal,
+
+    pub reference_id:Option<Uuid>,
+
+    pub barber_name:String,
+
+    pub remark:Option<String>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time:chrono::DateTime<Local>,
+}
+
+pub async fn get_balance_ledgers_by_member_id(
+    State(pg):State<AxumPg>,
+    Query(params):Query<PaginatedListRequest>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<PaginatedListResponse<BalanceLedgerResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let fn_get_query=||{
+        member_balance_ledgers::table
+            .left_join(barbers::table.on(member_balance_ledgers::barber_id.eq(barbers::barber_id.nullable())))
+            .filter(member_balance_ledgers::merchant_id.eq(merchant_id))
+            .filter(member_balance_ledgers::member_id.eq(member_id))
+            .into_boxed()
+    };
+
+    let count=fn_get_query()
+        .count()
+        .get_result(&mut *conn)
+        .unwrap();
+    let data=fn_get_query()
+        .order(member_balance_ledgers::id.desc())
+        .limit(params.page_size)
+        .offset(params.page_index*params.page_size)
+        .get_results::<(MemberBalanceLedger,Option<Barber>)>(&mut *conn)
+        .map(|v|v.into_iter().map(|t|BalanceLedgerResponse{
+            ledger_id:t.0.ledger_id,
+            entry_type_name:ledger_type::display_name(&t.0.entry_type).into(),
+            entry_type:t.0.entry_type,
+            amount:t.0.amount,
+            balance:t.0.balance,
+            reference_id:t.0.reference_id,
+            barber_name:t.1.map(|b|if b.enabled {b.real_name} else {"-".into()}).unwrap_or_default(),
+            remark:t.0.remark,
+            create_time:t.0.create_time,
+        }).collect())
+        .unwrap();
+
+    Ok(Json(PaginatedListResponse{
+        page_index:params.page_index,
+        page_size:params.page_size,
+        total_count:count,
+        data,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceAdjustmentRequest{
+    pub amount:BigDecimal, // 正数为增加，负数为减少
+
+    pub remark:String,
+}
+
+pub async fn adjust_balance(
+    State(pg):State<AxumPg>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<BalanceAdjustmentRequest>
+)->Result<Json<MerchantMember>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.amount.is_zero() {
+        return Err((StatusCode::BAD_REQUEST,"调整金额不能为0".to_string()));
+    }
+    if req.remark.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST,"调整原因不能为空".to_string()));
+    }
+
+    let barber_id=current_barber_id(&mut conn, merchant_id, auth.identity.unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        change_balance(conn, merchant_id, member_id, &req.amount, ledger_type::ADJUSTMENT, None, barber_id, Some(&req.remark))?;
+
+        Ok(())
+    })?;
+
+    let member=merchant_members::table
+        .filter(merchant_members::enabled.eq(true))
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .get_result::<MerchantMember>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(member))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceDrift{
+    pub member_id:Uuid,
+
+    pub member_name:String,
+
+    pub member_cellphone:String,
+
+    //会员当前余额
+    pub balance:BigDecimal,
+
+    //流水合计得出的余额
+    pub ledger_balance:BigDecimal,
+
+    //最后一条流水记录的余额
+    pub last_running_balance:Option<BigDecimal>,
+
+    //当前余额 - 流水合计
+    pub drift:BigDecimal,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciliationResponse{
+    pub checked_count:usize,
+
+    pub drift_count:usize,
+
+    pub drifts:Vec<BalanceDrift>,
+}
+
+// 根据流水重新计算会员余额，列出与当前余额不一致的会员
+pub async fn reconcile_balances(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<ReconciliationResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let members=merchant_members::table
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::enabled.eq(true))
+        .get_results::<MerchantMember>(&mut *conn)
+        .unwrap();
+
+    let ledger_sums=member_balance_ledgers::table
+        .filter(member_balance_ledgers::merchant_id.eq(merchant_id))
+        .group_by(member_balance_ledgers::member_id)
+        .select((member_balance_ledgers::member_id,diesel::dsl::sum(member_balance_ledgers::amount)))
+        .get_results::<(Uuid,Option<BigDecimal>)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_,_>>();
+
+    let last_balances=member_balance_ledgers::table
+        .filter(member_balance_ledgers::merchant_id.eq(merchant_id))
+        .select((member_balance_ledgers::member_id,member_balance_ledgers::balance))
+        .distinct_on(member_balance_ledgers::member_id)
+        .order_by(member_balance_ledgers::member_id)
+        .then_order_by(member_balance_ledgers::id.desc())
+        .get_results::<(Uuid,BigDecimal)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_,_>>();
+
+    let checked_count=members.len();
+    let drifts=members.into_iter()
+        .filter_map(|m|{
+            let ledger_balance=ledger_sums.get(&m.member_id).cloned().flatten().unwrap_or_else(BigDecimal::zero);
+            let last_running_balance=last_balances.get(&m.member_id).cloned();
+            let is_consistent=ledger_balance==m.balance
+                && last_running_balance.as_ref().map(|b|*b==m.balance).unwrap_or(m.balance.is_zero());
+            if is_consistent {
+                return None;
+            }
+
+            Some(BalanceDrift{
+                member_id:m.member_id,
+                member_name:m.real_name,
+                member_cellphone:m.cellphone,
+                drift:&m.balance-&ledger_balance,
+                balance:m.balance,
+                ledger_balance,
+                last_running_balance,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(ReconciliationResponse{
+        checked_count,
+        drift_count:drifts.len(),
+        drifts,
+    }))
+}
@@ -0,0 +1,441 @@
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use chrono::{Local, DateTime, NaiveDate, NaiveTime, Duration, Datelike};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, schedule::local_datetime};
+
+pub async fn get_business_hours(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<MerchantBusinessHour>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let data=merchant_business_hours::table
+        .filter(merchant_business_hours::enabled.eq(true))
+        .filter(merchant_business_hours::merchant_id.eq(merchant_id))
+        .order((merchant_business_hours::weekday.asc(),merchant_business_hours::open_time.asc()))
+        .get_results::<MerchantBusinessHour>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(data))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BusinessHourRequest{
+    pub weekday:i32,
+
+    pub open_time:NaiveTime,
+
+    pub close_time:NaiveTime, // 不晚于开门时间时表示营业到次日，00:00 即营业到午夜
+}
+
+// 整体替换商户的每周营业时间，传空数组表示不限制营业时间
+pub async fn update_business_hours(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<Vec<BusinessHourRequest>>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    for item in req.iter() {
+        if !(1..=7).contains(&item.weekday) {
+            return Err((StatusCode::BAD_REQUEST,"星期必须在1到7之间".to_string()));
+        }
+    }
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        diesel::update(
+            merchant_business_hours::table
+            .filter(merchant_business_hours::merchant_id.eq(merchant_id))
+            .filter(merchant_business_hours::enabled.eq(true))
+        )
+        .set((
+            merchant_business_hours::enabled.eq(false),
+            merchant_business_hours::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        for item in req.iter() {
+            let new_business_hour=NewMerchantBusinessHour{
+                business_hour_id:&Uuid::new_v4(),
+                merchant_id:&merchant_id,
+                weekday:item.weekday,
+                open_time:item.open_time,
+                close_time:item.close_time,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(merchant_business_hours::table)
+                .values(&new_business_hour)
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+pub async fn get_closures(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<MerchantClosure>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let data=merchant_closures::table
+        .filter(merchant_closures::enabled.eq(true))
+        .filter(merchant_closures::merchant_id.eq(merchant_id))
+        .filter(merchant_closures::closure_date.ge(Local::today().naive_local()))
+        .order(merchant_closures::closure_date.asc())
+        .get_results::<MerchantClosure>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(data))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClosureRequest{
+    pub closure_date:NaiveDate,
+
+    pub kind:String, // holiday / closure
+
+    pub name:Option<String>,
+}
+
+pub async fn add_closure(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<ClosureRequest>
+)->Result<Json<MerchantClosure>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.kind!="holiday" && req.kind!="closure" {
+        return Err((StatusCode::BAD_REQUEST,"停业类型只能是 holiday 或 closure".to_string()));
+    }
+
+    let existed=select(exists(
+        merchant_closures::table
+        .filter(merchant_closures::enabled.eq(true))
+        .filter(merchant_closures::merchant_id.eq(merchant_id))
+        .filter(merchant_closures::closure_date.eq(req.closure_date))
+        ))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"该日期已设置停业".to_string()));
+    }
+
+    let new_closure=NewMerchantClosure{
+        closure_id:&Uuid::new_v4(),
+        merchant_id:&merchant_id,
+        closure_date:req.closure_date,
+        kind:&req.kind,
+        name:req.name.as_deref(),
+        enabled:true,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    let closure=diesel::insert_into(merchant_closures::table)
+        .values(&new_closure)
+        .get_result::<MerchantClosure>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(closure))
+}
+
+pub async fn delete_closure(
+    State(pg):State<AxumPg>,
+    Path(closure_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        merchant_closures::table
+        .filter(merchant_closures::closure_id.eq(closure_id))
+        .filter(merchant_closures::merchant_id.eq(merchant_id))
+        .filter(merchant_closures::enabled.eq(true))
+    )
+    .set((
+        merchant_closures::enabled.eq(false),
+        merchant_closures::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"停业日期不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+// 商户在某段日期内的营业时间和停业日期
+pub struct BusinessCalendar{
+    business_hours:Vec<MerchantBusinessHour>,
+
+    closures:Vec<MerchantClosure>,
+}
+
+impl BusinessCalendar{
+    // 前一天营业到次日的时段会延续到 start_date，停业日期从前一天开始加载
+    pub fn load(conn:&mut PgConnection,merchant_id:Uuid,start_date:NaiveDate,end_date:NaiveDate)->QueryResult<Self>{
+        let business_hours=merchant_business_hours::table
+            .filter(merchant_business_hours::enabled.eq(true))
+            .filter(merchant_business_hours::merchant_id.eq(merchant_id))
+            .order(merchant_business_hours::open_time.asc())
+            .get_results::<MerchantBusinessHour>(conn)?;
+        let closures=merchant_closures::table
+            .filter(merchant_closures::enabled.eq(true))
+            .filter(merchant_closures::merchant_id.eq(merchant_id))
+            .filter(merchant_closures::closure_date.between(start_date-Duration::days(1),end_date))
+            .get_results::<MerchantClosure>(conn)?;
+
+        Ok(BusinessCalendar{
+            business_hours,
+            closures,
+        })
+    }
+
+    pub fn closure(&self,date:NaiveDate)->Option<&MerchantClosure>{
+        self.closures.iter().find(|c|c.closure_date==date)
+    }
+
+    // 当天的营业时段，None 表示商户未设置营业时间（不限制）
+    pub fn open_periods(&self,date:NaiveDate)->Option<Vec<(NaiveTime,NaiveTime)>>{
+        if self.closure(date).is_some() {
+            return Some(Vec::new());
+        }
+        if self.business_hours.is_empty() {
+            return None;
+        }
+
+        let weekday=date.weekday().number_from_monday() as i32;
+        Some(self.business_hours.iter()
+            .filter(|h|h.weekday==weekday)
+            .map(|h|(h.open_time,h.close_time))
+            .collect())
+    }
+
+    // 当天开始的营业时段，打烊时间不晚于开门时间的时段延续到次日
+    fn day_periods(&self,date:NaiveDate)->Option<Vec<(DateTime<Local>,DateTime<Local>)>>{
+        self.open_periods(date).map(|periods|periods.into_iter()
+            .map(|(open_time,close_time)|{
+                let close_date=if close_time<=open_time { date+Duration::days(1) } else { date };
+                (local_datetime(date,open_time),local_datetime(close_date,close_time))
+            })
+            .collect())
+    }
+
+    // 当天及前一天延续过来的营业时段，按开始时间排序
+    fn periods_covering(&self,date:NaiveDate)->Option<Vec<(DateTime<Local>,DateTime<Local>)>>{
+        let mut periods=self.day_periods(date)?;
+        periods.extend(self.day_periods(date-Duration::days(1)).unwrap_or_default());
+        periods.sort();
+        Some(periods)
+    }
+
+    pub fn is_open(&self,start_time:DateTime<Local>,end_time:DateTime<Local>)->bool{
+        match self.periods_covering(start_time.naive_local().date()) {
+            None=>true,
+            Some(periods)=>periods.iter().any(|(open_time,close_time)|*open_time<=start_time && end_time<=*close_time),
+        }
+    }
+
+    // 当天不营业的时段，用于日历中的背景显示
+    pub fn closed_periods(&self,date:NaiveDate)->Vec<(DateTime<Local>,DateTime<Local>)>{
+        let day_start=local_datetime(date,NaiveTime::from_hms(0, 0, 0));
+        let day_end=local_datetime(date+Duration::days(1),NaiveTime::from_hms(0, 0, 0));
+
+        let periods=match self.periods_covering(date) {
+            None=>return Vec::new(),
+            Some(periods)=>periods,
+        };
+
+        let mut closed=Vec::new();
+        let mut cursor=day_start;
+        for (open_time,close_time) in periods.into_iter() {
+            if open_time>cursor {
+                closed.push((cursor,open_time));
+            }
+            cursor=cursor.max(close_time);
+        }
+        if cursor<day_end {
+            closed.push((cursor,day_end));
+        }
+
+        closed
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use chrono::{Local, NaiveDate, NaiveTime, Duration};
+    use uuid::Uuid;
+    use crate::models::{MerchantBusinessHour, MerchantClosure};
+    use super::{BusinessCalendar, local_datetime};
+
+    fn business_hour(weekday:i32,open_time:NaiveTime,close_time:NaiveTime)->MerchantBusinessHour{
+        MerchantBusinessHour{
+            id:0,
+            business_hour_id:Uuid::new_v4(),
+            merchant_id:Uuid::nil(),
+            weekday,
+            open_time,
+            close_time,
+            enabled:true,
+            create_time:Local::now(),
+            update_time:Local::now(),
+            data:None,
+        }
+    }
+
+    fn closure(closure_date:NaiveDate)->MerchantClosure{
+        MerchantClosure{
+            id:0,
+            closure_id:Uuid::new_v4(),
+            merchant_id:Uuid::nil(),
+            closure_date,
+            kind:"holiday".into(),
+            name:None,
+            enabled:true,
+            create_time:Local::now(),
+            update_time:Local::now(),
+            data:None,
+        }
+    }
+
+    // 周一 9:00-12:00、13:00-18:00 营业
+    fn calendar(closures:Vec<MerchantClosure>)->BusinessCalendar{
+        BusinessCalendar{
+            business_hours:vec![
+                business_hour(1, NaiveTime::from_hms(9, 0, 0), NaiveTime::from_hms(12, 0, 0)),
+                business_hour(1, NaiveTime::from_hms(13, 0, 0), NaiveTime::from_hms(18, 0, 0)),
+            ],
+            closures,
+        }
+    }
+
+    fn at(date:NaiveDate,hour:u32,minute:u32)->chrono::DateTime<Local>{
+        local_datetime(date, NaiveTime::from_hms(hour, minute, 0))
+    }
+
+    #[test]
+    fn test_is_open(){
+        let monday=NaiveDate::from_ymd(2022, 11, 14);
+        let tuesday=monday+Duration::days(1);
+        let calendar=calendar(Vec::new());
+
+        assert!(calendar.is_open(at(monday, 9, 0), at(monday, 12, 0)));
+        assert!(calendar.is_open(at(monday, 13, 30), at(monday, 14, 0)));
+        // 跨越午休或超出打烊时间
+        assert!(!calendar.is_open(at(monday, 11, 30), at(monday, 13, 30)));
+        assert!(!calendar.is_open(at(monday, 17, 30), at(monday, 18, 30)));
+        assert!(!calendar.is_open(at(monday, 8, 30), at(monday, 9, 30)));
+        // 当天未设置营业时间
+        assert!(!calendar.is_open(at(tuesday, 10, 0), at(tuesday, 11, 0)));
+
+        // 未设置营业时间时不限制
+        let unrestricted=BusinessCalendar{ business_hours:Vec::new(), closures:Vec::new() };
+        assert!(unrestricted.is_open(at(tuesday, 23, 0), at(tuesday, 23, 30)));
+        assert!(unrestricted.closed_periods(tuesday).is_empty());
+    }
+
+    // 打烊时间不晚于开门时间的时段营业到次日，跨过零点的预约在营业时间内
+    #[test]
+    fn test_overnight(){
+        let monday=NaiveDate::from_ymd(2022, 11, 14);
+        let tuesday=monday+Duration::days(1);
+        let wednesday=tuesday+Duration::days(1);
+        let calendar=BusinessCalendar{
+            business_hours:vec![
+                business_hour(1, NaiveTime::from_hms(18, 0, 0), NaiveTime::from_hms(2, 0, 0)),
+                business_hour(2, NaiveTime::from_hms(20, 0, 0), NaiveTime::from_hms(0, 0, 0)),
+            ],
+            closures:Vec::new(),
+        };
+
+        assert!(calendar.is_open(at(monday, 22, 0), at(monday, 23, 0)));
+        assert!(calendar.is_open(at(monday, 23, 0), at(tuesday, 1, 0)));
+        assert!(calendar.is_open(at(tuesday, 0, 30), at(tuesday, 2, 0)));
+        assert!(!calendar.is_open(at(tuesday, 1, 30), at(tuesday, 2, 30)));
+        // 营业到 24:00
+        assert!(calendar.is_open(at(tuesday, 23, 0), at(wednesday, 0, 0)));
+        assert!(!calendar.is_open(at(tuesday, 23, 30), at(wednesday, 0, 30)));
+
+        assert_eq!(calendar.closed_periods(tuesday),vec![(at(tuesday, 2, 0),at(tuesday, 20, 0))]);
+        assert_eq!(calendar.closed_periods(wednesday),vec![(at(wednesday, 0, 0),at(wednesday+Duration::days(1), 0, 0))]);
+    }
+
+    // 停业日期优先于每周营业时间
+    #[test]
+    fn test_closure(){
+        let monday=NaiveDate::from_ymd(2022, 11, 14);
+        let next_monday=monday+Duration::days(7);
+        let calendar=calendar(vec![closure(monday)]);
+
+        assert!(calendar.closure(monday).is_some());
+        assert!(!calendar.is_open(at(monday, 9, 0), at(monday, 10, 0)));
+        assert_eq!(calendar.closed_periods(monday),vec![(at(monday, 0, 0),at(monday+Duration::days(1), 0, 0))]);
+        assert!(calendar.closure(next_monday).is_none());
+        assert!(calendar.is_open(at(next_monday, 9, 0), at(next_monday, 10, 0)));
+
+        // 未设置营业时间的商户，停业当天同样不营业
+        let unrestricted=BusinessCalendar{ business_hours:Vec::new(), closures:vec![closure(monday)] };
+        assert!(!unrestricted.is_open(at(monday, 9, 0), at(monday, 10, 0)));
+        assert!(unrestricted.is_open(at(next_monday, 9, 0), at(next_monday, 10, 0)));
+    }
+
+    #[test]
+    fn test_closed_periods(){
+        let monday=NaiveDate::from_ymd(2022, 11, 14);
+        let tuesday=monday+Duration::days(1);
+        let calendar=calendar(Vec::new());
+
+        assert_eq!(calendar.closed_periods(monday),vec![
+            (at(monday, 0, 0),at(monday, 9, 0)),
+            (at(monday, 12, 0),at(monday, 13, 0)),
+            (at(monday, 18, 0),at(tuesday, 0, 0)),
+        ]);
+        assert_eq!(calendar.closed_periods(tuesday),vec![(at(tuesday, 0, 0),at(tuesday+Duration::days(1), 0, 0))]);
+    }
+}
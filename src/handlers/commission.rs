@@ -0,0 +1,503 @@
+use std::{cmp::min, collections::HashMap};
+
+use axum::{http::StatusCode, Json, extract::{Path, Query, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, commission_type, order_status}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, statistic::TenderSummaryRequest};
+
+// 按 (理发师, 服务) 查找提成规则，依次匹配 理发师+服务、理发师、服务、全部
+pub struct CommissionCalculator{
+    rules:HashMap<(Option<Uuid>,Option<Uuid>),(String,BigDecimal)>,
+}
+
+impl CommissionCalculator{
+    pub fn new(rules:impl IntoIterator<Item=(Option<Uuid>,Option<Uuid>,String,BigDecimal)>)->Self{
+        CommissionCalculator{
+            rules:rules.into_iter().map(|r|((r.0,r.1),(r.2,r.3))).collect(),
+        }
+    }
+
+    fn find(&self,barber_id:Uuid,service_type_id:Uuid)->Option<&(String,BigDecimal)>{
+        [(Some(barber_id),Some(service_type_id)),(Some(barber_id),None),(None,Some(service_type_id)),(None,None)]
+            .iter()
+            .find_map(|key|self.rules.get(key))
+    }
+
+    // 百分比按明细实收金额计算；固定金额按项计算，退款记录扣回
+    pub fn service_commission(&self,barber_id:Uuid,service_type_id:Uuid,amount:&BigDecimal,is_reversal:bool)->BigDecimal{
+        match self.find(barber_id, service_type_id) {
+            Some((rule_type,value)) if rule_type==commission_type::PERCENTAGE=>(amount*value/BigDecimal::from(100)).round(2),
+            Some((_,value)) if is_reversal=>-value,
+            Some((_,value))=>value.clone(),
+            None=>BigDecimal::zero(),
+        }
+    }
+}
+
+fn load_commission_calculator(conn:&mut PgConnection,merchant_id:Uuid)->QueryResult<CommissionCalculator>{
+    let rules=commission_rules::table
+        .filter(commission_rules::enabled.eq(true))
+        .filter(commission_rules::merchant_id.eq(merchant_id))
+        .select((commission_rules::barber_id,commission_rules::service_type_id,commission_rules::rule_type,commission_rules::value))
+        .get_results::<(Option<Uuid>,Option<Uuid>,String,BigDecimal)>(conn)?;
+    Ok(CommissionCalculator::new(rules))
+}
+
+// 订单完成时按当时的提成规则计算并保存各明细的提成，之后修改规则不影响已完成的订单
+pub fn settle_order_commissions(conn:&mut PgConnection,merchant_id:Uuid,order_id:Uuid)->Result<(),TransactionError>{
+    let calculator=load_commission_calculator(conn, merchant_id)?;
+    let lines=order_lines::table
+        .filter(order_lines::enabled.eq(true))
+        .filter(order_lines::order_id.eq(order_id))
+        .get_results::<OrderLine>(conn)?;
+    for line in lines {
+        let commission=calculator.service_commission(line.barber_id, line.service_type_id, &line.amount, false);
+        diesel::update(
+            order_lines::table
+            .filter(order_lines::order_line_id.eq(line.order_line_id))
+        )
+        .set(order_lines::commission.eq(commission))
+        .execute(conn)?;
+    }
+    Ok(())
+}
+
+// 底薪按月计，按统计区间 [start,end) 覆盖的天数占当月天数的比例折算，跨月时逐月累加
+pub fn prorated_base_wage(base_wage:&BigDecimal,start:NaiveDate,end:NaiveDate)->BigDecimal{
+    let mut total=BigDecimal::zero();
+    let mut day=start;
+    while day<end {
+        let month_start=NaiveDate::from_ymd_opt(day.year(), day.month(), 1).unwrap();
+        let next_month=if day.month()==12 {
+            NaiveDate::from_ymd_opt(day.year()+1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(day.year(), day.month()+1, 1)
+        }.unwrap();
+        let until=min(next_month,end);
+        total+=base_wage*BigDecimal::from((until-day).num_days())/BigDecimal::from((next_month-month_start).num_days());
+        day=until;
+    }
+    total.round(2)
+}
+
+// 统计区间覆盖的自然日，不足一天的按一天计
+fn covered_days(start:DateTime<Local>,end:DateTime<Local>)->(NaiveDate,NaiveDate){
+    let last_day=(end-Duration::nanoseconds(1)).naive_local().date();
+    (start.naive_local().date(),last_day.succ_opt().unwrap())
+}
+
+pub fn recharge_commission(amount:&BigDecimal,recharge_rate:&BigDecimal)->BigDecimal{
+    (amount*recharge_rate/BigDecimal::from(100)).round(2)
+}
+
+fn check_rate(value:&BigDecimal)->Result<(),(StatusCode,String)>{
+    if *value<BigDecimal::zero() || *value>BigDecimal::from(100) {
+        return Err((StatusCode::BAD_REQUEST,"提成比例必须在0到100之间".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn get_commission_rules(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<CommissionRule>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let rules=commission_rules::table
+        .filter(commission_rules::enabled.eq(true))
+        .filter(commission_rules::merchant_id.eq(merchant_id))
+        .order(commission_rules::create_time.asc())
+        .get_results::<CommissionRule>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(rules))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommissionRuleRequest{
+    pub barber_id:Option<Uuid>, // 为空时适用于全部理发师
+
+    pub service_type_id:Option<Uuid>, // 为空时适用于全部服务
+
+    pub rule_type:String, // percentage / fixed
+
+    pub value:BigDecimal,
+}
+
+// 设置提成规则，相同理发师和服务的规则会被替换
+pub async fn set_commission_rule(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<CommissionRuleRequest>
+)->Result<Json<CommissionRule>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    match req.rule_type.as_str() {
+        commission_type::PERCENTAGE=>check_rate(&req.value)?,
+        commission_type::FIXED=>if req.value<BigDecimal::zero() {
+            return Err((StatusCode::BAD_REQUEST,"提成金额不能为负数".to_string()));
+        },
+        _=>return Err((StatusCode::BAD_REQUEST,format!("不支持的提成类型 {}",req.rule_type))),
+    }
+
+    if let Some(barber_id)=req.barber_id {
+        let barber_existed=select(exists(
+            barbers::table
+            .filter(barbers::enabled.eq(true))
+            .filter(barbers::merchant_id.eq(merchant_id))
+            .filter(barbers::barber_id.eq(barber_id))
+            ))
+            .get_result::<bool>(&mut *conn)
+            .unwrap();
+        if !barber_existed {
+            return Err((StatusCode::BAD_REQUEST,"理发师不存在".to_string()));
+        }
+    }
+    if let Some(service_type_id)=req.service_type_id {
+        let service_existed=select(exists(
+            service_types::table
+            .filter(service_types::enabled.eq(true))
+            .filter(service_types::merchant_id.eq(merchant_id))
+            .filter(service_types::service_type_id.eq(service_type_id))
+            ))
+            .get_result::<bool>(&mut *conn)
+            .unwrap();
+        if !service_existed {
+            return Err((StatusCode::BAD_REQUEST,"服务不存在".to_string()));
+        }
+    }
+
+    let rule=conn.transaction::<_,TransactionError,_>(|conn|{
+        let mut query=diesel::update(commission_rules::table)
+            .filter(commission_rules::enabled.eq(true))
+            .filter(commission_rules::merchant_id.eq(merchant_id))
+            .into_boxed();
+        query=match req.barber_id {
+            Some(barber_id)=>query.filter(commission_rules::barber_id.eq(barber_id)),
+            None=>query.filter(commission_rules::barber_id.is_null()),
+        };
+        query=match req.service_type_id {
+            Some(service_type_id)=>query.filter(commission_rules::service_type_id.eq(service_type_id)),
+            None=>query.filter(commission_rules::service_type_id.is_null()),
+        };
+        query.set((
+                commission_rules::enabled.eq(false),
+                commission_rules::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+
+        let new_rule=NewCommissionRule{
+            rule_id:&Uuid::new_v4(),
+            merchant_id:&merchant_id,
+            barber_id:req.barber_id.as_ref(),
+            service_type_id:req.service_type_id.as_ref(),
+            rule_type:&req.rule_type,
+            value:&req.value,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let rule=diesel::insert_into(commission_rules::table)
+            .values(&new_rule)
+            .get_result::<CommissionRule>(conn)?;
+
+        Ok(rule)
+    })?;
+
+    Ok(Json(rule))
+}
+
+pub async fn delete_commission_rule(
+    State(pg):State<AxumPg>,
+    Path(rule_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        commission_rules::table
+        .filter(commission_rules::enabled.eq(true))
+        .filter(commission_rules::merchant_id.eq(merchant_id))
+        .filter(commission_rules::rule_id.eq(rule_id))
+    )
+    .set((
+        commission_rules::enabled.eq(false),
+        commission_rules::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"提成规则不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+pub async fn get_barber_pay_setting(
+    State(pg):State<AxumPg>,
+    Path(barber_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Option<BarberPaySetting>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let setting=barber_pay_settings::table
+        .filter(barber_pay_settings::merchant_id.eq(merchant_id))
+        .filter(barber_pay_settings::barber_id.eq(barber_id))
+        .get_result::<BarberPaySetting>(&mut *conn)
+        .optional()
+        .unwrap();
+
+    Ok(Json(setting))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberPaySettingRequest{
+    pub base_wage:BigDecimal,
+
+    pub recharge_rate:BigDecimal, // 充值提成百分比(0-100)
+}
+
+pub async fn update_barber_pay_setting(
+    State(pg):State<AxumPg>,
+    Path(barber_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<BarberPaySettingRequest>
+)->Result<Json<BarberPaySetting>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.base_wage<BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"底薪不能为负数".to_string()));
+    }
+    check_rate(&req.recharge_rate)?;
+
+    let barber_existed=select(exists(
+        barbers::table
+        .filter(barbers::enabled.eq(true))
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .filter(barbers::barber_id.eq(barber_id))
+        ))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if !barber_existed {
+        return Err((StatusCode::NOT_FOUND,"理发师不存在".to_string()));
+    }
+
+    let new_setting=NewBarberPaySetting{
+        barber_id:&barber_id,
+        merchant_id:&merchant_id,
+        base_wage:&req.base_wage,
+        recharge_rate:&req.recharge_rate,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    let setting=diesel::insert_into(barber_pay_settings::table)
+        .values(&new_setting)
+        .on_conflict(barber_pay_settings::barber_id)
+        .do_update()
+        .set((
+            barber_pay_settings::base_wage.eq(&req.base_wage),
+            barber_pay_settings::recharge_rate.eq(&req.recharge_rate),
+            barber_pay_settings::update_time.eq(Local::now())
+        ))
+        .get_result::<BarberPaySetting>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(setting))
+}
+
+#[derive(Serialize,Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PayrollEntry{
+    pub barber_id:Uuid,
+
+    pub barber_name:String,
+
+    pub base_wage:BigDecimal, // 按统计区间折算的底薪
+
+    pub service_count:i64, // 退款的服务不计入
+
+    pub service_amount:BigDecimal,
+
+    pub service_commission:BigDecimal,
+
+    pub recharge_amount:BigDecimal, // 经手充值的实付本金，撤销为负数
+
+    pub recharge_commission:BigDecimal,
+
+    pub tip_amount:BigDecimal,
+
+    pub total_payable:BigDecimal, // 底薪 + 服务提成 + 充值提成 + 小费
+}
+
+// 工资报表：服务提成取订单完成时保存的金额，按完成时间统计，订单退款时在退款当期扣回；底薪按统计区间折算
+pub async fn get_payroll(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<PayrollEntry>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    // 未保存提成的历史订单按当前规则计算
+    let calculator=load_commission_calculator(&mut conn, merchant_id).unwrap();
+    let settings=barber_pay_settings::table
+        .filter(barber_pay_settings::merchant_id.eq(merchant_id))
+        .get_results::<BarberPaySetting>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|s|(s.barber_id,s))
+        .collect::<HashMap<_,_>>();
+
+    // 只有已完成订单及其退款记录有完成时间，未完成订单的退款不扣回提成
+    let lines=order_lines::table
+        .inner_join(orders::table.on(order_lines::order_id.eq(orders::order_id)))
+        .filter(order_lines::enabled.eq(true))
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::status.eq_any([order_status::COMPLETED,order_status::REVERSAL]))
+        .filter(orders::complete_time.ge(params.start_date).and(orders::complete_time.lt(params.end_date)))
+        .select((order_lines::barber_id,order_lines::service_type_id,order_lines::amount,order_lines::commission,orders::reversal_of))
+        .get_results::<(Uuid,Uuid,BigDecimal,Option<BigDecimal>,Option<Uuid>)>(&mut *conn)
+        .unwrap();
+
+    let recharges=recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::merchant_id.eq(merchant_id))
+        .filter(recharge_records::create_time.ge(params.start_date).and(recharge_records::create_time.lt(params.end_date)))
+        .group_by(recharge_records::barber_id)
+        .select((recharge_records::barber_id,diesel::dsl::sum(recharge_records::amount)))
+        .get_results::<(Uuid,Option<BigDecimal>)>(&mut *conn)
+        .unwrap();
+    let tips=order_tips::table
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::merchant_id.eq(merchant_id))
+        .filter(order_tips::create_time.ge(params.start_date).and(order_tips::create_time.lt(params.end_date)))
+        .group_by(order_tips::barber_id)
+        .select((order_tips::barber_id,diesel::dsl::sum(order_tips::amount)))
+        .get_results::<(Uuid,Option<BigDecimal>)>(&mut *conn)
+        .unwrap();
+
+    let mut entries:HashMap<Uuid,PayrollEntry>=HashMap::new();
+    for (barber_id,service_type_id,amount,commission,reversal_of) in lines {
+        let is_reversal=reversal_of.is_some();
+        let entry=entries.entry(barber_id).or_default();
+        entry.service_count+=if is_reversal { -1 } else { 1 };
+        entry.service_commission+=commission.unwrap_or_else(||calculator.service_commission(barber_id, service_type_id, &amount, is_reversal));
+        entry.service_amount+=amount;
+    }
+    for (barber_id,amount) in recharges {
+        entries.entry(barber_id).or_default().recharge_amount=amount.unwrap_or_default();
+    }
+    for (barber_id,amount) in tips {
+        entries.entry(barber_id).or_default().tip_amount=amount.unwrap_or_default();
+    }
+
+    let (start_day,end_day)=covered_days(params.start_date, params.end_date);
+
+    let barbers=barbers::table
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .order(barbers::create_time.asc())
+        .get_results::<Barber>(&mut *conn)
+        .unwrap();
+
+    // 已删除但在统计期间内有业绩的理发师仍需列出
+    let data=barbers.into_iter()
+        .filter_map(|b|{
+            let entry=entries.remove(&b.barber_id);
+            if !b.enabled && entry.is_none() {
+                return None;
+            }
+            let mut entry=entry.unwrap_or_default();
+            if let Some(setting)=settings.get(&b.barber_id) {
+                entry.base_wage=prorated_base_wage(&setting.base_wage, start_day, end_day);
+                entry.recharge_commission=recharge_commission(&entry.recharge_amount, &setting.recharge_rate);
+            }
+            entry.total_payable=&entry.base_wage+&entry.service_commission+&entry.recharge_commission+&entry.tip_amount;
+            entry.barber_id=b.barber_id;
+            entry.barber_name=b.real_name;
+            Some(entry)
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_service_commission(){
+        let (barber_a,barber_b,cut,dye)=(Uuid::new_v4(),Uuid::new_v4(),Uuid::new_v4(),Uuid::new_v4());
+        let calculator=CommissionCalculator::new(vec![
+            (None,None,commission_type::PERCENTAGE.to_string(),BigDecimal::from(10)),
+            (None,Some(dye),commission_type::FIXED.to_string(),BigDecimal::from(30)),
+            (Some(barber_a),None,commission_type::PERCENTAGE.to_string(),BigDecimal::from(20)),
+        ]);
+        let amount=BigDecimal::from_str("88.50").unwrap();
+
+        assert_eq!(calculator.service_commission(barber_a, cut, &amount, false), BigDecimal::from_str("17.70").unwrap());
+        assert_eq!(calculator.service_commission(barber_b, cut, &amount, false), BigDecimal::from_str("8.85").unwrap());
+        assert_eq!(calculator.service_commission(barber_b, dye, &amount, false), BigDecimal::from(30));
+        assert_eq!(calculator.service_commission(barber_b, dye, &-&amount, true), BigDecimal::from(-30));
+        assert_eq!(recharge_commission(&BigDecimal::from(1000), &BigDecimal::from(5)), BigDecimal::from(50));
+    }
+
+    #[test]
+    fn test_prorated_base_wage(){
+        let date=|m,d|NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+        let base_wage=BigDecimal::from(3000);
+
+        assert_eq!(prorated_base_wage(&base_wage, date(2, 1), date(3, 1)), BigDecimal::from(3000));
+        assert_eq!(prorated_base_wage(&base_wage, date(4, 1), date(4, 16)), BigDecimal::from(1500));
+        // 跨月按各月天数分别折算：4月后15天 + 5月全月
+        assert_eq!(prorated_base_wage(&base_wage, date(4, 16), date(6, 1)), BigDecimal::from(4500));
+        assert_eq!(prorated_base_wage(&base_wage, date(4, 1), date(4, 1)), BigDecimal::zero());
+    }
+}
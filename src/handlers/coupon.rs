@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Local, DateTime};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, discount_type}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::TransactionError;
+
+// 计算各项明细的优惠金额，(是否适用, 金额)；按百分比的逐项计算，固定金额的按明细顺序抵扣直至用完
+pub fn allocate_discount(discount_type:&str,discount_value:&BigDecimal,lines:&[(bool,BigDecimal)])->Vec<BigDecimal>{
+    let mut rest=discount_value.clone();
+    lines.iter().map(|(eligible,amount)|{
+        if !eligible {
+            return BigDecimal::zero();
+        }
+        let discount=if discount_type==discount_type::PERCENTAGE {
+            (amount*discount_value/BigDecimal::from(100)).round(2)
+        } else {
+            rest.clone()
+        };
+        let discount=if discount>*amount { amount.clone() } else { discount };
+        rest-=&discount;
+        discount
+    }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponResponse{
+    #[serde(flatten)]
+    pub coupon:Coupon,
+
+    pub service_type_ids:Vec<Uuid>, // 为空则不限服务项目
+}
+
+fn load_coupon_service_types(conn:&mut PgConnection,coupon_ids:&[Uuid])->QueryResult<HashMap<Uuid,Vec<Uuid>>>{
+    Ok(coupon_service_types::table
+        .filter(coupon_service_types::coupon_id.eq_any(coupon_ids))
+        .select((coupon_service_types::coupon_id,coupon_service_types::service_type_id))
+        .get_results::<(Uuid,Uuid)>(conn)?
+        .into_iter()
+        .fold(HashMap::new(),|mut items:HashMap<Uuid,Vec<Uuid>>,(coupon_id,service_type_id)|{
+            items.entry(coupon_id).or_default().push(service_type_id);
+            items
+        }))
+}
+
+// 按券码查找本次预约可用的优惠券；每位会员的使用次数在使用时检查
+pub fn find_coupon(conn:&mut PgConnection,merchant_id:Uuid,code:&str,member_id:Option<Uuid>,now:DateTime<Local>)->Result<CouponResponse,(StatusCode,String)>{
+    let coupon=coupons::table
+        .filter(coupons::enabled.eq(true))
+        .filter(coupons::merchant_id.eq(merchant_id))
+        .filter(coupons::code.eq(code.trim()))
+        .get_result::<Coupon>(conn)
+        .optional()
+        .unwrap()
+        .ok_or((StatusCode::BAD_REQUEST,"优惠券不存在".to_string()))?;
+
+    if coupon.start_time.map(|t|now<t).unwrap_or(false) || coupon.end_time.map(|t|now>=t).unwrap_or(false) {
+        return Err((StatusCode::BAD_REQUEST,"优惠券不在有效期内".to_string()));
+    }
+    if (!coupon.is_public || coupon.per_member_limit.is_some()) && member_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST,"该优惠券仅限会员使用".to_string()));
+    }
+    if let (false,Some(member_id))=(coupon.is_public,member_id) {
+        let issued=select(exists(member_coupons::table
+            .filter(member_coupons::enabled.eq(true))
+            .filter(member_coupons::coupon_id.eq(coupon.coupon_id))
+            .filter(member_coupons::member_id.eq(member_id))))
+            .get_result::<bool>(conn)
+            .unwrap();
+        if !issued {
+            return Err((StatusCode::BAD_REQUEST,"该会员未领取此优惠券".to_string()));
+        }
+    }
+
+    let service_type_ids=load_coupon_service_types(conn, &[coupon.coupon_id])
+        .unwrap()
+        .remove(&coupon.coupon_id)
+        .unwrap_or_default();
+
+    Ok(CouponResponse{
+        coupon,
+        service_type_ids,
+    })
+}
+
+// 记录优惠券使用，需在事务中调用；锁定优惠券，避免并发使用超出次数
+pub fn redeem_coupon(conn:&mut PgConnection,merchant_id:Uuid,coupon:&Coupon,order_id:Uuid,member_id:Option<Uuid>,discount:&BigDecimal)->Result<(),TransactionError>{
+    coupons::table
+        .filter(coupons::coupon_id.eq(coupon.coupon_id))
+        .select(coupons::id)
+        .for_update()
+        .get_result::<i64>(conn)?;
+
+    if let (Some(limit),Some(member_id))=(coupon.per_member_limit,member_id) {
+        let used=coupon_redemptions::table
+            .filter(coupon_redemptions::enabled.eq(true))
+            .filter(coupon_redemptions::coupon_id.eq(coupon.coupon_id))
+            .filter(coupon_redemptions::member_id.eq(member_id))
+            .count()
+            .get_result::<i64>(conn)?;
+        if used>=limit as i64 {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"该会员的优惠券使用次数已用完".to_string()));
+        }
+    }
+
+    let new_redemption=NewCouponRedemption{
+        redemption_id:&Uuid::new_v4(),
+        coupon_id:&coupon.coupon_id,
+        merchant_id:&merchant_id,
+        order_id:&order_id,
+        member_id:member_id.as_ref(),
+        discount,
+        enabled:true,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    diesel::insert_into(coupon_redemptions::table)
+        .values(&new_redemption)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// 预约取消后作废优惠券使用记录，退还使用次数
+pub fn release_coupon(conn:&mut PgConnection,order_id:Uuid)->QueryResult<usize>{
+    diesel::update(
+        coupon_redemptions::table
+        .filter(coupon_redemptions::order_id.eq(order_id))
+        .filter(coupon_redemptions::enabled.eq(true))
+    )
+    .set((
+        coupon_redemptions::enabled.eq(false),
+        coupon_redemptions::update_time.eq(Local::now())
+    ))
+    .execute(conn)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponRequest{
+    pub name:String,
+
+    pub code:Option<String>, // 为空则自动生成
+
+    pub discount_type:String,
+
+    pub discount_value:BigDecimal,
+
+    pub start_time:Option<DateTime<Local>>,
+
+    pub end_time:Option<DateTime<Local>>,
+
+    pub per_member_limit:Option<i32>,
+
+    pub is_public:bool,
+
+    #[serde(default)]
+    pub service_type_ids:Vec<Uuid>,
+}
+
+pub async fn get_coupons(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<CouponResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let coupons=coupons::table
+        .filter(coupons::enabled.eq(true))
+        .filter(coupons::merchant_id.eq(merchant_id))
+        .order(coupons::create_time.desc())
+        .get_results::<Coupon>(&mut *conn)
+        .unwrap();
+    let mut items=load_coupon_service_types(&mut conn, &coupons.iter().map(|c|c.coupon_id).collect::<Vec<_>>())
+        .unwrap();
+    let data=coupons.into_iter().map(|c|CouponResponse{
+            service_type_ids:items.remove(&c.coupon_id).unwrap_or_default(),
+            coupon:c,
+        }).collect();
+
+    Ok(Json(data))
+}
+
+pub async fn add_coupon(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<CouponRequest>
+)->Result<Json<CouponResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    match req.discount_type.as_str() {
+        discount_type::PERCENTAGE=>{
+            if req.discount_value<=BigDecimal::zero() || req.discount_value>BigDecimal::from(100) {
+                return Err((StatusCode::BAD_REQUEST,"折扣百分比必须大于0且不超过100".to_string()));
+            }
+        },
+        discount_type::FIXED=>{
+            if req.discount_value<=BigDecimal::zero() {
+                return Err((StatusCode::BAD_REQUEST,"减免金额必须大于0".to_string()));
+            }
+        },
+        _=>return Err((StatusCode::BAD_REQUEST,format!("不支持的优惠类型 {}",req.discount_type))),
+    }
+    if let (Some(start_time),Some(end_time))=(req.start_time,req.end_time) {
+        if end_time<=start_time {
+            return Err((StatusCode::BAD_REQUEST,"结束时间必须晚于开始时间".to_string()));
+        }
+    }
+    if req.per_member_limit.map(|n|n<=0).unwrap_or(false) {
+        return Err((StatusCode::BAD_REQUEST,"每位会员使用次数必须大于0".to_string()));
+    }
+
+    let code=match req.code.as_deref().map(str::trim) {
+        Some(code) if !code.is_empty()=>code.to_uppercase(),
+        _=>Uuid::new_v4().simple().to_string()[..8].to_uppercase(),
+    };
+    let existed=select(exists(coupons::table
+        .filter(coupons::enabled.eq(true))
+        .filter(coupons::merchant_id.eq(merchant_id))
+        .filter(coupons::code.eq(&code))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"券码已存在".to_string()));
+    }
+
+    let mut service_type_ids=req.service_type_ids.clone();
+    service_type_ids.sort();
+    service_type_ids.dedup();
+    let service_count=service_types::table
+        .filter(service_types::enabled.eq(true))
+        .filter(service_types::merchant_id.eq(merchant_id))
+        .filter(service_types::service_type_id.eq_any(&service_type_ids))
+        .count()
+        .get_result::<i64>(&mut *conn)
+        .unwrap();
+    if service_count!=service_type_ids.len() as i64 {
+        return Err((StatusCode::BAD_REQUEST,"服务类型不存在".to_string()));
+    }
+
+    let coupon_id=Uuid::new_v4();
+    let coupon=conn.transaction::<_,TransactionError,_>(|conn|{
+        let new_coupon=NewCoupon{
+            coupon_id:&coupon_id,
+            merchant_id:&merchant_id,
+            name:&req.name,
+            code:&code,
+            discount_type:&req.discount_type,
+            discount_value:&req.discount_value,
+            start_time:req.start_time,
+            end_time:req.end_time,
+            per_member_limit:req.per_member_limit,
+            is_public:req.is_public,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let coupon=diesel::insert_into(coupons::table)
+            .values(&new_coupon)
+            .get_result::<Coupon>(conn)?;
+
+        let new_items=service_type_ids.iter().map(|service_type_id|NewCouponServiceType{
+            coupon_id:&coupon_id,
+            service_type_id,
+        }).collect::<Vec<_>>();
+        diesel::insert_into(coupon_service_types::table)
+            .values(&new_items)
+            .execute(conn)?;
+
+        Ok(coupon)
+    })?;
+
+    Ok(Json(CouponResponse{
+        coupon,
+        service_type_ids,
+    }))
+}
+
+// 停用优惠券，已使用的记录保留用于统计
+pub async fn delete_coupon(
+    State(pg):State<AxumPg>,
+    Path(coupon_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        coupons::table
+        .filter(coupons::coupon_id.eq(coupon_id))
+        .filter(coupons::merchant_id.eq(merchant_id))
+        .filter(coupons::enabled.eq(true))
+    )
+    .set((
+        coupons::enabled.eq(false),
+        coupons::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"优惠券不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssueCouponRequest{
+    pub member_ids:Vec<Uuid>,
+}
+
+// 向指定会员发放优惠券，已发放的会员不重复发放
+pub async fn issue_coupon(
+    State(pg):State<AxumPg>,
+    Path(coupon_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<IssueCouponRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let coupon_existed=select(exists(coupons::table
+        .filter(coupons::enabled.eq(true))
+        .filter(coupons::merchant_id.eq(merchant_id))
+        .filter(coupons::coupon_id.eq(coupon_id))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if !coupon_existed {
+        return Err((StatusCode::NOT_FOUND,"优惠券不存在".to_string()));
+    }
+
+    let mut member_ids=req.member_ids.clone();
+    member_ids.sort();
+    member_ids.dedup();
+    let member_count=merchant_members::table
+        .filter(merchant_members::enabled.eq(true))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::member_id.eq_any(&member_ids))
+        .count()
+        .get_result::<i64>(&mut *conn)
+        .unwrap();
+    if member_count!=member_ids.len() as i64 {
+        return Err((StatusCode::BAD_REQUEST,"会员不存在".to_string()));
+    }
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let issued=member_coupons::table
+            .filter(member_coupons::enabled.eq(true))
+            .filter(member_coupons::coupon_id.eq(coupon_id))
+            .filter(member_coupons::member_id.eq_any(&member_ids))
+            .select(member_coupons::member_id)
+            .get_results::<Uuid>(conn)?;
+
+        for member_id in member_ids.iter().filter(|m|!issued.contains(m)) {
+            let new_member_coupon=NewMemberCoupon{
+                member_coupon_id:&Uuid::new_v4(),
+                coupon_id:&coupon_id,
+                merchant_id:&merchant_id,
+                member_id,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(member_coupons::table)
+                .values(&new_member_coupon)
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberCouponResponse{
+    #[serde(flatten)]
+    pub coupon:Coupon,
+
+    pub member_coupon_id:Uuid,
+
+    pub used_count:i64,
+}
+
+// 会员已领取的优惠券及使用次数
+pub async fn get_coupons_by_member_id(
+    State(pg):State<AxumPg>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<MemberCouponResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let used_counts=coupon_redemptions::table
+        .filter(coupon_redemptions::enabled.eq(true))
+        .filter(coupon_redemptions::merchant_id.eq(merchant_id))
+        .filter(coupon_redemptions::member_id.eq(member_id))
+        .group_by(coupon_redemptions::coupon_id)
+        .select((coupon_redemptions::coupon_id,diesel::dsl::count(coupon_redemptions::id)))
+        .get_results::<(Uuid,i64)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_,_>>();
+
+    let data=member_coupons::table
+        .inner_join(coupons::table.on(member_coupons::coupon_id.eq(coupons::coupon_id)))
+        .filter(member_coupons::enabled.eq(true))
+        .filter(coupons::enabled.eq(true))
+        .filter(member_coupons::merchant_id.eq(merchant_id))
+        .filter(member_coupons::member_id.eq(member_id))
+        .order(member_coupons::create_time.desc())
+        .get_results::<(MemberCoupon,Coupon)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|(member_coupon,coupon)|MemberCouponResponse{
+            used_count:used_counts.get(&coupon.coupon_id).cloned().unwrap_or(0),
+            member_coupon_id:member_coupon.member_coupon_id,
+            coupon,
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[cfg(test)]
+mod test{
+    use std::str::FromStr;
+    use bigdecimal::BigDecimal;
+    use crate::constant::discount_type;
+    use super::allocate_discount;
+
+    #[test]
+    fn test_allocate_discount(){
+        let d=|s:&str|BigDecimal::from_str(s).unwrap();
+        let lines=[(true,d("30")),(false,d("50")),(true,d("20"))];
+
+        assert_eq!(allocate_discount(discount_type::PERCENTAGE, &d("15"), &lines),vec![d("4.5"),d("0"),d("3")]);
+        assert_eq!(allocate_discount(discount_type::FIXED, &d("40"), &lines),vec![d("30"),d("0"),d("10")]);
+        assert_eq!(allocate_discount(discount_type::FIXED, &d("100"), &lines),vec![d("30"),d("0"),d("20")]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{io, panic::{AssertUnwindSafe, catch_unwind}};
+
+use axum::{body::{Bytes, StreamBody}, http::{header, StatusCode}, extract::{Query, State}, response::{IntoResponse, Response}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant,
+    spreadsheet::{Cell, ExportFormat}
+};
+use diesel::prelude::*;
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{Search, member::members_query, statistic::{OrderResponse, OrderRow, RechargeRecordResponse, RechargeRecordRow, StatisticFilter, filtered_orders, filtered_recharge_records, order_responses, order_rows, recharge_record_responses, recharge_record_rows}};
+
+// 每批查询的行数，导出时内存中只保留一批
+const BATCH_SIZE:i64=500;
+
+const ORDER_HEADERS:&[&str]=&["下单时间","顾客类型","会员姓名","会员手机号","服务项目","理发师","时长(分钟)","支付方式","支付明细","金额","小费","状态","退款原订单","退款原因","订单号"];
+const RECHARGE_RECORD_HEADERS:&[&str]=&["充值时间","会员姓名","会员手机号","实付本金","赠送金额","收款方式","经办理发师","撤销原记录","撤销原因","记录号"];
+const MEMBER_HEADERS:&[&str]=&["姓名","手机号","性别","生日","余额","积分","会员等级","备注","注册时间"];
+
+#[derive(Deserialize)]
+pub struct ExportRequest{
+    pub format:Option<String>, // csv / xlsx，默认 csv
+}
+
+impl ExportRequest{
+    fn parse(&self)->Result<ExportFormat,(StatusCode,String)>{
+        ExportFormat::parse(self.format.as_deref())
+            .ok_or_else(||(StatusCode::BAD_REQUEST,format!("不支持的导出格式 {}",self.format.as_deref().unwrap_or_default())))
+    }
+}
+
+fn time_cell(time:&DateTime<Local>)->Cell{
+    time.format("%Y-%m-%d %H:%M:%S").to_string().into()
+}
+
+fn amount_cell(amount:&BigDecimal)->Cell{
+    Cell::Number(amount.with_scale(2).to_string())
+}
+
+fn optional_cell(value:Option<String>)->Cell{
+    value.unwrap_or_default().into()
+}
+
+type PooledPgConnection=diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>;
+
+// 在阻塞线程中按 id 倒序分批查询，每批写完即发送，客户端断开后停止查询；
+// 响应头已发出，查询出错（包括 panic）时向响应体发送错误以中断传输，客户端不会得到截断的文件
+fn stream_export<F>(mut conn:PooledPgConnection,format:ExportFormat,file_name:&str,sheet_name:&str,headers:&'static [&'static str],mut load_batch:F)->Response
+where
+    F:FnMut(&mut PgConnection,Option<i64>)->QueryResult<Vec<(i64,Vec<Cell>)>>+Send+'static,
+{
+    let (tx,rx)=tokio::sync::mpsc::channel::<io::Result<Bytes>>(4);
+    let mut writer=format.writer(sheet_name);
+    let export_name=file_name.to_string();
+    tokio::task::spawn_blocking(move||{
+        let mut chunk=writer.begin(headers);
+        let mut last_id=None;
+        loop {
+            let rows=match catch_unwind(AssertUnwindSafe(||load_batch(&mut conn, last_id))) {
+                Ok(Ok(rows))=>rows,
+                Ok(Err(e))=>{
+                    tracing::error!("export {} failed: {}",export_name,e);
+                    let _=tx.blocking_send(Err(io::Error::other(e)));
+                    return;
+                },
+                Err(_)=>{
+                    tracing::error!("export {} panicked",export_name);
+                    let _=tx.blocking_send(Err(io::Error::other("导出失败")));
+                    return;
+                },
+            };
+            let Some(&(id,_))=rows.last() else {
+                break;
+            };
+            last_id=Some(id);
+            for (_,cells) in rows {
+                chunk.extend(writer.row(&cells));
+            }
+            if tx.blocking_send(Ok(Bytes::from(std::mem::take(&mut chunk)))).is_err() {
+                return;
+            }
+        }
+        chunk.extend(writer.finish());
+        let _=tx.blocking_send(Ok(Bytes::from(chunk)));
+    });
+
+    let stream=futures_util::stream::unfold(rx,|mut rx| async move {
+        rx.recv().await.map(|chunk|(chunk,rx))
+    });
+    let disposition=format!(
+        "attachment; filename=\"{file_name}-{}.{}\"",
+        Local::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+    (
+        [(header::CONTENT_TYPE,format.content_type().to_string()),(header::CONTENT_DISPOSITION,disposition)],
+        StreamBody::new(stream),
+    ).into_response()
+}
+
+// OrderResponse 的顾客类型、支付方式和状态已是显示名称
+fn order_cells(order:OrderResponse)->Vec<Cell>{
+    let (service_names,barber_names)=if order.lines.is_empty() {
+        (order.service_name,order.barber_name)
+    } else {
+        let mut barber_names=Vec::new();
+        for line in &order.lines {
+            if !barber_names.contains(&line.barber_name) {
+                barber_names.push(line.barber_name.clone());
+            }
+        }
+        (order.lines.iter().map(|l|l.service_name.as_str()).collect::<Vec<_>>().join("、"),barber_names.join("、"))
+    };
+    let payments=order.payments.iter()
+        .map(|p|format!("{} {}",p.tender_name,p.amount.with_scale(2)))
+        .collect::<Vec<_>>()
+        .join("、");
+    let tip_amount=order.tips.iter().map(|t|&t.amount).sum::<BigDecimal>();
+    vec![
+        time_cell(&order.create_time),
+        order.consumer_type.into(),
+        order.member_name.into(),
+        order.member_cellphone.into(),
+        service_names.into(),
+        barber_names.into(),
+        Cell::Number(order.total_minutes.to_string()),
+        order.payment_type.into(),
+        payments.into(),
+        amount_cell(&order.amount),
+        amount_cell(&tip_amount),
+        order.status.into(),
+        optional_cell(order.reversal_of.map(|id|id.to_string())),
+        optional_cell(order.reversal_reason),
+        order.order_id.to_string().into(),
+    ]
+}
+
+// 导出订单，筛选条件与订单统计列表一致
+pub async fn export_orders(
+    State(pg):State<AxumPg>,
+    Query(export):Query<ExportRequest>,
+    Query(search):Query<Search>,
+    Query(filter):Query<StatisticFilter>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let format=export.parse()?;
+    filter.validate()?;
+
+    let conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    Ok(stream_export(conn, format, "orders", "订单", ORDER_HEADERS, move |conn,last_id|{
+        let mut query=order_rows()
+            .filter(orders::id.eq_any(filtered_orders(merchant_id, &search, &filter).select(orders::id)))
+            .into_boxed();
+        if let Some(last_id)=last_id {
+            query=query.filter(orders::id.lt(last_id));
+        }
+        let rows=query
+            .order(orders::id.desc())
+            .limit(BATCH_SIZE)
+            .get_results::<OrderRow>(conn)?;
+        let ids=rows.iter().map(|t|t.0.id).collect::<Vec<_>>();
+        Ok(ids.into_iter().zip(order_responses(conn, rows).into_iter().map(order_cells)).collect())
+    }))
+}
+
+fn recharge_record_cells(record:RechargeRecordResponse)->Vec<Cell>{
+    vec![
+        time_cell(&record.crate_time),
+        record.member_name.into(),
+        record.member_cellphone.into(),
+        amount_cell(&record.amount),
+        amount_cell(&record.bonus),
+        record.tender_name.into(),
+        record.barber_name.into(),
+        optional_cell(record.reversal_of.map(|id|id.to_string())),
+        optional_cell(record.reversal_reason),
+        record.recharge_record_id.to_string().into(),
+    ]
+}
+
+// 导出充值记录，筛选条件与充值统计列表一致
+pub async fn export_recharge_records(
+    State(pg):State<AxumPg>,
+    Query(export):Query<ExportRequest>,
+    Query(search):Query<Search>,
+    Query(filter):Query<StatisticFilter>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let format=export.parse()?;
+    filter.validate_for_recharge_records()?;
+
+    let conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    Ok(stream_export(conn, format, "recharge_records", "充值记录", RECHARGE_RECORD_HEADERS, move |conn,last_id|{
+        let mut query=recharge_record_rows()
+            .filter(recharge_records::id.eq_any(filtered_recharge_records(merchant_id, &search, &filter).select(recharge_records::id)))
+            .into_boxed();
+        if let Some(last_id)=last_id {
+            query=query.filter(recharge_records::id.lt(last_id));
+        }
+        let rows=query
+            .order(recharge_records::id.desc())
+            .limit(BATCH_SIZE)
+            .get_results::<RechargeRecordRow>(conn)?;
+        let ids=rows.iter().map(|t|t.0.id).collect::<Vec<_>>();
+        Ok(ids.into_iter().zip(recharge_record_responses(conn, rows).into_iter().map(recharge_record_cells)).collect())
+    }))
+}
+
+fn member_cells(member:MerchantMember,level:Option<MemberLevel>)->Vec<Cell>{
+    vec![
+        member.real_name.into(),
+        member.cellphone.into(),
+        optional_cell(member.gender),
+        optional_cell(member.birth_day.map(|d|d.format("%Y-%m-%d").to_string())),
+        amount_cell(&member.balance),
+        Cell::Number(member.points.to_string()),
+        optional_cell(level.filter(|l|l.enabled).map(|l|l.name)),
+        optional_cell(member.remark),
+        time_cell(&member.create_time),
+    ]
+}
+
+// 导出会员，搜索条件与会员列表一致
+pub async fn export_members(
+    State(pg):State<AxumPg>,
+    Query(export):Query<ExportRequest>,
+    Query(search):Query<Search>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MEMBER]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let format=export.parse()?;
+
+    let conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    Ok(stream_export(conn, format, "members", "会员", MEMBER_HEADERS, move |conn,last_id|{
+        let mut query=merchant_members::table
+            .left_join(member_levels::table.on(merchant_members::member_level_id.eq(member_levels::member_level_id.nullable())))
+            .filter(merchant_members::id.eq_any(members_query(merchant_id, &search).select(merchant_members::id)))
+            .into_boxed();
+        if let Some(last_id)=last_id {
+            query=query.filter(merchant_members::id.lt(last_id));
+        }
+        let rows=query
+            .order(merchant_members::id.desc())
+            .limit(BATCH_SIZE)
+            .get_results::<(MerchantMember,Option<MemberLevel>)>(conn)?;
+        Ok(rows.into_iter().map(|(member,level)|(member.id,member_cells(member,level))).collect())
+    }))
+}
@@ -1,18 +1,20 @@
+use std::cmp::min;
+
 use axum::{http::StatusCode, Json, extract::{Query, Path, State}};
 use axum_session_authentication_middleware::session::AuthSession;
 use bigdecimal::{BigDecimal, Zero};
 use chrono::{Local, NaiveDate};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use crate::{
     schema::*,
     models::*, 
     authorization_policy, 
-    constant
+    constant::{self, order_status, ledger_type, payment_type}
 };
-use diesel::{prelude::*, select, dsl::exists}; 
+use diesel::{prelude::*, pg::Pg, select, dsl::exists}; 
 use crate::{models::User, axum_pg::AxumPg};
-use super::{PaginatedListRequest,PaginatedListResponse, Search, statistic::{OrderResponse, RechargeRecordResponse}};
+use super::{PaginatedListRequest,PaginatedListResponse, Search, TransactionError, balance_ledger::{change_balance, current_barber_id}, member_level::{MemberLevelResponse, load_member_level, promote_member}, recharge_tier::find_recharge_tier, service_package::{MemberPackageResponse, load_member_packages}, statistic::{OrderResponse, RechargeRecordResponse, load_order_lines, load_order_payments, load_order_reversals, load_recharge_reversals}, tip::load_order_tips};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -28,12 +30,39 @@ pub struct MemberRequest{
     pub remark:Option<String>,
 }
 
+// 按搜索条件过滤会员，列表和导出共用
+pub fn members_query<'a>(merchant_id:Uuid,search:&'a Search)->merchant_members::BoxedQuery<'a,Pg>{
+    let mut query=merchant_members::table
+        .filter(merchant_members::enabled.eq(true))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .into_boxed();
+        
+    if let Some(key)=search.key.as_ref(){
+        query=query.filter(merchant_members::cellphone.ilike(format!("%{key}%")).or(merchant_members::real_name.ilike(format!("%{key}%"))));  
+    }
+
+    if let Some(gender)=search.filter_gender.as_ref(){
+        query=query.filter(merchant_members::gender.eq(gender));  
+    }
+
+    query
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberListItem{
+    #[serde(flatten)]
+    pub member:MerchantMember,
+
+    pub level_name:Option<String>, // 会员等级名称
+}
+
 pub async fn get_members(
     State(pg):State<AxumPg>,
     Query(params):Query<PaginatedListRequest>, 
     Query(search):Query<Search>, 
     auth: AuthSession<AxumPg, AxumPg,User>,
-)->Result<Json<PaginatedListResponse<MerchantMember>>,(StatusCode,String)>{
+)->Result<Json<PaginatedListResponse<MemberListItem>>,(StatusCode,String)>{
     //检查登录&权限
     auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
     
@@ -41,33 +70,24 @@ pub async fn get_members(
     
     let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
 
-    let fn_get_members_query=||{
-        let mut query=merchant_members::table
-            .filter(merchant_members::enabled.eq(true))
-            .filter(merchant_members::merchant_id.eq(merchant_id))
-            .into_boxed();
-            
-        if let Some(key)=search.key.as_ref(){
-            query=query.filter(merchant_members::cellphone.ilike(format!("%{key}%")).or(merchant_members::real_name.ilike(format!("%{key}%"))));  
-        }
-
-        if let Some(gender)=search.filter_gender.as_ref(){
-            query=query.filter(merchant_members::gender.eq(gender));  
-        }
-
-        query
-    };
-
-    let count=fn_get_members_query()
+    let count=members_query(merchant_id, &search)
         .count()
         .get_result(&mut *conn)
         .unwrap();
-    let data=fn_get_members_query()
+    let data=merchant_members::table
+        .left_join(member_levels::table.on(merchant_members::member_level_id.eq(member_levels::member_level_id.nullable())))
+        .filter(merchant_members::id.eq_any(members_query(merchant_id, &search).select(merchant_members::id)))
         .order(merchant_members::create_time.desc())
         .limit(params.page_size)
         .offset(params.page_index*params.page_size)
-        .get_results::<MerchantMember>(&mut *conn)
-        .unwrap();
+        .get_results::<(MerchantMember,Option<MemberLevel>)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|(member,level)|MemberListItem{
+            member,
+            level_name:level.filter(|l|l.enabled).map(|l|l.name),
+        })
+        .collect::<Vec<_>>();
     
     Ok(Json(PaginatedListResponse{
         page_index:params.page_index,
@@ -223,11 +243,21 @@ pub async fn update_member(
     Ok(())
 }
 
+#[derive(Serialize)]
+pub struct MemberResponse{
+    #[serde(flatten)]
+    pub member:MerchantMember,
+
+    pub level:Option<MemberLevelResponse>, // 会员等级及会员价
+
+    pub packages:Vec<MemberPackageResponse>, // 已购买的套餐及剩余次数
+}
+
 pub async fn get_member(
     State(pg):State<AxumPg>,
     Path(member_id):Path<Uuid>, 
     auth: AuthSession<AxumPg, AxumPg,User>,
-)->Result<Json<MerchantMember>,(StatusCode,String)>{
+)->Result<Json<MemberResponse>,(StatusCode,String)>{
     //检查登录&权限
     auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
 
@@ -241,13 +271,24 @@ pub async fn get_member(
         .filter(merchant_members::merchant_id.eq(merchant_id))
         .get_result::<MerchantMember>(&mut *conn)
         .map_err(|e|(StatusCode::NOT_FOUND,e.to_string()))?;
+
+    let level=load_member_level(&mut conn, merchant_id, member_id)
+        .unwrap();
+    let packages=load_member_packages(&mut conn, merchant_id, member_id)
+        .unwrap();
         
-    Ok(Json(member))
+    Ok(Json(MemberResponse{
+        member,
+        level,
+        packages,
+    }))
 }
 
 #[derive(Deserialize)]
 pub struct RechargeRequest{
     amount:BigDecimal,
+
+    tender:Option<String>, // cash/card/wechat/alipay，默认现金
 }
 
 pub async fn recharge(
@@ -278,20 +319,11 @@ pub async fn recharge(
     if req.amount<=BigDecimal::zero() {
         return Err((StatusCode::BAD_REQUEST,"充值金额必须大于0".to_string()));
     }
+    let tender=req.tender.as_deref().unwrap_or(payment_type::CASH);
+    if !payment_type::is_tender(tender) || tender==payment_type::MEMBER || tender==payment_type::PACKAGE || tender==payment_type::POINTS {
+        return Err((StatusCode::BAD_REQUEST,format!("不支持的支付方式 {tender}")));
+    }
 
-    diesel::update(
-        merchant_members::table
-        .filter(merchant_members::member_id.eq(member_id))
-        .filter(merchant_members::merchant_id.eq(merchant_id))
-        .filter(merchant_members::enabled.eq(true))
-    )
-    .set((
-        merchant_members::balance.eq(merchant_members::balance + &req.amount),
-        merchant_members::update_time.eq(Local::now())
-    ))
-    .execute(&mut *conn)
-    .unwrap();
-    
     let barber=barbers::table
         .filter(barbers::enabled.eq(true))
         .filter(barbers::merchant_id.eq(merchant_id))
@@ -299,21 +331,144 @@ pub async fn recharge(
         .get_result::<Barber>(&mut *conn)
         .unwrap();
 
-    let new_recharge_record=NewRechargeRecord{
-        recharge_record_id:&Uuid::new_v4(),
-        merchant_id:&merchant_id,
-        member_id: &member_id,
-        amount:&req.amount,
-        barber_id:&barber.barber_id,
-        enabled:true,
-        create_time: Local::now(),
-        update_time: Local::now(),
-        data: None,
-    };
-    diesel::insert_into(recharge_records::table)
-        .values(&new_recharge_record)
-        .execute(&mut *conn)
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        // 按充值金额匹配赠送档位，赠送金额与本金分开记录
+        let tier=find_recharge_tier(conn, merchant_id, &req.amount)?;
+        let bonus=tier.as_ref().map(|t|t.bonus.clone()).unwrap_or_else(BigDecimal::zero);
+
+        let recharge_record_id=Uuid::new_v4();
+        let new_recharge_record=NewRechargeRecord{
+            recharge_record_id:&recharge_record_id,
+            merchant_id:&merchant_id,
+            member_id: &member_id,
+            amount:&req.amount,
+            barber_id:&barber.barber_id,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+            reversal_of: None,
+            reversal_reason: None,
+            bonus:&bonus,
+            recharge_tier_id:tier.as_ref().map(|t|&t.recharge_tier_id),
+            tender,
+        };
+        diesel::insert_into(recharge_records::table)
+            .values(&new_recharge_record)
+            .execute(conn)?;
+
+        change_balance(conn, merchant_id, member_id, &req.amount, ledger_type::RECHARGE, Some(recharge_record_id), Some(barber.barber_id), None)?;
+        if let Some(tier)=tier.as_ref() {
+            change_balance(conn, merchant_id, member_id, &bonus, ledger_type::RECHARGE_BONUS, Some(recharge_record_id), Some(barber.barber_id), Some(&tier.name))?;
+        }
+        promote_member(conn, merchant_id, member_id)?;
+
+        Ok(())
+    })?;
+    
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ReverseRechargeRequest{
+    reason:String,
+}
+
+// 撤销充值：保留原充值记录，新增一条金额为负数的撤销记录并扣回余额，已消费的部分不再退还
+pub async fn reverse_recharge(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Path(recharge_record_id):Path<Uuid>, 
+    Json(req): Json<ReverseRechargeRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+    
+    let mut conn=pg.pool.get().unwrap();
+    
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.reason.trim().is_empty(){
+        return Err((StatusCode::BAD_REQUEST,"撤销原因不能为空".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
         .unwrap();
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        // 锁定原充值记录，避免重复撤销
+        let record=recharge_records::table
+            .filter(recharge_records::enabled.eq(true))
+            .filter(recharge_records::merchant_id.eq(merchant_id))
+            .filter(recharge_records::recharge_record_id.eq(recharge_record_id))
+            .for_update()
+            .get_result::<RechargeRecord>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"充值记录不存在".to_string()))?;
+
+        if record.reversal_of.is_some() {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"撤销记录不能再撤销".to_string()));
+        }
+
+        let reversed=select(exists(
+            recharge_records::table
+            .filter(recharge_records::merchant_id.eq(merchant_id))
+            .filter(recharge_records::reversal_of.eq(recharge_record_id))
+            ))
+            .get_result::<bool>(conn)?;
+        if reversed {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"该充值记录已撤销".to_string()));
+        }
+
+        // 本金与赠送金额合并在余额中，约定消费时先用本金、后用赠送金额：
+        // 撤销时先扣回剩余的赠送金额，本金只退还余额中剩余的部分
+        let balance=merchant_members::table
+            .filter(merchant_members::enabled.eq(true))
+            .filter(merchant_members::merchant_id.eq(merchant_id))
+            .filter(merchant_members::member_id.eq(record.member_id))
+            .select(merchant_members::balance)
+            .for_update()
+            .get_result::<BigDecimal>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,"会员不存在".to_string()))?;
+        let bonus=min(record.bonus.clone(),balance.clone());
+        let amount=min(record.amount.clone(),&balance-&bonus);
+        if amount.is_zero() && bonus.is_zero() {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"会员余额已用完，无法撤销".to_string()));
+        }
+
+        // 撤销记录沿用原充值的经办理发师，充值提成在撤销当期扣回；操作人记录在余额流水中
+        let reversal_id=Uuid::new_v4();
+        let new_reversal=NewRechargeRecord{
+            recharge_record_id:&reversal_id,
+            merchant_id:&merchant_id,
+            member_id: &record.member_id,
+            amount:&-&amount,
+            barber_id:&record.barber_id,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+            reversal_of: Some(&recharge_record_id),
+            reversal_reason: Some(&req.reason),
+            bonus:&-&bonus,
+            recharge_tier_id:record.recharge_tier_id.as_ref(),
+            tender:&record.tender,
+        };
+        diesel::insert_into(recharge_records::table)
+            .values(&new_reversal)
+            .execute(conn)?;
+
+        if !amount.is_zero() {
+            change_balance(conn, merchant_id, record.member_id, &-&amount, ledger_type::RECHARGE_REVERSAL, Some(reversal_id), operator_id, Some(&req.reason))?;
+        }
+        if !bonus.is_zero() {
+            change_balance(conn, merchant_id, record.member_id, &-&bonus, ledger_type::BONUS_REVERSAL, Some(reversal_id), operator_id, Some(&req.reason))?;
+        }
+
+        Ok(())
+    })?;
     
     Ok(())
 }
@@ -347,12 +502,18 @@ pub async fn get_orders_by_member_id(
         .count()
         .get_result(&mut *conn)
         .unwrap();
-    let data=fn_get_query()
+    let rows=fn_get_query()
         .order(orders::create_time.desc())
         .limit(params.page_size)
         .offset(params.page_index*params.page_size)
         .get_results::<(Order,MerchantMember,Option<Barber>,Option<ServiceType>)>(&mut *conn)
-        .map(|v|v.into_iter().map(|t|OrderResponse{
+        .unwrap();
+    let order_ids=rows.iter().map(|t|t.0.order_id).collect::<Vec<_>>();
+    let mut lines=load_order_lines(&mut conn, &order_ids);
+    let mut payments=load_order_payments(&mut conn, &order_ids);
+    let mut tips=load_order_tips(&mut conn, &order_ids);
+    let mut reversals=load_order_reversals(&mut conn, &order_ids);
+    let data=rows.into_iter().map(|t|OrderResponse{
             order_id:t.0.order_id,
             service_name:t.3.map(|s|s.name).unwrap_or("-".into()),
             consumer_type: if t.0.consumer_type =="member" {
@@ -364,11 +525,17 @@ pub async fn get_orders_by_member_id(
             member_cellphone:t.1.cellphone.clone(),
             amount:t.0.amount,
             total_minutes:(t.0.end_time-t.0.start_time).num_minutes(),
-            payment_type: if t.0.payment_type=="member" {"会员充值".into()} else {"现金".into()},
+            payment_type: payment_type::display_name(&t.0.payment_type).into(),
             barber_name: if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone()} else {"-".into()},
+            status:order_status::display_name(&t.0.status).into(),
+            lines:lines.remove(&t.0.order_id).unwrap_or_default(),
+            payments:payments.remove(&t.0.order_id).unwrap_or_default(),
+            tips:tips.remove(&t.0.order_id).unwrap_or_default(),
+            reversed_by:reversals.remove(&t.0.order_id),
+            reversal_of:t.0.reversal_of,
+            reversal_reason:t.0.reversal_reason,
             create_time:t.0.create_time,
-        }).collect())
-        .unwrap();
+        }).collect();
     
     Ok(Json(PaginatedListResponse{
         page_index:params.page_index,
@@ -406,20 +573,27 @@ pub async fn get_recharge_records_by_member_id(
         .count()
         .get_result(&mut *conn)
         .unwrap();
-    let data=fn_get_query()
+    let rows=fn_get_query()
         .order(recharge_records::create_time.desc())
         .limit(params.page_size)
         .offset(params.page_index*params.page_size)
         .get_results::<(RechargeRecord,MerchantMember,Option<Barber>)>(&mut *conn)
-        .map(|v|v.into_iter().map(|t|RechargeRecordResponse{
+        .unwrap();
+    let mut reversals=load_recharge_reversals(&mut conn, &rows.iter().map(|t|t.0.recharge_record_id).collect::<Vec<_>>());
+    let data=rows.into_iter().map(|t|RechargeRecordResponse{
             recharge_record_id:t.0.recharge_record_id,
             member_name: t.1.real_name.clone(),
             member_cellphone:t.1.cellphone.clone(),
             amount:t.0.amount,
+            bonus:t.0.bonus,
+            tender_name:payment_type::display_name(&t.0.tender).into(),
+            tender:t.0.tender,
             barber_name:if t.2.as_ref().unwrap().enabled { t.2.as_ref().unwrap().real_name.clone()} else {"-".into() },
+            reversed_by:reversals.remove(&t.0.recharge_record_id),
+            reversal_of:t.0.reversal_of,
+            reversal_reason:t.0.reversal_reason,
             crate_time:t.0.create_time,
-        }).collect())
-        .unwrap();
+        }).collect();
     
     Ok(Json(PaginatedListResponse{
         page_index:params.page_index,
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero, One};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, statistic::is_completed_sale};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberLevelPriceRequest{
+    pub service_type_id:Uuid,
+
+    pub price:BigDecimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberLevelRequest{
+    pub name:String,
+
+    pub rank:i32,
+
+    pub discount_rate:BigDecimal,
+
+    pub recharge_threshold:Option<BigDecimal>,
+
+    pub spend_threshold:Option<BigDecimal>,
+
+    #[serde(default)]
+    pub prices:Vec<MemberLevelPriceRequest>, // 指定服务项目的会员价，未指定的按折扣率计算
+}
+
+impl MemberLevelRequest{
+    fn validate(&self)->Result<(),(StatusCode,String)>{
+        if self.name.trim().is_empty() {
+            return Err((StatusCode::BAD_REQUEST,"等级名称不能为空".to_string()));
+        }
+        if self.discount_rate<=BigDecimal::zero() || self.discount_rate>BigDecimal::one() {
+            return Err((StatusCode::BAD_REQUEST,"折扣率必须大于0且不超过1".to_string()));
+        }
+        let is_negative=|t:&Option<BigDecimal>|t.as_ref().map(|t|*t<BigDecimal::zero()).unwrap_or(false);
+        if is_negative(&self.recharge_threshold) || is_negative(&self.spend_threshold) {
+            return Err((StatusCode::BAD_REQUEST,"升级门槛不能为负数".to_string()));
+        }
+        if self.prices.iter().any(|p|p.price<BigDecimal::zero()) {
+            return Err((StatusCode::BAD_REQUEST,"会员价不能为负数".to_string()));
+        }
+        let mut service_type_ids=self.prices.iter().map(|p|p.service_type_id).collect::<Vec<_>>();
+        service_type_ids.sort();
+        service_type_ids.dedup();
+        if service_type_ids.len()!=self.prices.len() {
+            return Err((StatusCode::BAD_REQUEST,"同一服务项目只能设置一个会员价".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberLevelResponse{
+    #[serde(flatten)]
+    pub level:MemberLevel,
+
+    pub prices:Vec<MemberLevelPrice>,
+}
+
+impl MemberLevelResponse{
+    // 该等级下服务项目的价格，价目表优先，否则按原价打折
+    pub fn price_of(&self,service:&ServiceType)->BigDecimal{
+        self.prices.iter()
+            .find(|p|p.service_type_id==service.service_type_id)
+            .map(|p|p.price.clone())
+            .unwrap_or_else(||discount_price(&service.normal_prize, &self.level.discount_rate))
+    }
+}
+
+// 按折扣率计算价格，保留两位小数
+pub fn discount_price(price:&BigDecimal,discount_rate:&BigDecimal)->BigDecimal{
+    (price*discount_rate).round(2)
+}
+
+fn load_level_prices(conn:&mut PgConnection,member_level_ids:&[Uuid])->QueryResult<HashMap<Uuid,Vec<MemberLevelPrice>>>{
+    Ok(member_level_prices::table
+        .filter(member_level_prices::member_level_id.eq_any(member_level_ids))
+        .get_results::<MemberLevelPrice>(conn)?
+        .into_iter()
+        .fold(HashMap::new(),|mut prices:HashMap<Uuid,Vec<MemberLevelPrice>>,p|{
+            prices.entry(p.member_level_id).or_default().push(p);
+            prices
+        }))
+}
+
+// 会员当前等级及价目表，未设置等级时返回 None
+pub fn load_member_level(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid)->QueryResult<Option<MemberLevelResponse>>{
+    let level=merchant_members::table
+        .inner_join(member_levels::table.on(merchant_members::member_level_id.eq(member_levels::member_level_id.nullable())))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(member_levels::enabled.eq(true))
+        .select(member_levels::all_columns)
+        .get_result::<MemberLevel>(conn)
+        .optional()?;
+
+    let Some(level)=level else {
+        return Ok(None);
+    };
+    let prices=load_level_prices(conn, &[level.member_level_id])?
+        .remove(&level.member_level_id)
+        .unwrap_or_default();
+
+    Ok(Some(MemberLevelResponse{
+        level,
+        prices,
+    }))
+}
+
+// 按累计充值和累计消费自动升级会员等级，只升不降，需在事务中调用
+pub fn promote_member(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid)->QueryResult<Option<MemberLevel>>{
+    // 充值撤销记录金额为负数，合计即为实际累计充值
+    let recharge_total=recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::merchant_id.eq(merchant_id))
+        .filter(recharge_records::member_id.eq(member_id))
+        .select(diesel::dsl::sum(recharge_records::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_else(BigDecimal::zero);
+
+    // 已完成的订单计入消费，已完成订单的退款记录冲减
+    let spend_total=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::member_id.eq(member_id))
+        .filter(is_completed_sale())
+        .select(diesel::dsl::sum(orders::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_else(BigDecimal::zero);
+
+    let current_rank=merchant_members::table
+        .inner_join(member_levels::table.on(merchant_members::member_level_id.eq(member_levels::member_level_id.nullable())))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(member_levels::enabled.eq(true))
+        .select(member_levels::rank)
+        .get_result::<i32>(conn)
+        .optional()?;
+
+    let mut query=member_levels::table
+        .filter(member_levels::enabled.eq(true))
+        .filter(member_levels::merchant_id.eq(merchant_id))
+        .filter(member_levels::recharge_threshold.le(&recharge_total).or(member_levels::spend_threshold.le(&spend_total)))
+        .into_boxed();
+    if let Some(current_rank)=current_rank {
+        query=query.filter(member_levels::rank.gt(current_rank));
+    }
+    let level=query
+        .order(member_levels::rank.desc())
+        .first::<MemberLevel>(conn)
+        .optional()?;
+
+    if let Some(level)=level.as_ref() {
+        diesel::update(merchant_members::table)
+            .filter(merchant_members::merchant_id.eq(merchant_id))
+            .filter(merchant_members::member_id.eq(member_id))
+            .set((
+                merchant_members::member_level_id.eq(level.member_level_id),
+                merchant_members::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(level)
+}
+
+// 检查价目表中的服务项目是否都属于本商户
+fn check_service_types(conn:&mut PgConnection,merchant_id:Uuid,prices:&[MemberLevelPriceRequest])->Result<(),(StatusCode,String)>{
+    let service_type_ids=prices.iter().map(|p|p.service_type_id).collect::<Vec<_>>();
+    let service_count=service_types::table
+        .filter(service_types::enabled.eq(true))
+        .filter(service_types::merchant_id.eq(merchant_id))
+        .filter(service_types::service_type_id.eq_any(&service_type_ids))
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    if service_count!=service_type_ids.len() as i64 {
+        return Err((StatusCode::BAD_REQUEST,"服务类型不存在".to_string()));
+    }
+    Ok(())
+}
+
+fn insert_level_prices(conn:&mut PgConnection,member_level_id:Uuid,prices:&[MemberLevelPriceRequest])->QueryResult<Vec<MemberLevelPrice>>{
+    let new_prices=prices.iter().map(|p|NewMemberLevelPrice{
+        member_level_id:&member_level_id,
+        service_type_id:&p.service_type_id,
+        price:&p.price,
+    }).collect::<Vec<_>>();
+    diesel::insert_into(member_level_prices::table)
+        .values(&new_prices)
+        .get_results::<MemberLevelPrice>(conn)
+}
+
+pub async fn get_member_levels(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<MemberLevelResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let levels=member_levels::table
+        .filter(member_levels::enabled.eq(true))
+        .filter(member_levels::merchant_id.eq(merchant_id))
+        .order(member_levels::rank.asc())
+        .get_results::<MemberLevel>(&mut *conn)
+        .unwrap();
+    let mut prices=load_level_prices(&mut conn, &levels.iter().map(|l|l.member_level_id).collect::<Vec<_>>())
+        .unwrap();
+    let data=levels.into_iter().map(|l|MemberLevelResponse{
+            prices:prices.remove(&l.member_level_id).unwrap_or_default(),
+            level:l,
+        }).collect();
+
+    Ok(Json(data))
+}
+
+pub async fn add_member_level(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<MemberLevelRequest>
+)->Result<Json<MemberLevelResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    req.validate()?;
+    check_service_types(&mut conn, merchant_id, &req.prices)?;
+
+    let existed=select(exists(member_levels::table
+        .filter(member_levels::enabled.eq(true))
+        .filter(member_levels::rank.eq(req.rank))
+        .filter(member_levels::merchant_id.eq(merchant_id))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"已存在相同级别的会员等级".to_string()));
+    }
+
+    let member_level_id=Uuid::new_v4();
+    let data=conn.transaction::<_,TransactionError,_>(|conn|{
+        let new_level=NewMemberLevel{
+            member_level_id:&member_level_id,
+            merchant_id:&merchant_id,
+            name:&req.name,
+            rank:req.rank,
+            discount_rate:&req.discount_rate,
+            recharge_threshold:req.recharge_threshold.as_ref(),
+            spend_threshold:req.spend_threshold.as_ref(),
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let level=diesel::insert_into(member_levels::table)
+            .values(&new_level)
+            .get_result::<MemberLevel>(conn)?;
+        let prices=insert_level_prices(conn, member_level_id, &req.prices)?;
+
+        Ok(MemberLevelResponse{
+            level,
+            prices,
+        })
+    })?;
+
+    Ok(Json(data))
+}
+
+// 修改等级设置，价目表整体替换；已有会员不会因门槛调整而降级
+pub async fn update_member_level(
+    State(pg):State<AxumPg>,
+    Path(member_level_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<MemberLevelRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    req.validate()?;
+    check_service_types(&mut conn, merchant_id, &req.prices)?;
+
+    let existed=select(exists(member_levels::table
+        .filter(member_levels::enabled.eq(true))
+        .filter(member_levels::rank.eq(req.rank))
+        .filter(member_levels::member_level_id.ne(member_level_id))
+        .filter(member_levels::merchant_id.eq(merchant_id))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"已存在相同级别的会员等级".to_string()));
+    }
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let count=diesel::update(
+            member_levels::table
+            .filter(member_levels::member_level_id.eq(member_level_id))
+            .filter(member_levels::merchant_id.eq(merchant_id))
+            .filter(member_levels::enabled.eq(true))
+        )
+        .set((
+            member_levels::name.eq(&req.name),
+            member_levels::rank.eq(req.rank),
+            member_levels::discount_rate.eq(&req.discount_rate),
+            member_levels::recharge_threshold.eq(req.recharge_threshold.as_ref()),
+            member_levels::spend_threshold.eq(req.spend_threshold.as_ref()),
+            member_levels::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+        if count==0 {
+            return Err(TransactionError(StatusCode::NOT_FOUND,"会员等级不存在".to_string()));
+        }
+
+        diesel::delete(member_level_prices::table.filter(member_level_prices::member_level_id.eq(member_level_id)))
+            .execute(conn)?;
+        insert_level_prices(conn, member_level_id, &req.prices)?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// 删除等级，该等级的会员恢复为普通会员
+pub async fn delete_member_level(
+    State(pg):State<AxumPg>,
+    Path(member_level_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let count=diesel::update(
+            member_levels::table
+            .filter(member_levels::member_level_id.eq(member_level_id))
+            .filter(member_levels::merchant_id.eq(merchant_id))
+            .filter(member_levels::enabled.eq(true))
+        )
+        .set((
+            member_levels::enabled.eq(false),
+            member_levels::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+        if count==0 {
+            return Err(TransactionError(StatusCode::NOT_FOUND,"会员等级不存在".to_string()));
+        }
+
+        diesel::update(
+            merchant_members::table
+            .filter(merchant_members::merchant_id.eq(merchant_id))
+            .filter(merchant_members::member_level_id.eq(member_level_id))
+        )
+        .set((
+            merchant_members::member_level_id.eq(None::<Uuid>),
+            merchant_members::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetMemberLevelRequest{
+    pub member_level_id:Option<Uuid>, // 为空则取消等级
+}
+
+// 手动调整会员等级
+pub async fn set_member_level(
+    State(pg):State<AxumPg>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<SetMemberLevelRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if let Some(member_level_id)=req.member_level_id {
+        let existed=select(exists(member_levels::table
+            .filter(member_levels::enabled.eq(true))
+            .filter(member_levels::member_level_id.eq(member_level_id))
+            .filter(member_levels::merchant_id.eq(merchant_id))))
+            .get_result::<bool>(&mut *conn)
+            .unwrap();
+        if !existed {
+            return Err((StatusCode::BAD_REQUEST,"会员等级不存在".to_string()));
+        }
+    }
+
+    let count=diesel::update(
+        merchant_members::table
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::enabled.eq(true))
+    )
+    .set((
+        merchant_members::member_level_id.eq(req.member_level_id),
+        merchant_members::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"会员不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test{
+    use std::str::FromStr;
+    use bigdecimal::BigDecimal;
+    use super::discount_price;
+
+    #[test]
+    fn test_discount_price(){
+        let price=BigDecimal::from_str("58").unwrap();
+
+        assert_eq!(discount_price(&price, &BigDecimal::from_str("0.85").unwrap()),BigDecimal::from_str("49.30").unwrap());
+        assert_eq!(discount_price(&price, &BigDecimal::from_str("1").unwrap()),price);
+    }
+}
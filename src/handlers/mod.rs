@@ -1,13 +1,29 @@
 pub mod identity;
 pub mod barber;
 pub mod member;
+pub mod member_level;
+pub mod points;
+pub mod balance_ledger;
 pub mod appointment;
+pub mod business_hour;
+pub mod coupon;
+pub mod commission;
+pub mod tip;
+pub mod payment;
 pub mod service_type;
+pub mod service_package;
+pub mod receipt;
+pub mod recharge_tier;
 pub mod register;
 pub mod login;
 pub mod statistic;
+pub mod export;
+pub mod retention;
 pub mod merchant;
+pub mod schedule;
+pub mod shift;
 
+use axum::http::StatusCode;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
@@ -46,3 +62,18 @@ pub struct Search{
 
     filter_gender:Option<String>,
 }
+
+//事务中的业务错误，返回后事务回滚
+pub struct TransactionError(pub StatusCode,pub String);
+
+impl From<diesel::result::Error> for TransactionError{
+    fn from(e: diesel::result::Error) -> Self {
+        TransactionError(StatusCode::INTERNAL_SERVER_ERROR,e.to_string())
+    }
+}
+
+impl From<TransactionError> for (StatusCode,String){
+    fn from(e: TransactionError) -> Self {
+        (e.0,e.1)
+    }
+}
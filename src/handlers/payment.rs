@@ -0,0 +1,499 @@
+use axum::{body::Bytes, http::{HeaderMap, StatusCode}, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, charge_status, order_status},
+    payment_provider::{self, CallbackEvent, ChargeRequest, RefundRequest}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, balance_ledger::current_barber_id};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChargeResponse{
+    #[serde(flatten)]
+    pub charge:PaymentCharge,
+
+    pub refunds:Vec<PaymentRefund>,
+}
+
+fn load_charge_response(conn:&mut PgConnection,merchant_id:Uuid,charge_id:Uuid)->QueryResult<PaymentChargeResponse>{
+    let charge=payment_charges::table
+        .filter(payment_charges::merchant_id.eq(merchant_id))
+        .filter(payment_charges::charge_id.eq(charge_id))
+        .get_result::<PaymentCharge>(conn)?;
+    let refunds=payment_refunds::table
+        .filter(payment_refunds::charge_id.eq(charge_id))
+        .order(payment_refunds::id.asc())
+        .get_results::<PaymentRefund>(conn)?;
+
+    Ok(PaymentChargeResponse{charge,refunds})
+}
+
+// 收款须已成功、未退款、未被其他订单使用，且支付方式和金额一致
+fn check_usable(charge:&PaymentCharge,tender:&str,amount:&BigDecimal)->Result<(),TransactionError>{
+    if charge.status!=charge_status::SUCCEEDED || !charge.refunded_amount.is_zero() {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"该笔收款未成功或已退款".to_string()));
+    }
+    if charge.order_id.is_some() {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"该笔收款已用于其他订单".to_string()));
+    }
+    if charge.tender!=tender || &charge.amount!=amount {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"收款的支付方式或金额与订单不一致".to_string()));
+    }
+    Ok(())
+}
+
+// 订单使用渠道收款支付
+pub fn use_charge(conn:&mut PgConnection,merchant_id:Uuid,charge_id:Uuid,tender:&str,amount:&BigDecimal,order_id:Uuid)->Result<(),TransactionError>{
+    let charge=payment_charges::table
+        .filter(payment_charges::merchant_id.eq(merchant_id))
+        .filter(payment_charges::charge_id.eq(charge_id))
+        .for_update()
+        .get_result::<PaymentCharge>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,"收款记录不存在".to_string()))?;
+
+    check_usable(&charge, tender, amount)?;
+
+    diesel::update(
+        payment_charges::table
+        .filter(payment_charges::charge_id.eq(charge_id))
+    )
+    .set((
+        payment_charges::order_id.eq(order_id),
+        payment_charges::update_time.eq(Local::now())
+    ))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+// 校验回调，返回是否需要更新收款；只更新处理中的收款，重复回调直接忽略
+fn check_callback(charge:&PaymentCharge,event:&CallbackEvent)->Result<bool,TransactionError>{
+    if event.amount!=charge.amount {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"回调金额与收款金额不一致".to_string()));
+    }
+    if event.status!=charge_status::SUCCEEDED && event.status!=charge_status::FAILED {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,format!("不支持的收款状态 {}",event.status)));
+    }
+    if charge.status!=charge_status::PENDING {
+        if charge.status!=event.status {
+            tracing::warn!("payment callback status {} conflicts with charge {} status {}",event.status,charge.charge_id,charge.status);
+        }
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// 处理渠道回调
+fn apply_callback(conn:&mut PgConnection,provider:&str,event:&CallbackEvent)->Result<(),TransactionError>{
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let charge=payment_charges::table
+            .filter(payment_charges::charge_id.eq(event.charge_id))
+            .filter(payment_charges::provider.eq(provider))
+            .for_update()
+            .get_result::<PaymentCharge>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"收款记录不存在".to_string()))?;
+
+        if !check_callback(&charge, event)? {
+            return Ok(());
+        }
+
+        diesel::update(
+            payment_charges::table
+            .filter(payment_charges::charge_id.eq(event.charge_id))
+        )
+        .set((
+            payment_charges::provider_charge_id.eq(&event.provider_charge_id),
+            payment_charges::status.eq(&event.status),
+            payment_charges::failure_reason.eq(&event.failure_reason),
+            payment_charges::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChargeRequest{
+    pub provider:String,
+
+    pub tender:String, // card/wechat/alipay
+
+    pub amount:BigDecimal,
+
+    pub auth_code:Option<String>, // 顾客出示的付款码
+}
+
+// 通过支付渠道发起收款，立即返回的结果为处理中时等待渠道回调
+pub async fn add_payment_charge(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<PaymentChargeRequest>
+)->Result<Json<PaymentChargeResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let provider=payment_provider::provider(&req.provider)
+        .ok_or_else(||(StatusCode::BAD_REQUEST,format!("不支持的支付渠道 {}",req.provider)))?;
+    if !provider.supports(&req.tender) {
+        return Err((StatusCode::BAD_REQUEST,format!("支付渠道 {} 不支持支付方式 {}",req.provider,req.tender)));
+    }
+    if req.amount<=BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"收款金额必须大于0".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    // 先登记收款再请求渠道，渠道回调可能早于请求返回
+    let charge_id=Uuid::new_v4();
+    let new_charge=NewPaymentCharge{
+        charge_id:&charge_id,
+        merchant_id:&merchant_id,
+        provider:provider.name(),
+        tender:&req.tender,
+        amount:&req.amount,
+        refunded_amount:&BigDecimal::zero(),
+        status:charge_status::PENDING,
+        barber_id:operator_id.as_ref(),
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    diesel::insert_into(payment_charges::table)
+        .values(&new_charge)
+        .execute(&mut *conn)
+        .unwrap();
+
+    let result=provider.create_charge(ChargeRequest{
+        charge_id,
+        tender:&req.tender,
+        amount:&req.amount,
+        auth_code:req.auth_code.as_deref(),
+        notify_url:payment_provider::notify_url(provider.name()),
+    }).await;
+    let (provider_charge_id,status,failure_reason)=match result {
+        Ok(c)=>(Some(c.provider_charge_id),c.status,c.failure_reason),
+        Err(e)=>(None,charge_status::FAILED.to_string(),Some(e.to_string())),
+    };
+    diesel::update(
+        payment_charges::table
+        .filter(payment_charges::charge_id.eq(charge_id))
+        .filter(payment_charges::status.eq(charge_status::PENDING))
+    )
+    .set((
+        payment_charges::provider_charge_id.eq(provider_charge_id),
+        payment_charges::status.eq(status),
+        payment_charges::failure_reason.eq(failure_reason),
+        payment_charges::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+
+    Ok(Json(load_charge_response(&mut conn, merchant_id, charge_id).unwrap()))
+}
+
+pub async fn get_payment_charge(
+    State(pg):State<AxumPg>,
+    Path(charge_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<PaymentChargeResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let charge=load_charge_response(&mut conn, merchant_id, charge_id)
+        .map_err(|_|(StatusCode::NOT_FOUND,"收款记录不存在".to_string()))?;
+
+    Ok(Json(charge))
+}
+
+// 已登记、待提交渠道的退款
+pub struct PendingRefund{
+    refund_id:Uuid,
+    charge:PaymentCharge,
+    amount:BigDecimal,
+    reason:String,
+}
+
+// 检查收款的可退金额
+fn check_refundable(charge:&PaymentCharge,amount:&BigDecimal)->Result<(),TransactionError>{
+    if charge.status!=charge_status::SUCCEEDED {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"只有成功的收款可以退款".to_string()));
+    }
+    if &charge.refunded_amount+amount>charge.amount {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,format!("可退金额不足，剩余 {}",&charge.amount-&charge.refunded_amount)));
+    }
+    Ok(())
+}
+
+// 请求渠道前先登记退款并占用可退金额，避免并发退款超出收款金额，调用前须锁定收款
+fn reserve_refund(conn:&mut PgConnection,merchant_id:Uuid,charge:PaymentCharge,amount:BigDecimal,reason:&str,barber_id:Option<Uuid>)->Result<PendingRefund,TransactionError>{
+    check_refundable(&charge, &amount)?;
+
+    diesel::update(
+        payment_charges::table
+        .filter(payment_charges::charge_id.eq(charge.charge_id))
+    )
+    .set((
+        payment_charges::refunded_amount.eq(payment_charges::refunded_amount+&amount),
+        payment_charges::update_time.eq(Local::now())
+    ))
+    .execute(conn)?;
+
+    let refund_id=Uuid::new_v4();
+    let new_refund=NewPaymentRefund{
+        refund_id:&refund_id,
+        charge_id:&charge.charge_id,
+        merchant_id:&merchant_id,
+        amount:&amount,
+        reason,
+        status:charge_status::PENDING,
+        barber_id:barber_id.as_ref(),
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    diesel::insert_into(payment_refunds::table)
+        .values(&new_refund)
+        .execute(conn)?;
+
+    Ok(PendingRefund{refund_id,charge,amount,reason:reason.to_string()})
+}
+
+// 订单取消或退款时，登记原路退回该订单使用的渠道收款，事务提交后再逐笔提交渠道
+pub fn reserve_order_refunds(conn:&mut PgConnection,merchant_id:Uuid,order_id:Uuid,barber_id:Option<Uuid>,reason:&str)->Result<Vec<PendingRefund>,TransactionError>{
+    let charges=payment_charges::table
+        .filter(payment_charges::merchant_id.eq(merchant_id))
+        .filter(payment_charges::order_id.eq(order_id))
+        .order(payment_charges::id.asc())
+        .for_update()
+        .get_results::<PaymentCharge>(conn)?;
+
+    let mut refunds=Vec::new();
+    for charge in charges {
+        let amount=&charge.amount-&charge.refunded_amount;
+        if amount>BigDecimal::zero() {
+            refunds.push(reserve_refund(conn, merchant_id, charge, amount, reason, barber_id)?);
+        }
+    }
+    Ok(refunds)
+}
+
+// 请求渠道退款并记录结果，失败时释放占用的金额，可再次发起退款
+pub async fn submit_refund(conn:&mut PgConnection,refund:&PendingRefund)->Result<(),TransactionError>{
+    let result=match payment_provider::provider(&refund.charge.provider) {
+        Some(provider)=>provider.refund(RefundRequest{
+            refund_id:refund.refund_id,
+            provider_charge_id:refund.charge.provider_charge_id.as_deref().unwrap_or_default(),
+            amount:&refund.amount,
+            reason:&refund.reason,
+        }).await,
+        None=>Err(anyhow::anyhow!("不支持的支付渠道 {}",refund.charge.provider)),
+    };
+    let (provider_refund_id,status,failure_reason)=match result {
+        Ok(r)=>(Some(r.provider_refund_id),r.status,r.failure_reason),
+        Err(e)=>(None,charge_status::FAILED.to_string(),Some(e.to_string())),
+    };
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        diesel::update(
+            payment_refunds::table
+            .filter(payment_refunds::refund_id.eq(refund.refund_id))
+        )
+        .set((
+            payment_refunds::provider_refund_id.eq(provider_refund_id),
+            payment_refunds::status.eq(&status),
+            payment_refunds::failure_reason.eq(failure_reason),
+            payment_refunds::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        if status==charge_status::FAILED {
+            diesel::update(
+                payment_charges::table
+                .filter(payment_charges::charge_id.eq(refund.charge.charge_id))
+            )
+            .set((
+                payment_charges::refunded_amount.eq(payment_charges::refunded_amount-&refund.amount),
+                payment_charges::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRefundRequest{
+    pub amount:BigDecimal,
+
+    pub reason:String,
+}
+
+// 原路退回渠道收款，可部分退款
+pub async fn refund_payment_charge(
+    State(pg):State<AxumPg>,
+    Path(charge_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<PaymentRefundRequest>
+)->Result<Json<PaymentChargeResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.amount<=BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"退款金额必须大于0".to_string()));
+    }
+    if req.reason.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST,"退款原因不能为空".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let refund=conn.transaction::<_,TransactionError,_>(|conn|{
+        let charge=payment_charges::table
+            .filter(payment_charges::merchant_id.eq(merchant_id))
+            .filter(payment_charges::charge_id.eq(charge_id))
+            .for_update()
+            .get_result::<PaymentCharge>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"收款记录不存在".to_string()))?;
+
+        // 已用于订单的收款随订单取消或退款原路退回，订单仍有效时不能单独退款
+        if let Some(order_id)=charge.order_id {
+            let settled=select(exists(
+                orders::table
+                .filter(orders::merchant_id.eq(merchant_id))
+                .filter(
+                    orders::order_id.eq(order_id).and(orders::status.eq(order_status::CANCELLED))
+                    .or(orders::reversal_of.eq(order_id))
+                )
+                ))
+                .get_result::<bool>(conn)?;
+            if !settled {
+                return Err(TransactionError(StatusCode::BAD_REQUEST,"该笔收款已用于订单，请取消预约或为订单办理退款".to_string()));
+            }
+        }
+
+        reserve_refund(conn, merchant_id, charge, req.amount.clone(), &req.reason, operator_id)
+    })?;
+
+    submit_refund(&mut conn, &refund).await?;
+
+    Ok(Json(load_charge_response(&mut conn, merchant_id, charge_id).unwrap()))
+}
+
+// 支付渠道异步回调，无需登录，由渠道校验签名
+pub async fn payment_webhook(
+    State(pg):State<AxumPg>,
+    Path(provider_name):Path<String>,
+    headers:HeaderMap,
+    body:Bytes,
+)->Result<&'static str,(StatusCode,String)>{
+    let provider=payment_provider::provider(&provider_name)
+        .ok_or_else(||(StatusCode::NOT_FOUND,format!("不支持的支付渠道 {provider_name}")))?;
+    let event=provider.verify_callback(&headers, &body)
+        .map_err(|e|(StatusCode::BAD_REQUEST,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+    apply_callback(&mut conn, provider.name(), &event)?;
+
+    Ok("success")
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn charge(status:&str,amount:i32,refunded_amount:i32,order_id:Option<Uuid>)->PaymentCharge{
+        PaymentCharge{
+            id:1,
+            charge_id:Uuid::new_v4(),
+            merchant_id:Uuid::new_v4(),
+            provider:"mock".into(),
+            provider_charge_id:Some("mock_1".into()),
+            tender:"wechat".into(),
+            amount:amount.into(),
+            refunded_amount:refunded_amount.into(),
+            status:status.into(),
+            failure_reason:None,
+            order_id,
+            barber_id:None,
+            create_time:Local::now(),
+            update_time:Local::now(),
+            data:None,
+        }
+    }
+
+    fn event(charge:&PaymentCharge,status:&str,amount:i32)->CallbackEvent{
+        CallbackEvent{
+            charge_id:charge.charge_id,
+            provider_charge_id:"mock_1".into(),
+            status:status.into(),
+            amount:amount.into(),
+            failure_reason:None,
+        }
+    }
+
+    #[test]
+    fn test_check_callback(){
+        let pending=charge(charge_status::PENDING, 30, 0, None);
+        assert!(matches!(check_callback(&pending, &event(&pending, charge_status::SUCCEEDED, 30)), Ok(true)));
+        assert!(matches!(check_callback(&pending, &event(&pending, charge_status::FAILED, 30)), Ok(true)));
+        assert!(check_callback(&pending, &event(&pending, charge_status::SUCCEEDED, 40)).is_err());
+        assert!(check_callback(&pending, &event(&pending, charge_status::PENDING, 30)).is_err());
+
+        // 已确认的收款不再被回调修改
+        let succeeded=charge(charge_status::SUCCEEDED, 30, 0, None);
+        assert!(matches!(check_callback(&succeeded, &event(&succeeded, charge_status::SUCCEEDED, 30)), Ok(false)));
+        assert!(matches!(check_callback(&succeeded, &event(&succeeded, charge_status::FAILED, 30)), Ok(false)));
+    }
+
+    #[test]
+    fn test_check_usable(){
+        assert!(check_usable(&charge(charge_status::SUCCEEDED, 30, 0, None), "wechat", &30.into()).is_ok());
+        assert!(check_usable(&charge(charge_status::PENDING, 30, 0, None), "wechat", &30.into()).is_err());
+        assert!(check_usable(&charge(charge_status::SUCCEEDED, 30, 10, None), "wechat", &30.into()).is_err());
+        assert!(check_usable(&charge(charge_status::SUCCEEDED, 30, 0, Some(Uuid::new_v4())), "wechat", &30.into()).is_err());
+        assert!(check_usable(&charge(charge_status::SUCCEEDED, 30, 0, None), "alipay", &30.into()).is_err());
+        assert!(check_usable(&charge(charge_status::SUCCEEDED, 30, 0, None), "wechat", &20.into()).is_err());
+    }
+
+    #[test]
+    fn test_check_refundable(){
+        assert!(check_refundable(&charge(charge_status::SUCCEEDED, 30, 0, None), &30.into()).is_ok());
+        assert!(check_refundable(&charge(charge_status::SUCCEEDED, 30, 10, None), &20.into()).is_ok());
+        // 已占用的金额计入已退金额
+        assert!(check_refundable(&charge(charge_status::SUCCEEDED, 30, 10, None), &21.into()).is_err());
+        assert!(check_refundable(&charge(charge_status::FAILED, 30, 0, None), &30.into()).is_err());
+    }
+}
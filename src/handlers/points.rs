@@ -0,0 +1,498 @@
+use axum::{http::StatusCode, Json, extract::{Query, Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero, ToPrimitive};
+use chrono::{Local, Duration};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, ledger_type, payment_type, points_type},
+    my_date_format,
+    my_option_date_format
+};
+use diesel::prelude::*;
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{PaginatedListRequest, PaginatedListResponse, TransactionError, balance_ledger::{change_balance, current_barber_id}};
+
+// 消费金额可获得的积分，不足 1 分的部分舍去
+pub fn points_earned(amount:&BigDecimal,earn_rate:&BigDecimal)->i32{
+    (amount*earn_rate).with_scale(0).to_i32().unwrap_or(0).max(0)
+}
+
+// 抵扣金额所需的积分，金额必须是每积分抵扣金额的整数倍
+pub fn points_for_amount(amount:&BigDecimal,redeem_rate:&BigDecimal)->Option<i32>{
+    if *redeem_rate<=BigDecimal::zero() {
+        return None;
+    }
+    let points=amount/redeem_rate;
+    if !points.is_integer() {
+        return None;
+    }
+    points.to_i32()
+}
+
+// 积分可抵扣的金额
+pub fn points_value(points:i32,redeem_rate:&BigDecimal)->BigDecimal{
+    BigDecimal::from(points)*redeem_rate
+}
+
+pub fn load_points_setting(conn:&mut PgConnection,merchant_id:Uuid)->QueryResult<Option<PointsSetting>>{
+    merchant_points_settings::table
+        .filter(merchant_points_settings::enabled.eq(true))
+        .filter(merchant_points_settings::merchant_id.eq(merchant_id))
+        .get_result::<PointsSetting>(conn)
+        .optional()
+}
+
+// 更新会员积分并返回变更后的积分，扣减时积分不足则返回错误
+fn update_member_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,points:i32)->Result<i32,TransactionError>{
+    let mut query=diesel::update(merchant_members::table)
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::enabled.eq(true))
+        .into_boxed();
+    if points<0 {
+        query=query.filter(merchant_members::points.ge(-points));
+    }
+
+    query
+        .set((
+            merchant_members::points.eq(merchant_members::points + points),
+            merchant_members::update_time.eq(Local::now())
+        ))
+        .returning(merchant_members::points)
+        .get_result::<i32>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,if points<0 {"会员积分不足".to_string()} else {"会员不存在".to_string()}))
+}
+
+// 按过期时间先后扣减各笔获得积分中未使用的部分
+fn consume_earned_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,points:i32)->QueryResult<()>{
+    let entries=member_points_ledgers::table
+        .filter(member_points_ledgers::merchant_id.eq(merchant_id))
+        .filter(member_points_ledgers::member_id.eq(member_id))
+        .filter(member_points_ledgers::remaining.gt(0))
+        .order((member_points_ledgers::expire_time.asc().nulls_last(),member_points_ledgers::id.asc()))
+        .select((member_points_ledgers::id,member_points_ledgers::remaining))
+        .for_update()
+        .get_results::<(i64,i32)>(conn)?;
+
+    let mut rest=points;
+    for (id,remaining) in entries {
+        if rest<=0 {
+            break;
+        }
+        let used=remaining.min(rest);
+        diesel::update(member_points_ledgers::table.filter(member_points_ledgers::id.eq(id)))
+            .set(member_points_ledgers::remaining.eq(remaining-used))
+            .execute(conn)?;
+        rest-=used;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_points_ledger(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    member_id:Uuid,
+    entry_type:&str,
+    points:i32,
+    balance:i32,
+    expire_time:Option<chrono::DateTime<Local>>,
+    reference_id:Option<Uuid>,
+    barber_id:Option<Uuid>,
+    remark:Option<&str>,
+)->QueryResult<()>{
+    let new_ledger=NewMemberPointsLedger{
+        ledger_id:&Uuid::new_v4(),
+        merchant_id:&merchant_id,
+        member_id:&member_id,
+        entry_type,
+        points,
+        balance,
+        remaining:points.max(0),
+        expire_time,
+        reference_id:reference_id.as_ref(),
+        barber_id:barber_id.as_ref(),
+        remark,
+        create_time: Local::now(),
+        data: None,
+    };
+    diesel::insert_into(member_points_ledgers::table)
+        .values(&new_ledger)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// 将已过期且未使用的积分清零并记录过期流水，需在事务中调用
+pub fn expire_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid)->Result<(),TransactionError>{
+    let expired=member_points_ledgers::table
+        .filter(member_points_ledgers::merchant_id.eq(merchant_id))
+        .filter(member_points_ledgers::member_id.eq(member_id))
+        .filter(member_points_ledgers::remaining.gt(0))
+        .filter(member_points_ledgers::expire_time.le(Local::now()))
+        .select((member_points_ledgers::id,member_points_ledgers::remaining))
+        .for_update()
+        .get_results::<(i64,i32)>(conn)?;
+    let points=expired.iter().map(|e|e.1).sum::<i32>();
+    if points==0 {
+        return Ok(());
+    }
+
+    diesel::update(member_points_ledgers::table.filter(member_points_ledgers::id.eq_any(expired.iter().map(|e|e.0))))
+        .set(member_points_ledgers::remaining.eq(0))
+        .execute(conn)?;
+    let balance=update_member_points(conn, merchant_id, member_id, -points)?;
+    insert_points_ledger(conn, merchant_id, member_id, points_type::EXPIRE, -points, balance, None, None, None, None)?;
+
+    Ok(())
+}
+
+// 变更会员积分并记录流水，需在事务中调用；增加的积分按商户设置的有效天数过期
+#[allow(clippy::too_many_arguments)]
+pub fn change_points(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    member_id:Uuid,
+    points:i32,
+    entry_type:&str,
+    reference_id:Option<Uuid>,
+    barber_id:Option<Uuid>,
+    remark:Option<&str>,
+)->Result<i32,TransactionError>{
+    expire_points(conn, merchant_id, member_id)?;
+
+    let balance=update_member_points(conn, merchant_id, member_id, points)?;
+    let expire_time=if points>0 {
+        load_points_setting(conn, merchant_id)?
+            .and_then(|s|s.valid_days)
+            .map(|days|Local::now()+Duration::days(days as i64))
+    } else {
+        consume_earned_points(conn, merchant_id, member_id, -points)?;
+        None
+    };
+    insert_points_ledger(conn, merchant_id, member_id, entry_type, points, balance, expire_time, reference_id, barber_id, remark)?;
+
+    Ok(balance)
+}
+
+// 订单完成后按实付金额发放积分，积分抵扣的部分不再计积分
+pub fn award_order_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,order_id:Uuid,barber_id:Option<Uuid>)->Result<i32,TransactionError>{
+    let Some(setting)=load_points_setting(conn, merchant_id)? else {
+        return Ok(0);
+    };
+
+    let amount=order_payments::table
+        .filter(order_payments::enabled.eq(true))
+        .filter(order_payments::order_id.eq(order_id))
+        .filter(order_payments::tender.ne(payment_type::POINTS))
+        .select(diesel::dsl::sum(order_payments::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_else(BigDecimal::zero);
+    let points=points_earned(&amount, &setting.earn_rate);
+    if points>0 {
+        change_points(conn, merchant_id, member_id, points, points_type::EARN, Some(order_id), barber_id, None)?;
+    }
+
+    Ok(points)
+}
+
+fn sum_order_points(conn:&mut PgConnection,order_id:Uuid,entry_type:&str)->QueryResult<i32>{
+    Ok(member_points_ledgers::table
+        .filter(member_points_ledgers::reference_id.eq(order_id))
+        .filter(member_points_ledgers::entry_type.eq(entry_type))
+        .select(diesel::dsl::sum(member_points_ledgers::points))
+        .get_result::<Option<i64>>(conn)?
+        .unwrap_or(0) as i32)
+}
+
+// 退回订单抵扣使用的积分，reference_id 为取消的订单或退款记录
+pub fn refund_order_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,order_id:Uuid,reference_id:Uuid,barber_id:Option<Uuid>,reason:&str)->Result<(),TransactionError>{
+    let redeemed=-sum_order_points(conn, order_id, points_type::REDEEM)?;
+    if redeemed>0 {
+        change_points(conn, merchant_id, member_id, redeemed, points_type::REFUND, Some(reference_id), barber_id, Some(reason))?;
+    }
+    Ok(())
+}
+
+// 订单退款时扣回该订单获得的积分中未使用的部分，已使用或已过期的积分不再追回
+pub fn reverse_order_points(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,order_id:Uuid,reversal_id:Uuid,barber_id:Option<Uuid>,reason:&str)->Result<(),TransactionError>{
+    expire_points(conn, merchant_id, member_id)?;
+
+    let earned=member_points_ledgers::table
+        .filter(member_points_ledgers::merchant_id.eq(merchant_id))
+        .filter(member_points_ledgers::member_id.eq(member_id))
+        .filter(member_points_ledgers::reference_id.eq(order_id))
+        .filter(member_points_ledgers::entry_type.eq(points_type::EARN))
+        .filter(member_points_ledgers::remaining.gt(0))
+        .select((member_points_ledgers::id,member_points_ledgers::remaining))
+        .for_update()
+        .get_results::<(i64,i32)>(conn)?;
+    let points=earned.iter().map(|e|e.1).sum::<i32>();
+    if points==0 {
+        return Ok(());
+    }
+
+    diesel::update(member_points_ledgers::table.filter(member_points_ledgers::id.eq_any(earned.iter().map(|e|e.0))))
+        .set(member_points_ledgers::remaining.eq(0))
+        .execute(conn)?;
+    let balance=update_member_points(conn, merchant_id, member_id, -points)?;
+    insert_points_ledger(conn, merchant_id, member_id, points_type::EARN_REVERSAL, -points, balance, None, Some(reversal_id), barber_id, Some(reason))?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointsSettingRequest{
+    pub earn_rate:BigDecimal,
+
+    pub redeem_rate:BigDecimal,
+
+    pub valid_days:Option<i32>,
+}
+
+pub async fn get_points_setting(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Option<PointsSetting>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let setting=load_points_setting(&mut conn, merchant_id)
+        .unwrap();
+
+    Ok(Json(setting))
+}
+
+pub async fn update_points_setting(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<PointsSettingRequest>
+)->Result<Json<PointsSetting>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.earn_rate<BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"积分比例不能为负数".to_string()));
+    }
+    if req.redeem_rate<=BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"积分抵扣金额必须大于0".to_string()));
+    }
+    if req.valid_days.map(|n|n<=0).unwrap_or(false) {
+        return Err((StatusCode::BAD_REQUEST,"有效天数必须大于0".to_string()));
+    }
+
+    let new_setting=NewPointsSetting{
+        merchant_id:&merchant_id,
+        earn_rate:&req.earn_rate,
+        redeem_rate:&req.redeem_rate,
+        valid_days:req.valid_days,
+        enabled:true,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    // 调整有效天数只影响之后获得的积分
+    let setting=diesel::insert_into(merchant_points_settings::table)
+        .values(&new_setting)
+        .on_conflict(merchant_points_settings::merchant_id)
+        .do_update()
+        .set((
+            merchant_points_settings::earn_rate.eq(&req.earn_rate),
+            merchant_points_settings::redeem_rate.eq(&req.redeem_rate),
+            merchant_points_settings::valid_days.eq(req.valid_days),
+            merchant_points_settings::enabled.eq(true),
+            merchant_points_settings::update_time.eq(Local::now())
+        ))
+        .get_result::<PointsSetting>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(setting))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointsLedgerResponse{
+    #[serde(rename="id")]
+    pub ledger_id:Uuid,
+
+    pub entry_type:String,
+
+    pub entry_type_name:String,
+
+    pub points:i32,
+
+    pub balance:i32,
+
+    pub remaining:i32,
+
+    #[serde(with = "my_option_date_format")]
+    pub expire_time:Option<chrono::DateTime<Local>>,
+
+    pub reference_id:Option<Uuid>,
+
+    pub barber_name:String,
+
+    pub remark:Option<String>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time:chrono::DateTime<Local>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberPointsResponse{
+    pub points:i32,
+
+    pub ledgers:PaginatedListResponse<PointsLedgerResponse>,
+}
+
+pub async fn get_points_by_member_id(
+    State(pg):State<AxumPg>,
+    Query(params):Query<PaginatedListRequest>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<MemberPointsResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    // 先处理已过期的积分，再返回当前积分
+    let points=conn.transaction::<_,TransactionError,_>(|conn|{
+        expire_points(conn, merchant_id, member_id)?;
+
+        merchant_members::table
+            .filter(merchant_members::enabled.eq(true))
+            .filter(merchant_members::merchant_id.eq(merchant_id))
+            .filter(merchant_members::member_id.eq(member_id))
+            .select(merchant_members::points)
+            .get_result::<i32>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"会员不存在".to_string()))
+    })?;
+
+    let fn_get_query=||{
+        member_points_ledgers::table
+            .left_join(barbers::table.on(member_points_ledgers::barber_id.eq(barbers::barber_id.nullable())))
+            .filter(member_points_ledgers::merchant_id.eq(merchant_id))
+            .filter(member_points_ledgers::member_id.eq(member_id))
+            .into_boxed()
+    };
+
+    let count=fn_get_query()
+        .count()
+        .get_result(&mut *conn)
+        .unwrap();
+    let data=fn_get_query()
+        .order(member_points_ledgers::id.desc())
+        .limit(params.page_size)
+        .offset(params.page_index*params.page_size)
+        .get_results::<(MemberPointsLedger,Option<Barber>)>(&mut *conn)
+        .map(|v|v.into_iter().map(|t|PointsLedgerResponse{
+            ledger_id:t.0.ledger_id,
+            entry_type_name:points_type::display_name(&t.0.entry_type).into(),
+            entry_type:t.0.entry_type,
+            points:t.0.points,
+            balance:t.0.balance,
+            remaining:t.0.remaining,
+            expire_time:t.0.expire_time,
+            reference_id:t.0.reference_id,
+            barber_name:t.1.map(|b|if b.enabled {b.real_name} else {"-".into()}).unwrap_or_default(),
+            remark:t.0.remark,
+            create_time:t.0.create_time,
+        }).collect())
+        .unwrap();
+
+    Ok(Json(MemberPointsResponse{
+        points,
+        ledgers:PaginatedListResponse{
+            page_index:params.page_index,
+            page_size:params.page_size,
+            total_count:count,
+            data,
+        },
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct PointsConversionRequest{
+    points:i32,
+}
+
+// 积分按抵扣比例兑换为会员余额
+pub async fn convert_points(
+    State(pg):State<AxumPg>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<PointsConversionRequest>
+)->Result<Json<MerchantMember>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.points<=0 {
+        return Err((StatusCode::BAD_REQUEST,"兑换积分必须大于0".to_string()));
+    }
+    let setting=load_points_setting(&mut conn, merchant_id)
+        .unwrap()
+        .ok_or((StatusCode::BAD_REQUEST,"商户未开启积分".to_string()))?;
+    let amount=points_value(req.points, &setting.redeem_rate);
+
+    let barber_id=current_barber_id(&mut conn, merchant_id, auth.identity.unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        change_points(conn, merchant_id, member_id, -req.points, points_type::CONVERSION, None, barber_id, None)?;
+        change_balance(conn, merchant_id, member_id, &amount, ledger_type::POINTS_CONVERSION, None, barber_id, Some(&format!("{} 积分",req.points)))?;
+
+        Ok(())
+    })?;
+
+    let member=merchant_members::table
+        .filter(merchant_members::enabled.eq(true))
+        .filter(merchant_members::member_id.eq(member_id))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .get_result::<MerchantMember>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(member))
+}
+
+#[cfg(test)]
+mod test{
+    use std::str::FromStr;
+    use bigdecimal::BigDecimal;
+    use super::{points_earned, points_for_amount};
+
+    #[test]
+    fn test_points_rate(){
+        let rate=BigDecimal::from_str("1.5").unwrap();
+        assert_eq!(points_earned(&BigDecimal::from_str("99.9").unwrap(), &rate),149);
+
+        let redeem_rate=BigDecimal::from_str("0.01").unwrap();
+        assert_eq!(points_for_amount(&BigDecimal::from_str("12.34").unwrap(), &redeem_rate),Some(1234));
+        assert_eq!(points_for_amount(&BigDecimal::from_str("1").unwrap(), &BigDecimal::from_str("0.3").unwrap()),None);
+    }
+}
@@ -0,0 +1,578 @@
+use axum::{http::{header, StatusCode}, extract::{Path, Query, State}, response::{Html, IntoResponse, Response}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Local};
+use encoding_rs::GB18030;
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, order_status, payment_type, receipt_type}
+};
+use diesel::prelude::*;
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, statistic::{load_order_lines, load_order_payments}, tip::load_order_tips};
+
+// 小票内容，各部分之间打印分隔线，每行为左侧文字和右侧金额
+pub struct ReceiptDocument{
+    pub merchant_name:String,
+
+    pub address:Option<String>,
+
+    pub title:String,
+
+    pub receipt_no:String,
+
+    pub sections:Vec<Vec<(String,String)>>,
+}
+
+pub enum ReceiptFormat{
+    Html,
+    Text,
+    EscPos,
+}
+
+#[derive(Deserialize)]
+pub struct ReceiptRequest{
+    pub format:Option<String>, // html / text / escpos，默认 html
+
+    pub width:Option<u32>, // 纸宽 58 / 80 毫米，默认 58
+}
+
+impl ReceiptRequest{
+    fn parse(&self)->Result<(ReceiptFormat,u32),(StatusCode,String)>{
+        let format=match self.format.as_deref().unwrap_or("html") {
+            "html"=>ReceiptFormat::Html,
+            "text"=>ReceiptFormat::Text,
+            "escpos"=>ReceiptFormat::EscPos,
+            f=>return Err((StatusCode::BAD_REQUEST,format!("不支持的小票格式 {f}"))),
+        };
+        let width=self.width.unwrap_or(58);
+        if width!=58 && width!=80 {
+            return Err((StatusCode::BAD_REQUEST,"纸宽只支持 58 或 80 毫米".to_string()));
+        }
+        Ok((format,width))
+    }
+}
+
+// 热敏打印机默认字体每行字符数，中文占两个字符宽度
+pub fn columns_of(width:u32)->usize{
+    if width==80 { 48 } else { 32 }
+}
+
+pub fn format_receipt_no(receipt_no:i64)->String{
+    format!("{receipt_no:08}")
+}
+
+fn format_amount(amount:&BigDecimal)->String{
+    amount.with_scale(2).to_string()
+}
+
+fn format_time(time:&DateTime<Local>)->String{
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn display_width(s:&str)->usize{
+    s.chars().map(|c|if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+fn center(s:&str,columns:usize)->String{
+    let padding=columns.saturating_sub(display_width(s))/2;
+    format!("{}{s}"," ".repeat(padding))
+}
+
+// 左侧文字与右侧金额排在一行，放不下时金额换行右对齐
+pub fn layout_row(left:&str,right:&str,columns:usize)->Vec<String>{
+    let width=display_width(left)+display_width(right);
+    if right.is_empty() {
+        vec![left.to_string()]
+    } else if width<columns {
+        vec![format!("{left}{}{right}"," ".repeat(columns-width))]
+    } else {
+        vec![left.to_string(),format!("{}{right}"," ".repeat(columns.saturating_sub(display_width(right))))]
+    }
+}
+
+fn body_lines(doc:&ReceiptDocument,columns:usize)->Vec<String>{
+    let mut lines=vec![format!("No. {}",doc.receipt_no)];
+    for section in doc.sections.iter() {
+        lines.push("-".repeat(columns));
+        for (left,right) in section.iter() {
+            lines.extend(layout_row(left, right, columns));
+        }
+    }
+    lines.push("-".repeat(columns));
+    lines
+}
+
+pub fn render_text(doc:&ReceiptDocument,columns:usize)->String{
+    let mut lines=vec![center(&doc.merchant_name, columns)];
+    if let Some(address)=doc.address.as_ref() {
+        lines.push(center(address, columns));
+    }
+    lines.push(center(&doc.title, columns));
+    lines.extend(body_lines(doc, columns));
+    lines.push(center("谢谢惠顾", columns));
+    lines.join("\n")+"\n"
+}
+
+// ESC/POS 指令，FS & 进入汉字模式，文字按 GB18030 编码输出
+pub fn render_escpos(doc:&ReceiptDocument,columns:usize)->Vec<u8>{
+    const INIT:&[u8]=&[0x1b,0x40];
+    const CHINESE_MODE:&[u8]=&[0x1c,0x26];
+    const ALIGN_LEFT:&[u8]=&[0x1b,0x61,0x00];
+    const ALIGN_CENTER:&[u8]=&[0x1b,0x61,0x01];
+    const DOUBLE_SIZE:&[u8]=&[0x1d,0x21,0x11];
+    const NORMAL_SIZE:&[u8]=&[0x1d,0x21,0x00];
+    const BOLD_ON:&[u8]=&[0x1b,0x45,0x01];
+    const BOLD_OFF:&[u8]=&[0x1b,0x45,0x00];
+    const FEED_AND_CUT:&[u8]=&[0x1b,0x64,0x04,0x1d,0x56,0x42,0x00];
+
+    fn push_line(bytes:&mut Vec<u8>,line:&str){
+        let (encoded,_,_)=GB18030.encode(line);
+        bytes.extend_from_slice(&encoded);
+        bytes.push(b'\n');
+    }
+
+    let mut bytes=Vec::new();
+
+    bytes.extend_from_slice(INIT);
+    bytes.extend_from_slice(CHINESE_MODE);
+    bytes.extend_from_slice(ALIGN_CENTER);
+    bytes.extend_from_slice(DOUBLE_SIZE);
+    push_line(&mut bytes,&doc.merchant_name);
+    bytes.extend_from_slice(NORMAL_SIZE);
+    if let Some(address)=doc.address.as_ref() {
+        push_line(&mut bytes,address);
+    }
+    bytes.extend_from_slice(BOLD_ON);
+    push_line(&mut bytes,&doc.title);
+    bytes.extend_from_slice(BOLD_OFF);
+    bytes.extend_from_slice(ALIGN_LEFT);
+    for line in body_lines(doc, columns).iter() {
+        push_line(&mut bytes,line);
+    }
+    bytes.extend_from_slice(ALIGN_CENTER);
+    push_line(&mut bytes,"谢谢惠顾");
+    bytes.extend_from_slice(FEED_AND_CUT);
+    bytes
+}
+
+fn escape_html(s:&str)->String{
+    s.replace('&',"&amp;")
+        .replace('<',"&lt;")
+        .replace('>',"&gt;")
+        .replace('"',"&quot;")
+}
+
+pub fn render_html(doc:&ReceiptDocument,width:u32)->String{
+    let mut html=format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title} {no}</title><style>\
+        body{{width:{width}mm;margin:0 auto;font-family:monospace;font-size:12px}}\
+        h1{{font-size:16px;text-align:center;margin:4px 0}}\
+        p{{text-align:center;margin:2px 0}}p.footer{{border-top:1px dashed #000;padding-top:4px}}\
+        table{{width:100%;border-collapse:collapse;border-top:1px dashed #000}}\
+        td{{padding:1px 0;vertical-align:top}}td.amount{{text-align:right;white-space:nowrap}}\
+        </style></head><body><h1>{merchant_name}</h1>",
+        title=escape_html(&doc.title),
+        no=escape_html(&doc.receipt_no),
+        merchant_name=escape_html(&doc.merchant_name),
+    );
+    if let Some(address)=doc.address.as_ref() {
+        html.push_str(&format!("<p>{}</p>",escape_html(address)));
+    }
+    html.push_str(&format!("<p><b>{}</b></p><p>No. {}</p>",escape_html(&doc.title),escape_html(&doc.receipt_no)));
+    for section in doc.sections.iter() {
+        html.push_str("<table>");
+        for (left,right) in section.iter() {
+            html.push_str(&format!("<tr><td>{}</td><td class=\"amount\">{}</td></tr>",escape_html(left),escape_html(right)));
+        }
+        html.push_str("</table>");
+    }
+    html.push_str("<p class=\"footer\">谢谢惠顾</p></body></html>");
+    html
+}
+
+fn render(doc:&ReceiptDocument,format:ReceiptFormat,width:u32)->Response{
+    match format {
+        ReceiptFormat::Html=>Html(render_html(doc, width)).into_response(),
+        ReceiptFormat::Text=>render_text(doc, columns_of(width)).into_response(),
+        ReceiptFormat::EscPos=>(
+            [(header::CONTENT_TYPE,"application/octet-stream")],
+            render_escpos(doc, columns_of(width))
+        ).into_response(),
+    }
+}
+
+// 打印小票时获取编号，首次打印时按商户顺序分配，重复打印沿用原编号并增加打印次数
+fn assign_receipt(conn:&mut PgConnection,merchant_id:Uuid,receipt_type:&str,reference_id:Uuid)->Result<Receipt,TransactionError>{
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        // 锁定商户，保证编号连续不重复
+        merchants::table
+            .filter(merchants::merchant_id.eq(merchant_id))
+            .select(merchants::id)
+            .for_update()
+            .get_result::<i64>(conn)?;
+
+        let existing=diesel::update(
+            receipts::table
+            .filter(receipts::merchant_id.eq(merchant_id))
+            .filter(receipts::receipt_type.eq(receipt_type))
+            .filter(receipts::reference_id.eq(reference_id))
+        )
+        .set((
+            receipts::print_count.eq(receipts::print_count+1),
+            receipts::update_time.eq(Local::now())
+        ))
+        .get_result::<Receipt>(conn)
+        .optional()?;
+        if let Some(receipt)=existing {
+            return Ok(receipt);
+        }
+
+        let last_no=receipts::table
+            .filter(receipts::merchant_id.eq(merchant_id))
+            .select(diesel::dsl::max(receipts::receipt_no))
+            .get_result::<Option<i64>>(conn)?;
+        let new_receipt=NewReceipt{
+            receipt_id:&Uuid::new_v4(),
+            merchant_id:&merchant_id,
+            receipt_no:last_no.unwrap_or(0)+1,
+            receipt_type,
+            reference_id:&reference_id,
+            print_count:1,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let receipt=diesel::insert_into(receipts::table)
+            .values(&new_receipt)
+            .get_result::<Receipt>(conn)?;
+
+        Ok(receipt)
+    })
+}
+
+// 打印时分配编号，预览时只读取已分配的编号
+fn load_receipt(conn:&mut PgConnection,merchant_id:Uuid,receipt_type:&str,reference_id:Uuid,print:bool)->Result<Option<Receipt>,TransactionError>{
+    if print {
+        return assign_receipt(conn, merchant_id, receipt_type, reference_id).map(Some);
+    }
+
+    Ok(receipts::table
+        .filter(receipts::merchant_id.eq(merchant_id))
+        .filter(receipts::receipt_type.eq(receipt_type))
+        .filter(receipts::reference_id.eq(reference_id))
+        .get_result::<Receipt>(conn)
+        .optional()?)
+}
+
+fn receipt_no_of(receipt:Option<Receipt>)->String{
+    receipt.map(|r|format_receipt_no(r.receipt_no)).unwrap_or_else(||"-".into())
+}
+
+// 该笔交易后的会员余额：取关联该交易的最后一条余额流水，未使用余额的交易取交易时间之前的最后一条流水
+fn balance_after(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid,reference_id:Uuid,time:DateTime<Local>)->QueryResult<Option<BigDecimal>>{
+    let balance=member_balance_ledgers::table
+        .filter(member_balance_ledgers::merchant_id.eq(merchant_id))
+        .filter(member_balance_ledgers::member_id.eq(member_id))
+        .filter(member_balance_ledgers::reference_id.eq(reference_id))
+        .order(member_balance_ledgers::id.desc())
+        .select(member_balance_ledgers::balance)
+        .first::<BigDecimal>(conn)
+        .optional()?;
+    if balance.is_some() {
+        return Ok(balance);
+    }
+
+    member_balance_ledgers::table
+        .filter(member_balance_ledgers::merchant_id.eq(merchant_id))
+        .filter(member_balance_ledgers::member_id.eq(member_id))
+        .filter(member_balance_ledgers::create_time.le(time))
+        .order(member_balance_ledgers::id.desc())
+        .select(member_balance_ledgers::balance)
+        .first::<BigDecimal>(conn)
+        .optional()
+}
+
+fn load_merchant(conn:&mut PgConnection,merchant_id:Uuid)->QueryResult<Merchant>{
+    merchants::table
+        .filter(merchants::merchant_id.eq(merchant_id))
+        .get_result::<Merchant>(conn)
+}
+
+fn load_member(conn:&mut PgConnection,member_id:Option<Uuid>)->QueryResult<Option<MerchantMember>>{
+    match member_id {
+        Some(member_id)=>merchant_members::table
+            .filter(merchant_members::enabled.eq(true))
+            .filter(merchant_members::member_id.eq(member_id))
+            .get_result::<MerchantMember>(conn)
+            .optional(),
+        None=>Ok(None),
+    }
+}
+
+fn barber_name(conn:&mut PgConnection,barber_id:Uuid)->QueryResult<String>{
+    barbers::table
+        .filter(barbers::barber_id.eq(barber_id))
+        .get_result::<Barber>(conn)
+        .optional()
+        .map(|b|b.filter(|b|b.enabled).map(|b|b.real_name).unwrap_or_else(||"-".into()))
+}
+
+fn order_document(conn:&mut PgConnection,merchant_id:Uuid,order_id:Uuid,print:bool)->Result<ReceiptDocument,TransactionError>{
+    let order=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::order_id.eq(order_id))
+        .get_result::<Order>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"订单不存在".to_string()))?;
+    let receipt=load_receipt(conn, merchant_id, receipt_type::ORDER, order_id, print)?;
+    let merchant=load_merchant(conn, merchant_id)?;
+    let member=load_member(conn, order.member_id)?;
+
+    let mut info=vec![
+        ("时间".to_string(),format_time(&order.start_time)),
+        ("理发师".to_string(),barber_name(conn, order.barber_id)?),
+        ("状态".to_string(),order_status::display_name(&order.status).to_string()),
+    ];
+    if let Some(member)=member.as_ref() {
+        info.push(("会员".to_string(),member.real_name.clone()));
+    }
+    if let Some(reason)=order.reversal_reason.as_ref() {
+        info.push(("退款原因".to_string(),reason.clone()));
+    }
+
+    let mut items=load_order_lines(conn, &[order_id])
+        .remove(&order_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|l|(format!("{} ({})",l.service_name,l.barber_name),format_amount(&l.amount)))
+        .collect::<Vec<_>>();
+    items.push(("合计".to_string(),format_amount(&order.amount)));
+
+    // 早期订单没有支付明细，按订单支付方式显示
+    let mut tenders=load_order_payments(conn, &[order_id])
+        .remove(&order_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p|(p.tender_name,format_amount(&p.amount)))
+        .collect::<Vec<_>>();
+    if tenders.is_empty() {
+        tenders.push((payment_type::display_name(&order.payment_type).to_string(),format_amount(&order.amount)));
+    }
+
+    let mut sections=vec![info,items,tenders];
+    let tips=load_order_tips(conn, &[order_id])
+        .remove(&order_id)
+        .unwrap_or_default();
+    if !tips.is_empty() {
+        sections.push(tips.into_iter()
+            .map(|t|(format!("小费 {} ({})",t.barber_name,t.tender_name),format_amount(&t.amount)))
+            .collect());
+    }
+    if let Some(member)=member.as_ref() {
+        if let Some(balance)=balance_after(conn, merchant_id, member.member_id, order_id, order.create_time)? {
+            sections.push(vec![("会员余额".to_string(),format_amount(&balance))]);
+        }
+    }
+
+    Ok(ReceiptDocument{
+        merchant_name:merchant.merchant_name,
+        address:merchant.address,
+        title:if order.reversal_of.is_some() { "退款小票".into() } else { "消费小票".into() },
+        receipt_no:receipt_no_of(receipt),
+        sections,
+    })
+}
+
+fn recharge_document(conn:&mut PgConnection,merchant_id:Uuid,recharge_record_id:Uuid,print:bool)->Result<ReceiptDocument,TransactionError>{
+    let record=recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::merchant_id.eq(merchant_id))
+        .filter(recharge_records::recharge_record_id.eq(recharge_record_id))
+        .get_result::<RechargeRecord>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"充值记录不存在".to_string()))?;
+    let receipt=load_receipt(conn, merchant_id, receipt_type::RECHARGE, recharge_record_id, print)?;
+    let merchant=load_merchant(conn, merchant_id)?;
+    let member=load_member(conn, Some(record.member_id))?;
+
+    let mut info=vec![
+        ("时间".to_string(),format_time(&record.create_time)),
+        ("操作人".to_string(),barber_name(conn, record.barber_id)?),
+        ("会员".to_string(),member.as_ref().map(|m|m.real_name.clone()).unwrap_or_else(||"-".into())),
+    ];
+    if let Some(reason)=record.reversal_reason.as_ref() {
+        info.push(("撤销原因".to_string(),reason.clone()));
+    }
+
+    let mut items=vec![("充值金额".to_string(),format_amount(&record.amount))];
+    if !record.bonus.is_zero() {
+        items.push(("赠送金额".to_string(),format_amount(&record.bonus)));
+    }
+    items.push(("到账金额".to_string(),format_amount(&(&record.amount+&record.bonus))));
+    let tenders=vec![(payment_type::display_name(&record.tender).to_string(),format_amount(&record.amount))];
+
+    let mut sections=vec![info,items,tenders];
+    if let Some(balance)=balance_after(conn, merchant_id, record.member_id, recharge_record_id, record.create_time)? {
+        sections.push(vec![("会员余额".to_string(),format_amount(&balance))]);
+    }
+
+    Ok(ReceiptDocument{
+        merchant_name:merchant.merchant_name,
+        address:merchant.address,
+        title:if record.reversal_of.is_some() { "充值撤销小票".into() } else { "充值小票".into() },
+        receipt_no:receipt_no_of(receipt),
+        sections,
+    })
+}
+
+fn order_receipt(pg:AxumPg,auth:AuthSession<AxumPg, AxumPg,User>,order_id:Uuid,req:ReceiptRequest,print:bool)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let (format,width)=req.parse()?;
+    let doc=order_document(&mut conn, merchant_id, order_id, print)?;
+
+    Ok(render(&doc, format, width))
+}
+
+// 预览订单小票，不分配编号
+pub async fn get_order_receipt(
+    State(pg):State<AxumPg>,
+    Path(order_id):Path<Uuid>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    order_receipt(pg, auth, order_id, req, false)
+}
+
+// 打印订单小票，分配编号并记录打印次数
+pub async fn print_order_receipt(
+    State(pg):State<AxumPg>,
+    Path(order_id):Path<Uuid>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    order_receipt(pg, auth, order_id, req, true)
+}
+
+fn recharge_receipt(pg:AxumPg,auth:AuthSession<AxumPg, AxumPg,User>,recharge_record_id:Uuid,req:ReceiptRequest,print:bool)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let (format,width)=req.parse()?;
+    let doc=recharge_document(&mut conn, merchant_id, recharge_record_id, print)?;
+
+    Ok(render(&doc, format, width))
+}
+
+// 预览充值小票，不分配编号
+pub async fn get_recharge_receipt(
+    State(pg):State<AxumPg>,
+    Path(recharge_record_id):Path<Uuid>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    recharge_receipt(pg, auth, recharge_record_id, req, false)
+}
+
+// 打印充值小票，分配编号并记录打印次数
+pub async fn print_recharge_receipt(
+    State(pg):State<AxumPg>,
+    Path(recharge_record_id):Path<Uuid>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    recharge_receipt(pg, auth, recharge_record_id, req, true)
+}
+
+fn receipt_by_no(pg:AxumPg,auth:AuthSession<AxumPg, AxumPg,User>,receipt_no:i64,req:ReceiptRequest,print:bool)->Result<Response,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let (format,width)=req.parse()?;
+    let receipt=receipts::table
+        .filter(receipts::merchant_id.eq(merchant_id))
+        .filter(receipts::receipt_no.eq(receipt_no))
+        .get_result::<Receipt>(&mut *conn)
+        .optional()
+        .unwrap()
+        .ok_or_else(||(StatusCode::NOT_FOUND,"小票不存在".to_string()))?;
+
+    let doc=if receipt.receipt_type==receipt_type::RECHARGE {
+        recharge_document(&mut conn, merchant_id, receipt.reference_id, print)?
+    } else {
+        order_document(&mut conn, merchant_id, receipt.reference_id, print)?
+    };
+
+    Ok(render(&doc, format, width))
+}
+
+// 按小票编号查看小票
+pub async fn get_receipt_by_no(
+    State(pg):State<AxumPg>,
+    Path(receipt_no):Path<i64>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    receipt_by_no(pg, auth, receipt_no, req, false)
+}
+
+// 按小票编号补打小票
+pub async fn reprint_receipt(
+    State(pg):State<AxumPg>,
+    Path(receipt_no):Path<i64>,
+    Query(req):Query<ReceiptRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Response,(StatusCode,String)>{
+    receipt_by_no(pg, auth, receipt_no, req, true)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_layout_row(){
+        assert_eq!(layout_row("洗剪吹", "38.00", 16), vec!["洗剪吹     38.00"]);
+        assert_eq!(layout_row("小费 张三 (微信)", "10.00", 16), vec!["小费 张三 (微信)","           10.00"]);
+        assert_eq!(format_receipt_no(42), "00000042");
+    }
+
+    #[test]
+    fn test_render_escpos(){
+        let doc=ReceiptDocument{
+            merchant_name:"美丽".into(),
+            address:None,
+            title:"消费小票".into(),
+            receipt_no:format_receipt_no(1),
+            sections:vec![vec![("合计".into(),"38.00".into())]],
+        };
+        let bytes=render_escpos(&doc, columns_of(58));
+        assert!(bytes.starts_with(&[0x1b,0x40,0x1c,0x26]));
+        assert!(bytes.ends_with(&[0x1d,0x56,0x42,0x00]));
+        // 汉字按 GB18030 双字节编码
+        assert!(bytes.windows(4).any(|w|w==[0xba,0xcf,0xbc,0xc6]));
+        let (text,_,had_errors)=GB18030.decode(&bytes);
+        assert!(!had_errors);
+        assert!(text.contains(&format!("合计{}38.00"," ".repeat(32-4-5))));
+    }
+}
@@ -0,0 +1,191 @@
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Local;
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::{RechargeTier, NewRechargeTier}, authorization_policy, constant
+};
+use diesel::{
+    prelude::*, // for .filter
+    select,
+    dsl::exists,
+};
+use crate::{models::User, axum_pg::AxumPg};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeTierRequest{
+    pub name:String,
+
+    pub threshold:BigDecimal,
+
+    pub bonus:BigDecimal,
+}
+
+impl RechargeTierRequest{
+    fn validate(&self)->Result<(),(StatusCode,String)>{
+        if self.threshold<=BigDecimal::zero() {
+            return Err((StatusCode::BAD_REQUEST,"充值门槛必须大于0".to_string()));
+        }
+        if self.bonus<=BigDecimal::zero() {
+            return Err((StatusCode::BAD_REQUEST,"赠送金额必须大于0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// 充值金额可享受的最高档位
+pub fn find_recharge_tier(conn:&mut PgConnection,merchant_id:Uuid,amount:&BigDecimal)->QueryResult<Option<RechargeTier>>{
+    recharge_tiers::table
+        .filter(recharge_tiers::enabled.eq(true))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))
+        .filter(recharge_tiers::threshold.le(amount))
+        .order(recharge_tiers::threshold.desc())
+        .first::<RechargeTier>(conn)
+        .optional()
+}
+
+pub async fn get_recharge_tiers(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<RechargeTier>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let data=recharge_tiers::table
+        .filter(recharge_tiers::enabled.eq(true))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))
+        .order(recharge_tiers::threshold.asc())
+        .get_results::<RechargeTier>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(data))
+}
+
+pub async fn add_recharge_tier(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<RechargeTierRequest>
+)->Result<Json<RechargeTier>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    req.validate()?;
+
+    let existed=select(exists(recharge_tiers::table
+        .filter(recharge_tiers::enabled.eq(true))
+        .filter(recharge_tiers::threshold.eq(&req.threshold))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"已存在相同充值门槛的档位".to_string()));
+    }
+
+    let new_recharge_tier=NewRechargeTier{
+        recharge_tier_id: &Uuid::new_v4(),
+        merchant_id:&merchant_id,
+        name:&req.name,
+        threshold:&req.threshold,
+        bonus:&req.bonus,
+        enabled:true,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    let recharge_tier=diesel::insert_into(recharge_tiers::table)
+        .values(&new_recharge_tier)
+        .get_result::<RechargeTier>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(recharge_tier))
+}
+
+pub async fn update_recharge_tier(
+    State(pg):State<AxumPg>,
+    Path(recharge_tier_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<RechargeTierRequest>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    req.validate()?;
+
+    let existed=select(exists(recharge_tiers::table
+        .filter(recharge_tiers::enabled.eq(true))
+        .filter(recharge_tiers::threshold.eq(&req.threshold))
+        .filter(recharge_tiers::recharge_tier_id.ne(recharge_tier_id))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if existed {
+        return Err((StatusCode::BAD_REQUEST,"已存在相同充值门槛的档位".to_string()));
+    }
+
+    let count=diesel::update(
+        recharge_tiers::table
+        .filter(recharge_tiers::recharge_tier_id.eq(recharge_tier_id))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))
+        .filter(recharge_tiers::enabled.eq(true))
+    )
+    .set((
+        recharge_tiers::name.eq(req.name),
+        recharge_tiers::threshold.eq(req.threshold),
+        recharge_tiers::bonus.eq(req.bonus),
+        recharge_tiers::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"充值档位不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+pub async fn delete_recharge_tier(
+    State(pg):State<AxumPg>,
+    Path(recharge_tier_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        recharge_tiers::table
+        .filter(recharge_tiers::recharge_tier_id.eq(recharge_tier_id))
+        .filter(recharge_tiers::merchant_id.eq(merchant_id))
+        .filter(recharge_tiers::enabled.eq(true))
+    )
+    .set((
+        recharge_tiers::enabled.eq(false),
+        recharge_tiers::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"充值档位不存在".to_string()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,365 @@
+use std::collections::BTreeMap;
+
+use axum::{Json, http::StatusCode, extract::{State, Query}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, One};
+use chrono::{Datelike, NaiveDate};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Date, Integer, Numeric, Text, Timestamptz, Uuid as SqlUuid},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    models::*,
+    authorization_policy,
+    axum_pg::AxumPg,
+    constant::{self, order_status},
+};
+
+use super::{PaginatedListRequest, PaginatedListResponse, statistic::{TenderSummaryRequest, local_date_range, local_utc_offset, period_starts}};
+
+// 到店记录：已完成的会员预约，同一天多单只算一次到店
+const VISITS_SQL:&str="visits AS ( \
+    SELECT DISTINCT o.member_id, (o.start_time AT TIME ZONE $2::interval)::date AS visit_date \
+    FROM orders o \
+    WHERE o.enabled AND o.merchant_id=$1 AND o.member_id IS NOT NULL AND o.status=$3 \
+)";
+
+fn percentage(count:i64,total:i64)->BigDecimal{
+    if total==0 {
+        return BigDecimal::default();
+    }
+    (BigDecimal::from(count*100)/BigDecimal::from(total)).round(2)
+}
+
+#[derive(QueryableByName)]
+struct MonthCount{
+    #[diesel(sql_type=Date)]
+    month:NaiveDate,
+
+    #[diesel(sql_type=BigInt)]
+    count:i64,
+}
+
+#[derive(QueryableByName)]
+struct VisitStatistics{
+    #[diesel(sql_type=BigInt)]
+    visiting_members:i64,
+
+    #[diesel(sql_type=BigInt)]
+    repeat_members:i64,
+
+    #[diesel(sql_type=Numeric)]
+    average_gap:BigDecimal,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyNewMembers{
+    pub month:NaiveDate,
+
+    pub new_members:i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionSummary{
+    pub new_members:Vec<MonthlyNewMembers>, // 按注册时间统计
+
+    pub visiting_members:i64, // 区间内到店的会员数
+
+    pub repeat_members:i64, // 区间内到店两次及以上的会员数
+
+    pub repeat_visit_rate:BigDecimal, // 复购率，百分比
+
+    pub average_visit_gap_days:BigDecimal, // 同一会员相邻两次到店的平均间隔天数
+}
+
+// 会员留存概况：每月新增会员、复购率和平均到店间隔
+pub async fn get_retention_summary(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<RetentionSummary>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let mut new_members=diesel::sql_query(
+        "SELECT date_trunc('month', m.create_time AT TIME ZONE $2::interval)::date AS month, COUNT(*) AS count \
+        FROM merchant_members m \
+        WHERE m.enabled AND m.merchant_id=$1 AND m.create_time>=$3 AND m.create_time<$4 \
+        GROUP BY 1")
+        .bind::<SqlUuid,_>(merchant_id)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .load::<MonthCount>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|t|(t.month,t.count))
+        .collect::<BTreeMap<_,_>>();
+    let (start_date,end_date)=local_date_range(&params);
+    let statistics=diesel::sql_query(format!(
+        "WITH {VISITS_SQL}, gaps AS ( \
+            SELECT member_id, visit_date-LAG(visit_date) OVER (PARTITION BY member_id ORDER BY visit_date) AS gap \
+            FROM visits WHERE visit_date>=$4 AND visit_date<=$5 \
+        ) \
+        SELECT COUNT(DISTINCT member_id) AS visiting_members, \
+            COUNT(DISTINCT member_id) FILTER (WHERE gap IS NOT NULL) AS repeat_members, \
+            COALESCE(AVG(gap),0) AS average_gap \
+        FROM gaps"))
+        .bind::<SqlUuid,_>(merchant_id)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<Date,_>(start_date)
+        .bind::<Date,_>(end_date)
+        .get_result::<VisitStatistics>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(RetentionSummary{
+        new_members:period_starts("month", start_date, end_date).into_iter()
+            .map(|month|MonthlyNewMembers{
+                month,
+                new_members:new_members.remove(&month).unwrap_or_default(),
+            })
+            .collect(),
+        visiting_members:statistics.visiting_members,
+        repeat_members:statistics.repeat_members,
+        repeat_visit_rate:percentage(statistics.repeat_members, statistics.visiting_members),
+        average_visit_gap_days:statistics.average_gap.round(1),
+    }))
+}
+
+#[derive(QueryableByName)]
+struct CohortVisit{
+    #[diesel(sql_type=Date)]
+    cohort_month:NaiveDate,
+
+    #[diesel(sql_type=Date)]
+    visit_month:NaiveDate,
+
+    #[diesel(sql_type=BigInt)]
+    members:i64,
+}
+
+#[derive(Serialize,Debug,PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CohortMonth{
+    pub month_offset:usize, // 0 为首次到店当月
+
+    pub month:NaiveDate,
+
+    pub members:i64, // 当月再次到店的会员数
+
+    pub rate:BigDecimal, // 留存率，百分比
+}
+
+#[derive(Serialize,Debug,PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cohort{
+    pub cohort_month:NaiveDate,
+
+    pub members:i64, // 当月首次到店的会员数
+
+    pub retention:Vec<CohortMonth>,
+}
+
+// 按首次到店月份分组，统计之后每个月仍有到店的会员，直到 end_date 所在月份
+fn build_cohorts(visits:Vec<(NaiveDate,NaiveDate,i64)>,end_date:NaiveDate)->Vec<Cohort>{
+    let mut cohorts=BTreeMap::<NaiveDate,BTreeMap<NaiveDate,i64>>::new();
+    for (cohort_month,visit_month,members) in visits {
+        cohorts.entry(cohort_month).or_default().insert(visit_month, members);
+    }
+    cohorts.into_iter()
+        .map(|(cohort_month,visits)|{
+            let members=visits.get(&cohort_month).copied().unwrap_or_default();
+            Cohort{
+                cohort_month,
+                members,
+                retention:period_starts("month", cohort_month, end_date).into_iter()
+                    .enumerate()
+                    .map(|(month_offset,month)|{
+                        let count=visits.get(&month).copied().unwrap_or_default();
+                        CohortMonth{month_offset,month,members:count,rate:percentage(count, members)}
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+// 留存队列：区间内各月首次到店的会员在之后每个月的到店情况
+pub async fn get_retention_cohorts(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<Cohort>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let (start_date,end_date)=local_date_range(&params);
+    let visits=diesel::sql_query(format!(
+        "WITH {VISITS_SQL}, monthly AS ( \
+            SELECT DISTINCT member_id, date_trunc('month', visit_date)::date AS visit_month FROM visits WHERE visit_date<=$5 \
+        ), cohorts AS ( \
+            SELECT member_id, MIN(visit_month) AS cohort_month FROM monthly GROUP BY member_id \
+        ) \
+        SELECT c.cohort_month, m.visit_month, COUNT(*) AS members \
+        FROM cohorts c INNER JOIN monthly m ON m.member_id=c.member_id \
+        WHERE c.cohort_month>=$4 \
+        GROUP BY 1,2 ORDER BY 1,2"))
+        .bind::<SqlUuid,_>(merchant_id)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<Date,_>(start_date.with_day(1).unwrap())
+        .bind::<Date,_>(end_date)
+        .load::<CohortVisit>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|t|(t.cohort_month,t.visit_month,t.members))
+        .collect();
+
+    Ok(Json(build_cohorts(visits, end_date)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AtRiskRequest{
+    pub factor:Option<BigDecimal>, // 距上次到店超过平均间隔的倍数，默认 2
+
+    pub min_visits:Option<i64>, // 至少到店次数，默认 3
+}
+
+#[derive(QueryableByName,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AtRiskMember{
+    #[diesel(sql_type=SqlUuid)]
+    pub member_id:Uuid,
+
+    #[diesel(sql_type=Text)]
+    pub real_name:String,
+
+    #[diesel(sql_type=Text)]
+    pub cellphone:String,
+
+    #[diesel(sql_type=Numeric)]
+    pub balance:BigDecimal,
+
+    #[diesel(sql_type=BigInt)]
+    pub visit_count:i64,
+
+    #[diesel(sql_type=Date)]
+    pub last_visit_date:NaiveDate,
+
+    #[diesel(sql_type=Numeric)]
+    pub average_visit_gap_days:BigDecimal,
+
+    #[diesel(sql_type=Integer)]
+    pub days_since_last_visit:i32,
+}
+
+#[derive(QueryableByName)]
+struct Count{
+    #[diesel(sql_type=BigInt)]
+    count:i64,
+}
+
+// 流失风险会员：距上次到店的天数远超其平均到店间隔，按超出倍数从高到低排列
+pub async fn get_at_risk_members(
+    State(pg):State<AxumPg>,
+    Query(params):Query<PaginatedListRequest>,
+    Query(req):Query<AtRiskRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<PaginatedListResponse<AtRiskMember>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let factor=req.factor.unwrap_or_else(||BigDecimal::from(2));
+    if factor<BigDecimal::one() {
+        return Err((StatusCode::BAD_REQUEST,"倍数不能小于 1".into()));
+    }
+    let min_visits=req.min_visits.unwrap_or(3);
+    if min_visits<2 {
+        return Err((StatusCode::BAD_REQUEST,"至少需要到店 2 次才能计算到店间隔".into()));
+    }
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    // 平均间隔 = (最后一次 - 第一次) / (到店次数 - 1)
+    let risks_sql=format!(
+        "WITH {VISITS_SQL}, risks AS ( \
+            SELECT member_id, COUNT(*) AS visit_count, MAX(visit_date) AS last_visit_date, \
+                ROUND((MAX(visit_date)-MIN(visit_date))::numeric/(COUNT(*)-1),1) AS average_visit_gap_days, \
+                (now() AT TIME ZONE $2::interval)::date-MAX(visit_date) AS days_since_last_visit \
+            FROM visits GROUP BY member_id HAVING COUNT(*)>=$4 \
+        )");
+    let from_sql="FROM risks r INNER JOIN merchant_members m ON m.member_id=r.member_id AND m.merchant_id=$1 \
+        WHERE m.enabled AND r.days_since_last_visit>r.average_visit_gap_days*$5";
+
+    let count=diesel::sql_query(format!("{risks_sql} SELECT COUNT(*) AS count {from_sql}"))
+        .bind::<SqlUuid,_>(merchant_id)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<BigInt,_>(min_visits)
+        .bind::<Numeric,_>(&factor)
+        .get_result::<Count>(&mut *conn)
+        .unwrap()
+        .count;
+    let data=diesel::sql_query(format!(
+        "{risks_sql} SELECT m.member_id, m.real_name, m.cellphone, m.balance, r.visit_count, r.last_visit_date, r.average_visit_gap_days, r.days_since_last_visit \
+        {from_sql} \
+        ORDER BY r.days_since_last_visit/r.average_visit_gap_days DESC, r.last_visit_date \
+        LIMIT $6 OFFSET $7"))
+        .bind::<SqlUuid,_>(merchant_id)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<BigInt,_>(min_visits)
+        .bind::<Numeric,_>(&factor)
+        .bind::<BigInt,_>(params.page_size)
+        .bind::<BigInt,_>(params.page_index*params.page_size)
+        .load::<AtRiskMember>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(PaginatedListResponse{
+        page_index:params.page_index,
+        page_size:params.page_size,
+        total_count:count,
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_build_cohorts(){
+        let month=|m|NaiveDate::from_ymd_opt(2023, m, 1).unwrap();
+        let cohorts=build_cohorts(vec![
+            (month(1),month(1),4),
+            (month(1),month(3),1),
+            (month(2),month(2),2),
+            (month(2),month(3),2),
+        ], NaiveDate::from_ymd_opt(2023, 3, 15).unwrap());
+
+        assert_eq!(cohorts.len(), 2);
+        assert_eq!(cohorts[0].members, 4);
+        assert_eq!(
+            cohorts[0].retention.iter().map(|c|(c.month_offset,c.members,c.rate.clone())).collect::<Vec<_>>(),
+            vec![(0,4,BigDecimal::from(100)),(1,0,BigDecimal::default()),(2,1,BigDecimal::from(25))]
+        );
+        assert_eq!(cohorts[1].retention.len(), 2);
+        assert_eq!(cohorts[1].retention[1].rate, BigDecimal::from(100));
+    }
+}
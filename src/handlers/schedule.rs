@@ -0,0 +1,465 @@
+use axum::{http::StatusCode, Json, extract::{Query, Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use chrono::{Local, DateTime, NaiveDate, NaiveTime, Duration, Datelike, TimeZone};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant,
+    my_date_format
+};
+use diesel::prelude::*;
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, appointment::occupies_barber, business_hour::BusinessCalendar};
+
+//可预约时段的间隔（分钟）
+const SLOT_INTERVAL_MINUTES:i64=15;
+
+//一次最多查询的天数
+const MAX_SLOT_QUERY_DAYS:i64=31;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberScheduleResponse{
+    pub working_hours:Vec<BarberWorkingHour>,
+
+    pub overrides:Vec<BarberScheduleOverride>,
+}
+
+pub async fn get_barber_schedule(
+    State(pg):State<AxumPg>,
+    Path(barber_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<BarberScheduleResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let working_hours=barber_working_hours::table
+        .filter(barber_working_hours::enabled.eq(true))
+        .filter(barber_working_hours::merchant_id.eq(merchant_id))
+        .filter(barber_working_hours::barber_id.eq(barber_id))
+        .order((barber_working_hours::weekday.asc(),barber_working_hours::start_time.asc()))
+        .get_results::<BarberWorkingHour>(&mut *conn)
+        .unwrap();
+
+    let overrides=barber_schedule_overrides::table
+        .filter(barber_schedule_overrides::enabled.eq(true))
+        .filter(barber_schedule_overrides::merchant_id.eq(merchant_id))
+        .filter(barber_schedule_overrides::barber_id.eq(barber_id))
+        .filter(barber_schedule_overrides::override_date.ge(Local::today().naive_local()))
+        .order(barber_schedule_overrides::override_date.asc())
+        .get_results::<BarberScheduleOverride>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(BarberScheduleResponse{
+        working_hours,
+        overrides,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkingHourRequest{
+    pub weekday:i32,
+
+    pub start_time:NaiveTime,
+
+    pub end_time:NaiveTime,
+
+    pub kind:String, // work / break
+}
+
+// 整体替换理发师的每周工作时间
+pub async fn update_barber_working_hours(
+    State(pg):State<AxumPg>,
+    Path(barber_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<Vec<WorkingHourRequest>>
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    for item in req.iter() {
+        if !(1..=7).contains(&item.weekday) {
+            return Err((StatusCode::BAD_REQUEST,"星期必须在1到7之间".to_string()));
+        }
+        if item.end_time<=item.start_time {
+            return Err((StatusCode::BAD_REQUEST,"结束时间必须晚于开始时间".to_string()));
+        }
+        if item.kind!="work" && item.kind!="break" {
+            return Err((StatusCode::BAD_REQUEST,"时间类型只能是 work 或 break".to_string()));
+        }
+    }
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        barbers::table
+            .filter(barbers::enabled.eq(true))
+            .filter(barbers::merchant_id.eq(merchant_id))
+            .filter(barbers::barber_id.eq(barber_id))
+            .get_result::<Barber>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"理发师不存在".to_string()))?;
+
+        diesel::update(
+            barber_working_hours::table
+            .filter(barber_working_hours::barber_id.eq(barber_id))
+            .filter(barber_working_hours::merchant_id.eq(merchant_id))
+            .filter(barber_working_hours::enabled.eq(true))
+        )
+        .set((
+            barber_working_hours::enabled.eq(false),
+            barber_working_hours::update_time.eq(Local::now())
+        ))
+        .execute(conn)?;
+
+        for item in req.iter() {
+            let new_working_hour=NewBarberWorkingHour{
+                working_hour_id:&Uuid::new_v4(),
+                merchant_id:&merchant_id,
+                barber_id:&barber_id,
+                weekday:item.weekday,
+                start_time:item.start_time,
+                end_time:item.end_time,
+                kind:&item.kind,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(barber_working_hours::table)
+                .values(&new_working_hour)
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleOverrideRequest{
+    pub override_date:NaiveDate,
+
+    pub is_day_off:bool,
+
+    pub start_time:Option<NaiveTime>,
+
+    pub end_time:Option<NaiveTime>,
+
+    pub remark:Option<String>,
+}
+
+pub async fn add_barber_schedule_override(
+    State(pg):State<AxumPg>,
+    Path(barber_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<ScheduleOverrideRequest>
+)->Result<Json<BarberScheduleOverride>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if !req.is_day_off {
+        match (req.start_time,req.end_time) {
+            (Some(start_time),Some(end_time)) if end_time>start_time=>{},
+            (Some(_),Some(_))=>return Err((StatusCode::BAD_REQUEST,"结束时间必须晚于开始时间".to_string())),
+            _=>return Err((StatusCode::BAD_REQUEST,"非休息日需填写工作时间".to_string())),
+        }
+    }
+
+    let _barber=barbers::table
+        .filter(barbers::enabled.eq(true))
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .filter(barbers::barber_id.eq(barber_id))
+        .get_result::<Barber>(&mut *conn)
+        .map_err(|_|(StatusCode::NOT_FOUND,"理发师不存在".to_string()))?;
+
+    let new_override=NewBarberScheduleOverride{
+        override_id:&Uuid::new_v4(),
+        merchant_id:&merchant_id,
+        barber_id:&barber_id,
+        override_date:req.override_date,
+        is_day_off:req.is_day_off,
+        start_time:if req.is_day_off { None } else { req.start_time },
+        end_time:if req.is_day_off { None } else { req.end_time },
+        remark:req.remark.as_deref(),
+        enabled:true,
+        create_time: Local::now(),
+        update_time: Local::now(),
+        data: None,
+    };
+    let schedule_override=diesel::insert_into(barber_schedule_overrides::table)
+        .values(&new_override)
+        .get_result::<BarberScheduleOverride>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(schedule_override))
+}
+
+pub async fn delete_barber_schedule_override(
+    State(pg):State<AxumPg>,
+    Path(override_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        barber_schedule_overrides::table
+        .filter(barber_schedule_overrides::override_id.eq(override_id))
+        .filter(barber_schedule_overrides::merchant_id.eq(merchant_id))
+        .filter(barber_schedule_overrides::enabled.eq(true))
+    )
+    .set((
+        barber_schedule_overrides::enabled.eq(false),
+        barber_schedule_overrides::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"排班调整不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableSlotsRequest{
+    pub start_date:NaiveDate,
+
+    pub end_date:NaiveDate,
+
+    pub service_type_id:Uuid,
+
+    pub barber_id:Option<Uuid>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableSlot{
+    pub barber_id:Uuid,
+
+    pub barber_name:String,
+
+    #[serde(rename="start", with = "my_date_format")]
+    pub start_time:DateTime<Local>,
+
+    #[serde(rename="end", with = "my_date_format")]
+    pub end_time:DateTime<Local>,
+}
+
+pub async fn get_available_slots(
+    State(pg):State<AxumPg>,
+    Query(params):Query<AvailableSlotsRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<AvailableSlot>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if params.end_date<params.start_date {
+        return Err((StatusCode::BAD_REQUEST,"结束日期不能早于开始日期".to_string()));
+    }
+    if (params.end_date-params.start_date).num_days()>=MAX_SLOT_QUERY_DAYS {
+        return Err((StatusCode::BAD_REQUEST,format!("一次最多查询{}天",MAX_SLOT_QUERY_DAYS)));
+    }
+
+    let service_type=service_types::table
+        .filter(service_types::enabled.eq(true))
+        .filter(service_types::merchant_id.eq(merchant_id))
+        .filter(service_types::service_type_id.eq(params.service_type_id))
+        .get_result::<ServiceType>(&mut *conn)
+        .map_err(|_|(StatusCode::NOT_FOUND,"服务类型不存在".to_string()))?;
+    let duration=Duration::minutes(service_type.estimated_duration as i64);
+    if duration<=Duration::zero() {
+        return Err((StatusCode::BAD_REQUEST,"服务类型的预计时长无效".to_string()));
+    }
+
+    let mut query=barbers::table
+        .filter(barbers::enabled.eq(true))
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .into_boxed();
+    if let Some(barber_id)=params.barber_id {
+        query=query.filter(barbers::barber_id.eq(barber_id));
+    }
+    let barbers=query
+        .order(barbers::create_time.asc())
+        .get_results::<Barber>(&mut *conn)
+        .unwrap();
+    let barber_ids=barbers.iter().map(|b|b.barber_id).collect::<Vec<_>>();
+
+    let range_start=local_datetime(params.start_date, NaiveTime::from_hms(0, 0, 0));
+    let range_end=local_datetime(params.end_date+Duration::days(1), NaiveTime::from_hms(0, 0, 0));
+
+    let working_hours=barber_working_hours::table
+        .filter(barber_working_hours::enabled.eq(true))
+        .filter(barber_working_hours::merchant_id.eq(merchant_id))
+        .filter(barber_working_hours::barber_id.eq_any(&barber_ids))
+        .get_results::<BarberWorkingHour>(&mut *conn)
+        .unwrap();
+    let overrides=barber_schedule_overrides::table
+        .filter(barber_schedule_overrides::enabled.eq(true))
+        .filter(barber_schedule_overrides::merchant_id.eq(merchant_id))
+        .filter(barber_schedule_overrides::barber_id.eq_any(&barber_ids))
+        .filter(barber_schedule_overrides::override_date.between(params.start_date,params.end_date))
+        .get_results::<BarberScheduleOverride>(&mut *conn)
+        .unwrap();
+    let booked=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::barber_id.eq_any(&barber_ids).or(orders::order_id.eq_any(
+            order_lines::table
+            .filter(order_lines::enabled.eq(true))
+            .filter(order_lines::barber_id.eq_any(&barber_ids))
+            .select(order_lines::order_id)
+        )))
+        .filter(occupies_barber())
+        .filter(orders::start_time.lt(range_end).and(orders::end_time.gt(range_start)))
+        .get_results::<Order>(&mut *conn)
+        .unwrap();
+    let booked_lines=order_lines::table
+        .filter(order_lines::enabled.eq(true))
+        .filter(order_lines::order_id.eq_any(booked.iter().map(|o|o.order_id).collect::<Vec<_>>()))
+        .select((order_lines::order_id,order_lines::barber_id))
+        .get_results::<(Uuid,Uuid)>(&mut *conn)
+        .unwrap();
+
+    let calendar=BusinessCalendar::load(&mut conn, merchant_id, params.start_date, params.end_date)
+        .unwrap();
+
+    let now=Local::now();
+    let mut slots=Vec::new();
+    for barber in barbers.iter() {
+        let barber_booked=booked.iter()
+            .filter(|o|o.barber_id==barber.barber_id || booked_lines.contains(&(o.order_id,barber.barber_id)))
+            .map(|o|(o.start_time,o.end_time))
+            .collect::<Vec<_>>();
+
+        let mut date=params.start_date;
+        while date<=params.end_date {
+            let weekday=date.weekday().number_from_monday() as i32;
+            let day_overrides=overrides.iter()
+                .filter(|o|o.barber_id==barber.barber_id && o.override_date==date)
+                .collect::<Vec<_>>();
+
+            let work_periods=if day_overrides.is_empty() {
+                working_hours.iter()
+                    .filter(|w|w.barber_id==barber.barber_id && w.weekday==weekday && w.kind=="work")
+                    .map(|w|(w.start_time,w.end_time))
+                    .collect::<Vec<_>>()
+            } else if day_overrides.iter().any(|o|o.is_day_off) {
+                Vec::new()
+            } else {
+                day_overrides.iter()
+                    .filter_map(|o|Some((o.start_time?,o.end_time?)))
+                    .collect::<Vec<_>>()
+            };
+            let breaks=working_hours.iter()
+                .filter(|w|w.barber_id==barber.barber_id && w.weekday==weekday && w.kind=="break")
+                .map(|w|(w.start_time,w.end_time))
+                .collect::<Vec<_>>();
+
+            let free_slots=compute_free_slots(date, &work_periods, &breaks, &barber_booked, duration, Duration::minutes(SLOT_INTERVAL_MINUTES));
+            slots.extend(free_slots.into_iter()
+                .filter(|(start_time,end_time)|*start_time>=now && calendar.is_open(*start_time, *end_time))
+                .map(|(start_time,end_time)|AvailableSlot{
+                    barber_id:barber.barber_id,
+                    barber_name:barber.real_name.clone(),
+                    start_time,
+                    end_time,
+                }));
+
+            date+=Duration::days(1);
+        }
+    }
+    slots.sort_by_key(|s|s.start_time);
+
+    Ok(Json(slots))
+}
+
+pub fn local_datetime(date:NaiveDate,time:NaiveTime)->DateTime<Local>{
+    Local.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .unwrap_or_else(||Local.from_utc_datetime(&date.and_time(time)))
+}
+
+// 在工作时间内按间隔生成时段，去掉与休息时间、已有预约重叠的时段
+pub fn compute_free_slots(
+    date:NaiveDate,
+    work_periods:&[(NaiveTime,NaiveTime)],
+    breaks:&[(NaiveTime,NaiveTime)],
+    booked:&[(DateTime<Local>,DateTime<Local>)],
+    duration:Duration,
+    interval:Duration,
+)->Vec<(DateTime<Local>,DateTime<Local>)>{
+    let breaks=breaks.iter()
+        .map(|(s,e)|(local_datetime(date,*s),local_datetime(date,*e)))
+        .collect::<Vec<_>>();
+
+    let mut slots=Vec::new();
+    for (work_start,work_end) in work_periods.iter() {
+        let work_end=local_datetime(date,*work_end);
+        let mut start=local_datetime(date,*work_start);
+        while start+duration<=work_end {
+            let end=start+duration;
+            let is_free=breaks.iter().chain(booked.iter())
+                .all(|(s,e)|!(*s<end && *e>start));
+            if is_free {
+                slots.push((start,end));
+            }
+            start+=interval;
+        }
+    }
+    slots.sort();
+    slots.dedup();
+
+    slots
+}
+
+#[cfg(test)]
+mod test{
+    use chrono::{Duration, NaiveDate, NaiveTime};
+    use super::{compute_free_slots, local_datetime};
+
+    #[test]
+    fn test_compute_free_slots(){
+        let date=NaiveDate::from_ymd(2022, 11, 14);
+        let work=[(NaiveTime::from_hms(9, 0, 0),NaiveTime::from_hms(12, 0, 0))];
+        let breaks=[(NaiveTime::from_hms(10, 0, 0),NaiveTime::from_hms(10, 30, 0))];
+        let booked=[(local_datetime(date, NaiveTime::from_hms(11, 0, 0)),local_datetime(date, NaiveTime::from_hms(11, 30, 0)))];
+
+        let slots=compute_free_slots(date, &work, &breaks, &booked, Duration::minutes(30), Duration::minutes(30));
+        let starts=slots.iter().map(|(s,_)|s.time()).collect::<Vec<_>>();
+
+        assert_eq!(starts,vec![
+            NaiveTime::from_hms(9, 0, 0),
+            NaiveTime::from_hms(9, 30, 0),
+            NaiveTime::from_hms(10, 30, 0),
+            NaiveTime::from_hms(11, 30, 0),
+        ]);
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Local, DateTime, Duration};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, ledger_type, payment_type}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, balance_ledger::{change_balance, current_barber_id}};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServicePackageRequest{
+    pub name:String,
+
+    pub price:BigDecimal,
+
+    pub total_uses:Option<i32>,
+
+    pub valid_days:Option<i32>,
+
+    pub service_type_ids:Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServicePackageResponse{
+    #[serde(flatten)]
+    pub package:ServicePackage,
+
+    pub service_type_ids:Vec<Uuid>,
+}
+
+// 按套餐分组获取包含的服务类型
+fn load_package_service_types(conn:&mut PgConnection,package_ids:&[Uuid])->QueryResult<HashMap<Uuid,Vec<Uuid>>>{
+    Ok(service_package_items::table
+        .filter(service_package_items::enabled.eq(true))
+        .filter(service_package_items::package_id.eq_any(package_ids))
+        .select((service_package_items::package_id,service_package_items::service_type_id))
+        .get_results::<(Uuid,Uuid)>(conn)?
+        .into_iter()
+        .fold(HashMap::new(),|mut items:HashMap<Uuid,Vec<Uuid>>,(package_id,service_type_id)|{
+            items.entry(package_id).or_default().push(service_type_id);
+            items
+        }))
+}
+
+pub async fn get_service_packages(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<ServicePackageResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let packages=service_packages::table
+        .filter(service_packages::enabled.eq(true))
+        .filter(service_packages::merchant_id.eq(merchant_id))
+        .order(service_packages::create_time.desc())
+        .get_results::<ServicePackage>(&mut *conn)
+        .unwrap();
+    let mut items=load_package_service_types(&mut conn, &packages.iter().map(|p|p.package_id).collect::<Vec<_>>())
+        .unwrap();
+    let data=packages.into_iter().map(|p|ServicePackageResponse{
+            service_type_ids:items.remove(&p.package_id).unwrap_or_default(),
+            package:p,
+        }).collect();
+
+    Ok(Json(data))
+}
+
+pub async fn add_service_package(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<ServicePackageRequest>
+)->Result<Json<ServicePackageResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.price<BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"套餐价格不能为负数".to_string()));
+    }
+    if req.total_uses.is_none() && req.valid_days.is_none() {
+        return Err((StatusCode::BAD_REQUEST,"套餐需设置使用次数或有效天数".to_string()));
+    }
+    if req.total_uses.map(|n|n<=0).unwrap_or(false) || req.valid_days.map(|n|n<=0).unwrap_or(false) {
+        return Err((StatusCode::BAD_REQUEST,"使用次数和有效天数必须大于0".to_string()));
+    }
+
+    let mut service_type_ids=req.service_type_ids.clone();
+    service_type_ids.sort();
+    service_type_ids.dedup();
+    if service_type_ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST,"套餐至少包含一项服务".to_string()));
+    }
+    let service_count=service_types::table
+        .filter(service_types::enabled.eq(true))
+        .filter(service_types::merchant_id.eq(merchant_id))
+        .filter(service_types::service_type_id.eq_any(&service_type_ids))
+        .count()
+        .get_result::<i64>(&mut *conn)
+        .unwrap();
+    if service_count!=service_type_ids.len() as i64 {
+        return Err((StatusCode::BAD_REQUEST,"服务类型不存在".to_string()));
+    }
+
+    let package_id=Uuid::new_v4();
+    let package=conn.transaction::<_,TransactionError,_>(|conn|{
+        let new_package=NewServicePackage{
+            package_id:&package_id,
+            merchant_id:&merchant_id,
+            name:&req.name,
+            price:&req.price,
+            total_uses:req.total_uses,
+            valid_days:req.valid_days,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let package=diesel::insert_into(service_packages::table)
+            .values(&new_package)
+            .get_result::<ServicePackage>(conn)?;
+
+        for service_type_id in service_type_ids.iter() {
+            let new_item=NewServicePackageItem{
+                package_id:&package_id,
+                merchant_id:&merchant_id,
+                service_type_id,
+                enabled:true,
+                create_time: Local::now(),
+                update_time: Local::now(),
+                data: None,
+            };
+            diesel::insert_into(service_package_items::table)
+                .values(&new_item)
+                .execute(conn)?;
+        }
+
+        Ok(package)
+    })?;
+
+    Ok(Json(ServicePackageResponse{
+        package,
+        service_type_ids,
+    }))
+}
+
+// 下架套餐，已购买的会员套餐仍可继续使用
+pub async fn delete_service_package(
+    State(pg):State<AxumPg>,
+    Path(package_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<(),(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::MERCHANT_ADMINISTRATOR]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=diesel::update(
+        service_packages::table
+        .filter(service_packages::package_id.eq(package_id))
+        .filter(service_packages::merchant_id.eq(merchant_id))
+        .filter(service_packages::enabled.eq(true))
+    )
+    .set((
+        service_packages::enabled.eq(false),
+        service_packages::update_time.eq(Local::now())
+    ))
+    .execute(&mut *conn)
+    .unwrap();
+    if count==0 {
+        return Err((StatusCode::NOT_FOUND,"套餐不存在".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberPackageResponse{
+    #[serde(flatten)]
+    pub member_package:MemberPackage,
+
+    pub package_name:String,
+
+    pub service_type_ids:Vec<Uuid>,
+
+    pub available:bool, // 未过期且有剩余次数
+}
+
+// 会员已购买的套餐
+pub fn load_member_packages(conn:&mut PgConnection,merchant_id:Uuid,member_id:Uuid)->QueryResult<Vec<MemberPackageResponse>>{
+    let rows=member_packages::table
+        .inner_join(service_packages::table.on(member_packages::package_id.eq(service_packages::package_id)))
+        .filter(member_packages::enabled.eq(true))
+        .filter(member_packages::merchant_id.eq(merchant_id))
+        .filter(member_packages::member_id.eq(member_id))
+        .order(member_packages::create_time.desc())
+        .get_results::<(MemberPackage,ServicePackage)>(conn)?;
+    let items=load_package_service_types(conn, &rows.iter().map(|t|t.0.package_id).collect::<Vec<_>>())?;
+
+    let now=Local::now();
+    Ok(rows.into_iter().map(|t|MemberPackageResponse{
+            available:is_package_available(&t.0, now),
+            package_name:t.1.name,
+            service_type_ids:items.get(&t.0.package_id).cloned().unwrap_or_default(),
+            member_package:t.0,
+        }).collect())
+}
+
+fn is_package_available(member_package:&MemberPackage,time:DateTime<Local>)->bool{
+    member_package.remaining_uses.map(|n|n>0).unwrap_or(true)
+        && member_package.expire_time.map(|t|time<=t).unwrap_or(true)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuyPackageRequest{
+    pub package_id:Uuid,
+
+    pub payment_type:String, // member/cash/card/wechat/alipay
+}
+
+// 套餐不能用套餐或积分购买，积分抵扣只用于预约
+fn check_package_payment_type(tender:&str)->Result<(),(StatusCode,String)>{
+    if !payment_type::is_tender(tender) || tender==payment_type::PACKAGE || tender==payment_type::POINTS {
+        return Err((StatusCode::BAD_REQUEST,format!("不支持的支付方式 {tender}")));
+    }
+    Ok(())
+}
+
+pub async fn buy_package(
+    State(pg):State<AxumPg>,
+    Path(member_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<BuyPackageRequest>
+)->Result<Json<MemberPackage>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    check_package_payment_type(&req.payment_type)?;
+
+    let member_existed=select(exists(
+        merchant_members::table
+        .filter(merchant_members::enabled.eq(true))
+        .filter(merchant_members::merchant_id.eq(merchant_id))
+        .filter(merchant_members::member_id.eq(member_id))
+        ))
+        .get_result::<bool>(&mut *conn)
+        .unwrap();
+    if !member_existed {
+        return Err((StatusCode::BAD_REQUEST,"会员不存在".to_string()));
+    }
+
+    let package=service_packages::table
+        .filter(service_packages::enabled.eq(true))
+        .filter(service_packages::merchant_id.eq(merchant_id))
+        .filter(service_packages::package_id.eq(req.package_id))
+        .get_result::<ServicePackage>(&mut *conn)
+        .map_err(|_|(StatusCode::BAD_REQUEST,"套餐不存在".to_string()))?;
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let member_package=conn.transaction::<_,TransactionError,_>(|conn|{
+        let member_package_id=Uuid::new_v4();
+        let new_member_package=NewMemberPackage{
+            member_package_id:&member_package_id,
+            merchant_id:&merchant_id,
+            member_id:&member_id,
+            package_id:&package.package_id,
+            remaining_uses:package.total_uses,
+            expire_time:package.valid_days.map(|days|Local::now()+Duration::days(days as i64)),
+            price:&package.price,
+            payment_type:&req.payment_type,
+            barber_id:operator_id.as_ref(),
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let member_package=diesel::insert_into(member_packages::table)
+            .values(&new_member_package)
+            .get_result::<MemberPackage>(conn)?;
+
+        if req.payment_type==payment_type::MEMBER {
+            change_balance(conn, merchant_id, member_id, &-&package.price, ledger_type::PACKAGE_PURCHASE, Some(member_package_id), operator_id, Some(&package.name))?;
+        }
+
+        Ok(member_package)
+    })?;
+
+    Ok(Json(member_package))
+}
+
+// 使用套餐支付预约，每项服务扣减一次，需在事务中调用
+pub fn consume_package(
+    conn:&mut PgConnection,
+    merchant_id:Uuid,
+    member_id:Uuid,
+    member_package_id:Uuid,
+    service_type_ids:&[Uuid],
+    start_time:DateTime<Local>,
+)->Result<(),TransactionError>{
+    let member_package=member_packages::table
+        .filter(member_packages::enabled.eq(true))
+        .filter(member_packages::merchant_id.eq(merchant_id))
+        .filter(member_packages::member_package_id.eq(member_package_id))
+        .for_update()
+        .get_result::<MemberPackage>(conn)
+        .optional()?
+        .filter(|p|p.member_id==member_id)
+        .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,"会员套餐不存在".to_string()))?;
+
+    if member_package.expire_time.map(|t|t<start_time).unwrap_or(false) {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"套餐已过期".to_string()));
+    }
+
+    let uses=service_type_ids.len() as i32;
+    if member_package.remaining_uses.map(|n|n<uses).unwrap_or(false) {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"套餐剩余次数不足".to_string()));
+    }
+
+    let covered=load_package_service_types(conn, &[member_package.package_id])?
+        .remove(&member_package.package_id)
+        .unwrap_or_default();
+    if service_type_ids.iter().any(|id|!covered.contains(id)) {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"套餐不包含所选服务".to_string()));
+    }
+
+    if member_package.remaining_uses.is_some() {
+        diesel::update(member_packages::table.filter(member_packages::member_package_id.eq(member_package_id)))
+            .set((
+                member_packages::remaining_uses.eq(member_packages::remaining_uses - uses),
+                member_packages::update_time.eq(Local::now())
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+// 取消或退款时退回套餐次数，需在事务中调用
+pub fn restore_package_uses(conn:&mut PgConnection,merchant_id:Uuid,member_package_id:Uuid,uses:i32)->Result<(),TransactionError>{
+    diesel::update(
+        member_packages::table
+        .filter(member_packages::merchant_id.eq(merchant_id))
+        .filter(member_packages::member_package_id.eq(member_package_id))
+        .filter(member_packages::remaining_uses.is_not_null())
+    )
+    .set((
+        member_packages::remaining_uses.eq(member_packages::remaining_uses + uses),
+        member_packages::update_time.eq(Local::now())
+    ))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_check_package_payment_type(){
+        assert!(check_package_payment_type(payment_type::CASH).is_ok());
+        assert!(check_package_payment_type(payment_type::MEMBER).is_ok());
+        assert!(check_package_payment_type(payment_type::PACKAGE).is_err());
+        assert!(check_package_payment_type(payment_type::POINTS).is_err());
+        assert!(check_package_payment_type(payment_type::MIXED).is_err());
+    }
+}
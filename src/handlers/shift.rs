@@ -0,0 +1,411 @@
+use axum::{http::StatusCode, Json, extract::{Path, Query, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, payment_type, shift_status, cash_movement_type}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{PaginatedListRequest, PaginatedListResponse, TransactionError, balance_ledger::current_barber_id};
+
+#[derive(Serialize,Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftCashSummary{
+    pub opening_float:BigDecimal, // 备用金
+
+    pub order_cash:BigDecimal, // 现金支付的订单，退款为负数
+
+    pub tip_cash:BigDecimal, // 现金小费
+
+    pub recharge_cash:BigDecimal, // 现金充值，撤销为负数
+
+    pub package_cash:BigDecimal, // 现金购买套餐
+
+    pub cash_in:BigDecimal,
+
+    pub cash_out:BigDecimal,
+
+    pub expected_cash:BigDecimal, // 应有现金
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftResponse{
+    #[serde(flatten)]
+    pub shift:Shift,
+
+    pub summary:ShiftCashSummary,
+
+    pub movements:Vec<ShiftCashMovement>,
+}
+
+// 统计班次时间段内的现金收支，未结班次统计到当前时间；订单按收款时间统计，取消和退款按负数记录在操作时，不影响已结班次
+fn cash_summary(conn:&mut PgConnection,shift:&Shift,end_time:DateTime<Local>)->QueryResult<ShiftCashSummary>{
+    let order_cash=order_payments::table
+        .inner_join(orders::table.on(order_payments::order_id.eq(orders::order_id)))
+        .filter(order_payments::enabled.eq(true))
+        .filter(order_payments::tender.eq(payment_type::CASH))
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(shift.merchant_id))
+        .filter(order_payments::create_time.ge(shift.open_time).and(order_payments::create_time.lt(end_time)))
+        .select(diesel::dsl::sum(order_payments::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_default();
+    let tip_cash=order_tips::table
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::merchant_id.eq(shift.merchant_id))
+        .filter(order_tips::tender.eq(payment_type::CASH))
+        .filter(order_tips::create_time.ge(shift.open_time).and(order_tips::create_time.lt(end_time)))
+        .select(diesel::dsl::sum(order_tips::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_default();
+    let recharge_cash=recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::merchant_id.eq(shift.merchant_id))
+        .filter(recharge_records::tender.eq(payment_type::CASH))
+        .filter(recharge_records::create_time.ge(shift.open_time).and(recharge_records::create_time.lt(end_time)))
+        .select(diesel::dsl::sum(recharge_records::amount))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_default();
+    let package_cash=member_packages::table
+        .filter(member_packages::enabled.eq(true))
+        .filter(member_packages::merchant_id.eq(shift.merchant_id))
+        .filter(member_packages::payment_type.eq(payment_type::CASH))
+        .filter(member_packages::create_time.ge(shift.open_time).and(member_packages::create_time.lt(end_time)))
+        .select(diesel::dsl::sum(member_packages::price))
+        .get_result::<Option<BigDecimal>>(conn)?
+        .unwrap_or_default();
+    let movements=shift_cash_movements::table
+        .filter(shift_cash_movements::shift_id.eq(shift.shift_id))
+        .group_by(shift_cash_movements::movement_type)
+        .select((shift_cash_movements::movement_type,diesel::dsl::sum(shift_cash_movements::amount)))
+        .get_results::<(String,Option<BigDecimal>)>(conn)?;
+
+    let mut summary=ShiftCashSummary{
+        opening_float:shift.opening_float.clone(),
+        order_cash,
+        tip_cash,
+        recharge_cash,
+        package_cash,
+        ..Default::default()
+    };
+    for (movement_type,amount) in movements {
+        match movement_type.as_str() {
+            cash_movement_type::CASH_IN=>summary.cash_in=amount.unwrap_or_default(),
+            _=>summary.cash_out=amount.unwrap_or_default(),
+        }
+    }
+    summary.expected_cash=&summary.opening_float+&summary.order_cash+&summary.tip_cash+&summary.recharge_cash+&summary.package_cash+&summary.cash_in-&summary.cash_out;
+
+    Ok(summary)
+}
+
+fn load_shift_response(conn:&mut PgConnection,shift:Shift)->QueryResult<ShiftResponse>{
+    let summary=cash_summary(conn, &shift, shift.close_time.unwrap_or_else(Local::now))?;
+    let movements=shift_cash_movements::table
+        .filter(shift_cash_movements::shift_id.eq(shift.shift_id))
+        .order(shift_cash_movements::id.asc())
+        .get_results::<ShiftCashMovement>(conn)?;
+
+    Ok(ShiftResponse{shift,summary,movements})
+}
+
+fn lock_open_shift(conn:&mut PgConnection,merchant_id:Uuid,shift_id:Uuid)->Result<Shift,TransactionError>{
+    let shift=shifts::table
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .filter(shifts::shift_id.eq(shift_id))
+        .for_update()
+        .get_result::<Shift>(conn)
+        .optional()?
+        .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"班次不存在".to_string()))?;
+    if shift.status!=shift_status::OPEN {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"该班次已结班".to_string()));
+    }
+    Ok(shift)
+}
+
+// 已结班次时间段内收取的款项不能再修改，订单状态不受影响，退款另行记录在当前班次
+pub fn check_payments_unlocked(conn:&mut PgConnection,merchant_id:Uuid,order_id:Uuid)->Result<(),TransactionError>{
+    let locked=select(exists(
+        order_payments::table
+        .inner_join(shifts::table.on(
+            shifts::merchant_id.eq(order_payments::merchant_id)
+            .and(shifts::open_time.le(order_payments::create_time))
+            .and(shifts::close_time.gt(order_payments::create_time.nullable()))
+        ))
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .filter(shifts::status.eq(shift_status::CLOSED))
+        .filter(order_payments::enabled.eq(true))
+        .filter(order_payments::order_id.eq(order_id))
+        ))
+        .get_result::<bool>(conn)?;
+    if locked {
+        return Err(TransactionError(StatusCode::BAD_REQUEST,"该订单的款项已在已结班次收取，不能修改".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn get_shifts(
+    State(pg):State<AxumPg>,
+    Query(params):Query<PaginatedListRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<PaginatedListResponse<Shift>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=shifts::table
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .count()
+        .get_result(&mut *conn)
+        .unwrap();
+    let data=shifts::table
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .order(shifts::open_time.desc())
+        .limit(params.page_size)
+        .offset(params.page_index*params.page_size)
+        .get_results::<Shift>(&mut *conn)
+        .unwrap();
+
+    Ok(Json(PaginatedListResponse{
+        page_index:params.page_index,
+        page_size:params.page_size,
+        total_count:count,
+        data,
+    }))
+}
+
+// 当前未结班次，没有则返回 null
+pub async fn get_current_shift(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Option<ShiftResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let shift=shifts::table
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .filter(shifts::status.eq(shift_status::OPEN))
+        .get_result::<Shift>(&mut *conn)
+        .optional()
+        .unwrap();
+    let data=shift.map(|s|load_shift_response(&mut conn, s).unwrap());
+
+    Ok(Json(data))
+}
+
+pub async fn get_shift(
+    State(pg):State<AxumPg>,
+    Path(shift_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<ShiftResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let shift=shifts::table
+        .filter(shifts::merchant_id.eq(merchant_id))
+        .filter(shifts::shift_id.eq(shift_id))
+        .get_result::<Shift>(&mut *conn)
+        .map_err(|_|(StatusCode::NOT_FOUND,"班次不存在".to_string()))?;
+
+    Ok(Json(load_shift_response(&mut conn, shift).unwrap()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenShiftRequest{
+    pub opening_float:BigDecimal,
+
+    pub remark:Option<String>,
+}
+
+pub async fn open_shift(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<OpenShiftRequest>
+)->Result<Json<ShiftResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.opening_float<BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"备用金不能小于0".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let shift=conn.transaction::<_,TransactionError,_>(|conn|{
+        let opened=select(exists(
+            shifts::table
+            .filter(shifts::merchant_id.eq(merchant_id))
+            .filter(shifts::status.eq(shift_status::OPEN))
+            ))
+            .get_result::<bool>(conn)?;
+        if opened {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"当前班次未结班，不能重复开班".to_string()));
+        }
+
+        let new_shift=NewShift{
+            shift_id:&Uuid::new_v4(),
+            merchant_id:&merchant_id,
+            status:shift_status::OPEN,
+            opening_float:&req.opening_float,
+            opened_by:operator_id.as_ref(),
+            open_time: Local::now(),
+            remark:req.remark.as_deref(),
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        let shift=diesel::insert_into(shifts::table)
+            .values(&new_shift)
+            .get_result::<Shift>(conn)?;
+
+        Ok(shift)
+    })?;
+
+    Ok(Json(load_shift_response(&mut conn, shift).unwrap()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CashMovementRequest{
+    pub movement_type:String, // in / out
+
+    pub amount:BigDecimal,
+
+    pub reason:String,
+}
+
+// 班次内存入或取出现金
+pub async fn add_cash_movement(
+    State(pg):State<AxumPg>,
+    Path(shift_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<CashMovementRequest>
+)->Result<Json<ShiftResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.movement_type!=cash_movement_type::CASH_IN && req.movement_type!=cash_movement_type::CASH_OUT {
+        return Err((StatusCode::BAD_REQUEST,format!("不支持的存取类型 {}",req.movement_type)));
+    }
+    if req.amount<=BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"金额必须大于0".to_string()));
+    }
+    if req.reason.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST,"存取原因不能为空".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let shift=conn.transaction::<_,TransactionError,_>(|conn|{
+        let shift=lock_open_shift(conn, merchant_id, shift_id)?;
+
+        let new_movement=NewShiftCashMovement{
+            movement_id:&Uuid::new_v4(),
+            shift_id:&shift_id,
+            merchant_id:&merchant_id,
+            movement_type:&req.movement_type,
+            amount:&req.amount,
+            reason:&req.reason,
+            barber_id:operator_id.as_ref(),
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        diesel::insert_into(shift_cash_movements::table)
+            .values(&new_movement)
+            .execute(conn)?;
+
+        Ok(shift)
+    })?;
+
+    Ok(Json(load_shift_response(&mut conn, shift).unwrap()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloseShiftRequest{
+    pub counted_cash:BigDecimal, // 清点的实际现金
+
+    pub remark:Option<String>,
+}
+
+// 结班：计算应有现金并与清点金额比较，结班后该时间段的订单不能再修改
+pub async fn close_shift(
+    State(pg):State<AxumPg>,
+    Path(shift_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<CloseShiftRequest>
+)->Result<Json<ShiftResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.counted_cash<BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"清点金额不能小于0".to_string()));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    let shift=conn.transaction::<_,TransactionError,_>(|conn|{
+        let shift=lock_open_shift(conn, merchant_id, shift_id)?;
+
+        let close_time=Local::now();
+        let summary=cash_summary(conn, &shift, close_time)?;
+        let shift=diesel::update(
+            shifts::table
+            .filter(shifts::shift_id.eq(shift_id))
+        )
+        .set((
+            shifts::status.eq(shift_status::CLOSED),
+            shifts::closed_by.eq(operator_id),
+            shifts::close_time.eq(close_time),
+            shifts::expected_cash.eq(&summary.expected_cash),
+            shifts::counted_cash.eq(&req.counted_cash),
+            shifts::cash_difference.eq(&req.counted_cash-&summary.expected_cash),
+            shifts::remark.eq(req.remark.as_deref().or(shift.remark.as_deref())),
+            shifts::update_time.eq(Local::now())
+        ))
+        .get_result::<Shift>(conn)?;
+
+        Ok(shift)
+    })?;
+
+    Ok(Json(load_shift_response(&mut conn, shift).unwrap()))
+}
@@ -1,23 +1,27 @@
+use std::collections::{BTreeMap, HashMap};
+
 use axum::{Json, http::StatusCode, extract::{State, Query}};
 use axum_session_authentication_middleware::session::AuthSession;
 use bigdecimal::BigDecimal;
-use chrono::Local;
+use chrono::{Datelike, Duration, Local, NaiveDate};
 use diesel::QueryDsl;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use diesel::{
     prelude::*, // for .filter
+    pg::Pg,
+    sql_types::{BigInt, Bool, Date, Nullable, Numeric, Text, Timestamptz},
 }; 
 use crate::{
     models::*, 
     authorization_policy, 
     axum_pg::AxumPg, 
-    constant, 
+    constant::{self, order_status, payment_type}, 
     schema::*,
     my_date_format
 };
 
-use super::{PaginatedListResponse, PaginatedListRequest, Search};
+use super::{PaginatedListResponse, PaginatedListRequest, Search, tip::{OrderTipResponse, load_order_tips}};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -41,49 +45,272 @@ pub struct OrderResponse{
 
     pub barber_name:String,
 
+    pub status:String,
+
+    pub lines:Vec<OrderLineResponse>,
+
+    pub payments:Vec<OrderPaymentResponse>,
+
+    pub tips:Vec<OrderTipResponse>, // 小费，不计入订单金额
+
+    pub reversal_of:Option<Uuid>, // 退款记录对应的原订单
+
+    pub reversal_reason:Option<String>,
+
+    pub reversed_by:Option<Uuid>, // 原订单对应的退款记录
+
     #[serde(with = "my_date_format")]
     pub create_time:chrono::DateTime<Local>,
 }
 
-pub async fn get_orders(
-    State(pg):State<AxumPg>,
-    Query(params):Query<PaginatedListRequest>, 
-    Query(search):Query<Search>, 
-    auth: AuthSession<AxumPg, AxumPg,User>,
-)->Result<Json<PaginatedListResponse<OrderResponse>>,(StatusCode,String)>{
-    //检查登录&权限
-    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
-    
-    let mut conn=pg.pool.get().unwrap();
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderLineResponse{
+    #[serde(rename="id")]
+    pub order_line_id:Uuid,
 
-    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+    pub service_type_id:Uuid,
 
-    let fn_get_query=||{
-        let mut query=orders::table
-            .left_join(merchant_members::table.on(merchant_members::member_id.nullable().eq(orders::member_id)))
-            .left_join(barbers::table.on(orders::barber_id.eq(barbers::barber_id)))
-            .left_join(service_types::table.on(orders::service_type_id.eq(service_types::service_type_id)))
-            .filter(orders::enabled.eq(true))
-            .filter(orders::merchant_id.eq(merchant_id))
-            .into_boxed();
-        
-        if let Some(key)=search.key.as_ref() {
-            query=query.filter(merchant_members::real_name.ilike(format!("%{key}%")));   
+    pub service_name:String,
+
+    pub barber_id:Uuid,
+
+    pub barber_name:String,
+
+    pub amount:BigDecimal,
+
+    pub list_price:Option<BigDecimal>,
+
+    pub price_override_reason:Option<String>,
+
+    pub duration:i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPaymentResponse{
+    pub tender:String,
+
+    pub tender_name:String,
+
+    pub amount:BigDecimal,
+}
+
+// 按订单分组获取支付方式
+pub fn load_order_payments(conn:&mut PgConnection,order_ids:&[Uuid])->HashMap<Uuid,Vec<OrderPaymentResponse>>{
+    order_payments::table
+        .filter(order_payments::enabled.eq(true))
+        .filter(order_payments::order_id.eq_any(order_ids))
+        .order(order_payments::id.asc())
+        .get_results::<OrderPayment>(conn)
+        .unwrap()
+        .into_iter()
+        .fold(HashMap::new(),|mut payments:HashMap<Uuid,Vec<OrderPaymentResponse>>,p|{
+            payments.entry(p.order_id).or_default().push(OrderPaymentResponse{
+                tender_name:payment_type::display_name(&p.tender).into(),
+                tender:p.tender,
+                amount:p.amount,
+            });
+            payments
+        })
+}
+
+// 按订单分组获取订单明细
+pub fn load_order_lines(conn:&mut PgConnection,order_ids:&[Uuid])->HashMap<Uuid,Vec<OrderLineResponse>>{
+    order_lines::table
+        .left_join(service_types::table.on(order_lines::service_type_id.eq(service_types::service_type_id)))
+        .left_join(barbers::table.on(order_lines::barber_id.eq(barbers::barber_id)))
+        .filter(order_lines::enabled.eq(true))
+        .filter(order_lines::order_id.eq_any(order_ids))
+        .order(order_lines::id.asc())
+        .get_results::<(OrderLine,Option<ServiceType>,Option<Barber>)>(conn)
+        .unwrap()
+        .into_iter()
+        .fold(HashMap::new(),|mut lines:HashMap<Uuid,Vec<OrderLineResponse>>,t|{
+            lines.entry(t.0.order_id).or_default().push(OrderLineResponse{
+                order_line_id:t.0.order_line_id,
+                service_type_id:t.0.service_type_id,
+                service_name:t.1.filter(|s|s.enabled).map(|s|s.name).unwrap_or_else(||"-".into()),
+                barber_id:t.0.barber_id,
+                barber_name:t.2.filter(|b|b.enabled).map(|b|b.real_name).unwrap_or_else(||"-".into()),
+                amount:t.0.amount,
+                list_price:t.0.list_price,
+                price_override_reason:t.0.price_override_reason,
+                duration:t.0.duration,
+            });
+            lines
+        })
+}
+
+diesel::alias!(orders as original_orders: OriginalOrders);
+
+// 计入营业额的订单：已完成的订单，以及已完成订单的退款记录
+pub fn is_completed_sale()->Box<dyn BoxableExpression<orders::table,Pg,SqlType=Nullable<Bool>>>{
+    Box::new(orders::status.eq(order_status::COMPLETED).nullable().or(orders::reversal_of.eq_any(
+        original_orders
+        .filter(original_orders.field(orders::status).eq(order_status::COMPLETED))
+        .select(original_orders.field(orders::order_id).nullable())
+    )))
+}
+
+// 原订单ID -> 退款记录ID
+pub fn load_order_reversals(conn:&mut PgConnection,order_ids:&[Uuid])->HashMap<Uuid,Uuid>{
+    orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::reversal_of.eq_any(order_ids))
+        .select((orders::reversal_of.assume_not_null(),orders::order_id))
+        .get_results::<(Uuid,Uuid)>(conn)
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+// 原充值记录ID -> 撤销记录ID
+pub fn load_recharge_reversals(conn:&mut PgConnection,recharge_record_ids:&[Uuid])->HashMap<Uuid,Uuid>{
+    recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::reversal_of.eq_any(recharge_record_ids))
+        .select((recharge_records::reversal_of.assume_not_null(),recharge_records::recharge_record_id))
+        .get_results::<(Uuid,Uuid)>(conn)
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticFilter{
+    pub start_date:Option<chrono::DateTime<Local>>,
+
+    pub end_date:Option<chrono::DateTime<Local>>,
+
+    pub barber_id:Option<Uuid>, // 订单按任一明细的理发师筛选，充值按经办理发师筛选
+
+    pub service_type_id:Option<Uuid>, // 仅订单
+
+    pub payment_type:Option<String>, // 订单按任一支付方式筛选，充值按收款方式筛选
+
+    pub consumer_type:Option<String>, // member / walk-in
+
+    pub min_amount:Option<BigDecimal>,
+
+    pub max_amount:Option<BigDecimal>,
+}
+
+impl StatisticFilter{
+    pub fn validate(&self)->Result<(),(StatusCode,String)>{
+        if let Some(tender)=self.payment_type.as_deref() {
+            if !payment_type::is_tender(tender) {
+                return Err((StatusCode::BAD_REQUEST,format!("不支持的支付方式 {tender}")));
+            }
+        }
+        if let Some(consumer_type)=self.consumer_type.as_deref() {
+            if consumer_type!="member" && consumer_type!="walk-in" {
+                return Err((StatusCode::BAD_REQUEST,format!("不支持的顾客类型 {consumer_type}")));
+            }
+        }
+        if let (Some(start_date),Some(end_date))=(self.start_date,self.end_date) {
+            if start_date>=end_date {
+                return Err((StatusCode::BAD_REQUEST,"开始时间必须早于结束时间".into()));
+            }
+        }
+        if let (Some(min_amount),Some(max_amount))=(self.min_amount.as_ref(),self.max_amount.as_ref()) {
+            if min_amount>max_amount {
+                return Err((StatusCode::BAD_REQUEST,"最小金额不能大于最大金额".into()));
+            }
         }
+        Ok(())
+    }
 
-        query
-    };
+    pub fn validate_for_recharge_records(&self)->Result<(),(StatusCode,String)>{
+        self.validate()?;
+        if self.service_type_id.is_some() {
+            return Err((StatusCode::BAD_REQUEST,"充值记录不支持按服务类型筛选".into()));
+        }
+        // 只有会员可以充值
+        if self.consumer_type.as_deref()==Some("walk-in") {
+            return Err((StatusCode::BAD_REQUEST,"充值记录不支持按进店顾客筛选".into()));
+        }
+        Ok(())
+    }
+}
+
+// 按搜索条件和筛选条件过滤订单，列表和导出共用
+pub fn filtered_orders<'a>(merchant_id:Uuid,search:&'a Search,filter:&'a StatisticFilter)->orders::BoxedQuery<'a,Pg>{
+    let mut query=orders::table
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .into_boxed();
     
-    let count=fn_get_query()
-        .count()
-        .get_result(&mut *conn)
-        .unwrap();
-    let data=fn_get_query()
-        .order(orders::create_time.desc())
-        .limit(params.page_size)
-        .offset(params.page_index*params.page_size)
-        .get_results::<(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>)>(&mut *conn)
-        .map(|v|v.into_iter().map(|t|OrderResponse{
+    if let Some(key)=search.key.as_ref() {
+        query=query.filter(orders::member_id.eq_any(
+            merchant_members::table
+                .filter(merchant_members::real_name.ilike(format!("%{key}%")))
+                .select(merchant_members::member_id.nullable())
+        ));
+    }
+    if let Some(start_date)=filter.start_date {
+        query=query.filter(orders::create_time.ge(start_date));
+    }
+    if let Some(end_date)=filter.end_date {
+        query=query.filter(orders::create_time.lt(end_date));
+    }
+    if let Some(barber_id)=filter.barber_id {
+        query=query.filter(orders::barber_id.eq(barber_id).or(orders::order_id.eq_any(
+            order_lines::table
+                .filter(order_lines::enabled.eq(true))
+                .filter(order_lines::barber_id.eq(barber_id))
+                .select(order_lines::order_id)
+        )));
+    }
+    if let Some(service_type_id)=filter.service_type_id {
+        query=query.filter(orders::service_type_id.eq(service_type_id).or(orders::order_id.eq_any(
+            order_lines::table
+                .filter(order_lines::enabled.eq(true))
+                .filter(order_lines::service_type_id.eq(service_type_id))
+                .select(order_lines::order_id)
+        )));
+    }
+    if let Some(tender)=filter.payment_type.as_ref() {
+        query=query.filter(orders::payment_type.eq(tender).or(orders::order_id.eq_any(
+            order_payments::table
+                .filter(order_payments::enabled.eq(true))
+                .filter(order_payments::tender.eq(tender))
+                .select(order_payments::order_id)
+        )));
+    }
+    if let Some(consumer_type)=filter.consumer_type.as_ref() {
+        query=query.filter(orders::consumer_type.eq(consumer_type));
+    }
+    if let Some(min_amount)=filter.min_amount.as_ref() {
+        query=query.filter(orders::amount.ge(min_amount));
+    }
+    if let Some(max_amount)=filter.max_amount.as_ref() {
+        query=query.filter(orders::amount.le(max_amount));
+    }
+
+    query
+}
+
+pub type OrderRow=(Order,Option<MerchantMember>,Option<Barber>,Option<ServiceType>);
+
+type OrderRowsQuery=diesel::dsl::LeftJoinOn<diesel::dsl::LeftJoinOn<diesel::dsl::LeftJoinOn<orders::table,merchant_members::table,diesel::dsl::Eq<diesel::dsl::Nullable<merchant_members::member_id>,orders::member_id>>,barbers::table,diesel::dsl::Eq<orders::barber_id,barbers::barber_id>>,service_types::table,diesel::dsl::Eq<orders::service_type_id,service_types::service_type_id>>;
+
+// 订单关联会员、理发师和服务类型，需再按 filtered_orders 过滤
+pub fn order_rows()->OrderRowsQuery{
+    orders::table
+        .left_join(merchant_members::table.on(merchant_members::member_id.nullable().eq(orders::member_id)))
+        .left_join(barbers::table.on(orders::barber_id.eq(barbers::barber_id)))
+        .left_join(service_types::table.on(orders::service_type_id.eq(service_types::service_type_id)))
+}
+
+pub fn order_responses(conn:&mut PgConnection,rows:Vec<OrderRow>)->Vec<OrderResponse>{
+    let order_ids=rows.iter().map(|t|t.0.order_id).collect::<Vec<_>>();
+    let mut lines=load_order_lines(conn, &order_ids);
+    let mut payments=load_order_payments(conn, &order_ids);
+    let mut tips=load_order_tips(conn, &order_ids);
+    let mut reversals=load_order_reversals(conn, &order_ids);
+    rows.into_iter().map(|t|OrderResponse{
             order_id:t.0.order_id,
             service_name:if t.3.as_ref().unwrap().enabled { t.3.as_ref().unwrap().name.clone() } else { "-".into()}, //TODO 冗余  已删除
             consumer_type: if t.0.consumer_type =="member" { "会员".into() } else {"进店顾客".into()},
@@ -99,17 +326,71 @@ pub async fn get_orders(
                 },
             amount:t.0.amount,
             total_minutes:(t.0.end_time-t.0.start_time).num_minutes(),
-            payment_type: if t.0.payment_type=="member" {"会员充值".into()} else {"现金".into()},
+            payment_type: payment_type::display_name(&t.0.payment_type).into(),
             barber_name: if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone() } else {"-".into() },
+            status:order_status::display_name(&t.0.status).into(),
+            lines:lines.remove(&t.0.order_id).unwrap_or_default(),
+            payments:payments.remove(&t.0.order_id).unwrap_or_default(),
+            tips:tips.remove(&t.0.order_id).unwrap_or_default(),
+            reversed_by:reversals.remove(&t.0.order_id),
+            reversal_of:t.0.reversal_of,
+            reversal_reason:t.0.reversal_reason,
             create_time:t.0.create_time,
-        }).collect())
+        }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderListResponse{
+    #[serde(flatten)]
+    pub list:PaginatedListResponse<OrderResponse>,
+
+    pub total_amount:BigDecimal, // 筛选结果中已完成订单及其退款记录的金额合计，退款记录为负数
+}
+
+pub async fn get_orders(
+    State(pg):State<AxumPg>,
+    Query(params):Query<PaginatedListRequest>, 
+    Query(search):Query<Search>, 
+    Query(filter):Query<StatisticFilter>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<OrderListResponse>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+    
+    filter.validate()?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let count=filtered_orders(merchant_id, &search, &filter)
+        .count()
+        .get_result(&mut *conn)
         .unwrap();
+    let total_amount=filtered_orders(merchant_id, &search, &filter)
+        .filter(is_completed_sale())
+        .select(diesel::dsl::sum(orders::amount))
+        .get_result::<Option<BigDecimal>>(&mut *conn)
+        .unwrap()
+        .unwrap_or_default();
+    let rows=order_rows()
+        .filter(orders::id.eq_any(filtered_orders(merchant_id, &search, &filter).select(orders::id)))
+        .order(orders::create_time.desc())
+        .limit(params.page_size)
+        .offset(params.page_index*params.page_size)
+        .get_results::<OrderRow>(&mut *conn)
+        .unwrap();
+    let data=order_responses(&mut conn, rows);
     
-    Ok(Json(PaginatedListResponse{
-        page_index:params.page_index,
-        page_size:params.page_size,
-        total_count:count,
-        data:data,
+    Ok(Json(OrderListResponse{
+        list:PaginatedListResponse{
+            page_index:params.page_index,
+            page_size:params.page_size,
+            total_count:count,
+            data:data,
+        },
+        total_amount,
     }))
 }
 
@@ -123,65 +404,695 @@ pub struct RechargeRecordResponse{
 
     pub member_cellphone:String,
     
-    pub amount:BigDecimal,
+    pub amount:BigDecimal, // 实付本金
+
+    pub bonus:BigDecimal, // 赠送金额
+
+    pub tender:String,
+
+    pub tender_name:String,
 
     pub barber_name:String,
 
+    pub reversal_of:Option<Uuid>, // 撤销记录对应的原充值记录
+
+    pub reversal_reason:Option<String>,
+
+    pub reversed_by:Option<Uuid>, // 原充值记录对应的撤销记录
+
     #[serde(with = "my_date_format")]
     pub crate_time:chrono::DateTime<Local>,
 }
 
+// 按搜索条件和筛选条件过滤充值记录，列表和导出共用
+pub fn filtered_recharge_records<'a>(merchant_id:Uuid,search:&'a Search,filter:&'a StatisticFilter)->recharge_records::BoxedQuery<'a,Pg>{
+    let mut query=recharge_records::table
+        .filter(recharge_records::enabled.eq(true))
+        .filter(recharge_records::merchant_id.eq(merchant_id))
+        .into_boxed();
+    
+    if let Some(key)=search.key.as_ref() {
+        query=query.filter(recharge_records::member_id.eq_any(
+            merchant_members::table
+                .filter(merchant_members::real_name.ilike(format!("%{key}%")))
+                .select(merchant_members::member_id)
+        ));
+    }
+    if let Some(start_date)=filter.start_date {
+        query=query.filter(recharge_records::create_time.ge(start_date));
+    }
+    if let Some(end_date)=filter.end_date {
+        query=query.filter(recharge_records::create_time.lt(end_date));
+    }
+    if let Some(barber_id)=filter.barber_id {
+        query=query.filter(recharge_records::barber_id.eq(barber_id));
+    }
+    if let Some(tender)=filter.payment_type.as_ref() {
+        query=query.filter(recharge_records::tender.eq(tender));
+    }
+    if let Some(min_amount)=filter.min_amount.as_ref() {
+        query=query.filter(recharge_records::amount.ge(min_amount));
+    }
+    if let Some(max_amount)=filter.max_amount.as_ref() {
+        query=query.filter(recharge_records::amount.le(max_amount));
+    }
+
+    query
+}
+
+pub type RechargeRecordRow=(RechargeRecord,Option<MerchantMember>,Option<Barber>);
+
+type RechargeRecordRowsQuery=diesel::dsl::LeftJoinOn<diesel::dsl::LeftJoinOn<recharge_records::table,merchant_members::table,diesel::dsl::Eq<recharge_records::member_id,merchant_members::member_id>>,barbers::table,diesel::dsl::Eq<recharge_records::barber_id,barbers::barber_id>>;
+
+// 充值记录关联会员和理发师，需再按 filtered_recharge_records 过滤
+pub fn recharge_record_rows()->RechargeRecordRowsQuery{
+    recharge_records::table
+        .left_join(merchant_members::table.on(recharge_records::member_id.eq(merchant_members::member_id)))
+        .left_join(barbers::table.on(recharge_records::barber_id.eq(barbers::barber_id)))
+}
+
+pub fn recharge_record_responses(conn:&mut PgConnection,rows:Vec<RechargeRecordRow>)->Vec<RechargeRecordResponse>{
+    let mut reversals=load_recharge_reversals(conn, &rows.iter().map(|t|t.0.recharge_record_id).collect::<Vec<_>>());
+    rows.into_iter().map(|t|RechargeRecordResponse{
+            recharge_record_id:t.0.recharge_record_id,
+            member_name: if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().real_name.clone()} else { "-".into()},
+            member_cellphone:if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().cellphone.clone()} else { "-".into()},
+            amount:t.0.amount,
+            bonus:t.0.bonus,
+            tender_name:payment_type::display_name(&t.0.tender).into(),
+            tender:t.0.tender,
+            barber_name:if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone()} else { "-".into()},
+            reversed_by:reversals.remove(&t.0.recharge_record_id),
+            reversal_of:t.0.reversal_of,
+            reversal_reason:t.0.reversal_reason,
+            crate_time:t.0.create_time,
+        }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeRecordListResponse{
+    #[serde(flatten)]
+    pub list:PaginatedListResponse<RechargeRecordResponse>,
+
+    pub total_amount:BigDecimal, // 筛选结果的实付本金合计，撤销记录为负数
+
+    pub total_bonus:BigDecimal,
+}
+
 pub async fn get_recharge_records(
     State(pg):State<AxumPg>,
     Query(params):Query<PaginatedListRequest>, 
     Query(search):Query<Search>, 
+    Query(filter):Query<StatisticFilter>, 
     auth: AuthSession<AxumPg, AxumPg,User>,
-)->Result<Json<PaginatedListResponse<RechargeRecordResponse>>,(StatusCode,String)>{
+)->Result<Json<RechargeRecordListResponse>,(StatusCode,String)>{
     //检查登录&权限
     auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
     
+    filter.validate_for_recharge_records()?;
+
     let mut conn=pg.pool.get().unwrap();
 
     let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
 
-    let fn_get_query=||{
-        let mut query=recharge_records::table
-        .left_join(merchant_members::table.on(recharge_records::member_id.eq(merchant_members::member_id)))
-        .left_join(barbers::table.on(recharge_records::barber_id.eq(barbers::barber_id)))
-        .filter(recharge_records::enabled.eq(true))
-        .filter(recharge_records::merchant_id.eq(merchant_id))
-        .into_boxed();
-        
-        if let Some(key)=search.key.as_ref() {
-            query=query.filter(merchant_members::real_name.ilike(format!("%{key}%"))); 
-        }
-
-        query
-    };
-   
-    let count=fn_get_query()
+    let count=filtered_recharge_records(merchant_id, &search, &filter)
         .count()
         .get_result(&mut *conn)
         .unwrap();
-    let data=fn_get_query()
+    let (total_amount,total_bonus)=filtered_recharge_records(merchant_id, &search, &filter)
+        .select((diesel::dsl::sum(recharge_records::amount),diesel::dsl::sum(recharge_records::bonus)))
+        .get_result::<(Option<BigDecimal>,Option<BigDecimal>)>(&mut *conn)
+        .unwrap();
+    let rows=recharge_record_rows()
+        .filter(recharge_records::id.eq_any(filtered_recharge_records(merchant_id, &search, &filter).select(recharge_records::id)))
         .order(recharge_records::create_time.desc())
         .limit(params.page_size)
         .offset(params.page_index*params.page_size)
-        .get_results::<(RechargeRecord,Option<MerchantMember>,Option<Barber>)>(&mut *conn)
-        .map(|v|v.into_iter().map(|t|RechargeRecordResponse{
-            recharge_record_id:t.0.recharge_record_id,
-            member_name: if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().real_name.clone()} else { "-".into()},
-            member_cellphone:if t.1.as_ref().unwrap().enabled {t.1.as_ref().unwrap().cellphone.clone()} else { "-".into()},
-            amount:t.0.amount,
-            barber_name:if t.2.as_ref().unwrap().enabled {t.2.as_ref().unwrap().real_name.clone()} else { "-".into()},
-            crate_time:t.0.create_time,
-        }).collect())
+        .get_results::<RechargeRecordRow>(&mut *conn)
         .unwrap();
+    let data=recharge_record_responses(&mut conn, rows);
     
-    Ok(Json(PaginatedListResponse{
-        page_index:params.page_index,
-        page_size:params.page_size,
-        total_count:count,
-        data:data,
+    Ok(Json(RechargeRecordListResponse{
+        list:PaginatedListResponse{
+            page_index:params.page_index,
+            page_size:params.page_size,
+            total_count:count,
+            data:data,
+        },
+        total_amount:total_amount.unwrap_or_default(),
+        total_bonus:total_bonus.unwrap_or_default(),
     }))
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenderSummaryRequest{
+    pub start_date:chrono::DateTime<Local>,
+
+    pub end_date:chrono::DateTime<Local>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenderSummary{
+    pub tender:String,
+
+    pub tender_name:String,
+
+    pub amount:BigDecimal, // 扣除退款后的金额
+
+    pub order_count:i64, // 使用该支付方式的订单数，不含退款记录
+
+    pub reversal_amount:BigDecimal, // 退款金额，为负数
+
+    pub reversal_count:i64,
+
+    pub tip_amount:BigDecimal, // 小费单独统计，不计入营业额
+}
+
+impl TenderSummary{
+    fn new(tender:String)->Self{
+        TenderSummary{
+            tender_name:payment_type::display_name(&tender).into(),
+            tender,
+            amount:BigDecimal::default(),
+            order_count:0,
+            reversal_amount:BigDecimal::default(),
+            reversal_count:0,
+            tip_amount:BigDecimal::default(),
+        }
+    }
+}
+
+// 按支付方式汇总营业额和小费，与营业额汇总一致只计已完成的订单及其退款记录，按完成时间统计，退款单独统计
+pub async fn get_tender_summary(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<TenderSummary>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+    
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let fn_get_query=||{
+        order_payments::table
+            .inner_join(orders::table.on(order_payments::order_id.eq(orders::order_id)))
+            .filter(order_payments::enabled.eq(true))
+            .filter(orders::enabled.eq(true))
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::status.eq_any([order_status::COMPLETED,order_status::REVERSAL]))
+            .filter(orders::complete_time.ge(params.start_date).and(orders::complete_time.lt(params.end_date)))
+    };
+    let payments=fn_get_query()
+        .filter(orders::reversal_of.is_null())
+        .group_by(order_payments::tender)
+        .select((order_payments::tender,diesel::dsl::sum(order_payments::amount),diesel::dsl::count_distinct(order_payments::order_id)))
+        .get_results::<(String,Option<BigDecimal>,i64)>(&mut *conn)
+        .unwrap();
+    let reversals=fn_get_query()
+        .filter(orders::reversal_of.is_not_null())
+        .group_by(order_payments::tender)
+        .select((order_payments::tender,diesel::dsl::sum(order_payments::amount),diesel::dsl::count_distinct(order_payments::order_id)))
+        .get_results::<(String,Option<BigDecimal>,i64)>(&mut *conn)
+        .unwrap();
+    let tips=order_tips::table
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::merchant_id.eq(merchant_id))
+        .filter(order_tips::create_time.ge(params.start_date).and(order_tips::create_time.lt(params.end_date)))
+        .group_by(order_tips::tender)
+        .select((order_tips::tender,diesel::dsl::sum(order_tips::amount)))
+        .get_results::<(String,Option<BigDecimal>)>(&mut *conn)
+        .unwrap();
+
+    let mut summaries=BTreeMap::new();
+    for (tender,amount,order_count) in payments {
+        let summary=summaries.entry(tender.clone()).or_insert_with(||TenderSummary::new(tender));
+        summary.amount=amount.unwrap_or_default();
+        summary.order_count=order_count;
+    }
+    for (tender,reversal_amount,reversal_count) in reversals {
+        let summary=summaries.entry(tender.clone())
+            .or_insert_with(||TenderSummary::new(tender));
+        let reversal_amount=reversal_amount.unwrap_or_default();
+        summary.amount+=&reversal_amount;
+        summary.reversal_amount=reversal_amount;
+        summary.reversal_count=reversal_count;
+    }
+    for (tender,tip_amount) in tips {
+        summaries.entry(tender.clone())
+            .or_insert_with(||TenderSummary::new(tender))
+            .tip_amount=tip_amount.unwrap_or_default();
+    }
+
+    Ok(Json(summaries.into_values().collect()))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberEarnings{
+    pub barber_id:Uuid,
+
+    pub barber_name:String,
+
+    pub service_count:i64, // 退款的服务不计入
+
+    pub service_amount:BigDecimal, // 服务营业额，不含小费
+
+    pub tip_amount:BigDecimal,
+
+    pub earnings:BigDecimal, // 服务营业额 + 小费
+}
+
+// 按理发师汇总服务营业额和小费，服务与工资报表一致只计已完成的订单及其退款记录，按完成时间统计
+pub async fn get_barber_earnings(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<BarberEarnings>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+    
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let lines=order_lines::table
+        .inner_join(orders::table.on(order_lines::order_id.eq(orders::order_id)))
+        .filter(order_lines::enabled.eq(true))
+        .filter(orders::enabled.eq(true))
+        .filter(orders::merchant_id.eq(merchant_id))
+        .filter(orders::status.eq_any([order_status::COMPLETED,order_status::REVERSAL]))
+        .filter(orders::complete_time.ge(params.start_date).and(orders::complete_time.lt(params.end_date)))
+        .select((order_lines::barber_id,order_lines::amount,orders::reversal_of))
+        .get_results::<(Uuid,BigDecimal,Option<Uuid>)>(&mut *conn)
+        .unwrap();
+    let mut tips=order_tips::table
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::merchant_id.eq(merchant_id))
+        .filter(order_tips::create_time.ge(params.start_date).and(order_tips::create_time.lt(params.end_date)))
+        .group_by(order_tips::barber_id)
+        .select((order_tips::barber_id,diesel::dsl::sum(order_tips::amount)))
+        .get_results::<(Uuid,Option<BigDecimal>)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_,_>>();
+
+    let barbers=barbers::table
+        .filter(barbers::merchant_id.eq(merchant_id))
+        .order(barbers::create_time.asc())
+        .get_results::<Barber>(&mut *conn)
+        .unwrap();
+    let mut services:HashMap<Uuid,(i64,BigDecimal)>=HashMap::new();
+    for (barber_id,amount,reversal_of) in lines {
+        let service=services.entry(barber_id).or_default();
+        service.0+=if reversal_of.is_some() { -1 } else { 1 };
+        service.1+=amount;
+    }
+
+    // 已删除但在统计期间内有业绩的理发师仍需列出
+    let data=barbers.into_iter()
+        .filter_map(|b|{
+            let service=services.remove(&b.barber_id);
+            let tip_amount=tips.remove(&b.barber_id).flatten();
+            if !b.enabled && service.is_none() && tip_amount.is_none() {
+                return None;
+            }
+            let (service_count,service_amount)=service.unwrap_or_default();
+            let tip_amount=tip_amount.unwrap_or_default();
+            Some(BarberEarnings{
+                barber_id:b.barber_id,
+                barber_name:b.real_name,
+                service_count,
+                earnings:&service_amount+&tip_amount,
+                service_amount,
+                tip_amount,
+            })
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponSummary{
+    pub coupon_id:Uuid,
+
+    pub coupon_name:String,
+
+    pub redemption_count:i64,
+
+    pub discount:BigDecimal, // 优惠金额合计，即优惠券成本
+}
+
+// 按优惠券汇总使用次数和优惠金额，已取消预约的使用记录不计入
+pub async fn get_coupon_summary(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>, 
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<CouponSummary>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+    
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let summaries=coupon_redemptions::table
+        .filter(coupon_redemptions::enabled.eq(true))
+        .filter(coupon_redemptions::merchant_id.eq(merchant_id))
+        .filter(coupon_redemptions::create_time.ge(params.start_date).and(coupon_redemptions::create_time.lt(params.end_date)))
+        .group_by(coupon_redemptions::coupon_id)
+        .select((coupon_redemptions::coupon_id,diesel::dsl::count(coupon_redemptions::id),diesel::dsl::sum(coupon_redemptions::discount)))
+        .get_results::<(Uuid,i64,Option<BigDecimal>)>(&mut *conn)
+        .unwrap();
+    let names=coupons::table
+        .filter(coupons::coupon_id.eq_any(summaries.iter().map(|s|s.0)))
+        .select((coupons::coupon_id,coupons::name))
+        .get_results::<(Uuid,String)>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_,_>>();
+
+    let data=summaries.into_iter()
+        .map(|(coupon_id,redemption_count,discount)|CouponSummary{
+            coupon_id,
+            coupon_name:names.get(&coupon_id).cloned().unwrap_or_default(),
+            redemption_count,
+            discount:discount.unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[derive(Deserialize)]
+pub struct PeriodRequest{
+    pub period:Option<String>, // day / week / month，默认 day
+}
+
+impl PeriodRequest{
+    fn period(&self)->Result<&str,(StatusCode,String)>{
+        match self.period.as_deref().unwrap_or("day") {
+            p @ ("day"|"week"|"month")=>Ok(p),
+            p=>Err((StatusCode::BAD_REQUEST,format!("不支持的统计周期 {p}"))),
+        }
+    }
+}
+
+// 统计区间内各周期的起始日期，按周统计时以周一为起始
+pub fn period_starts(period:&str,start_date:NaiveDate,end_date:NaiveDate)->Vec<NaiveDate>{
+    let mut date=match period {
+        "week"=>start_date-Duration::days(start_date.weekday().num_days_from_monday() as i64),
+        "month"=>start_date.with_day(1).unwrap(),
+        _=>start_date,
+    };
+    let mut dates=Vec::new();
+    while date<=end_date {
+        dates.push(date);
+        date=match period {
+            "week"=>date+Duration::days(7),
+            "month"=>if date.month()==12 {
+                NaiveDate::from_ymd_opt(date.year()+1, 1, 1).unwrap()
+            } else {
+                NaiveDate::from_ymd_opt(date.year(), date.month()+1, 1).unwrap()
+            },
+            _=>date+Duration::days(1),
+        };
+    }
+    dates
+}
+
+// 按本地时区划分周期，如 +08:00
+pub fn local_utc_offset()->String{
+    let seconds=Local::now().offset().local_minus_utc();
+    let sign=if seconds<0 { '-' } else { '+' };
+    format!("{sign}{:02}:{:02}",seconds.abs()/3600,seconds.abs()%3600/60)
+}
+
+pub fn local_date_range(params:&TenderSummaryRequest)->(NaiveDate,NaiveDate){
+    (
+        params.start_date.naive_local().date(),
+        (params.end_date-Duration::microseconds(1)).naive_local().date(),
+    )
+}
+
+#[derive(QueryableByName)]
+struct PeriodTotal{
+    #[diesel(sql_type=Date)]
+    period_start:NaiveDate,
+
+    #[diesel(sql_type=BigInt)]
+    count:i64,
+
+    #[diesel(sql_type=Numeric)]
+    amount:BigDecimal,
+
+    #[diesel(sql_type=Numeric)]
+    extra:BigDecimal,
+}
+
+#[derive(QueryableByName)]
+struct PeriodTenderTotal{
+    #[diesel(sql_type=Date)]
+    period_start:NaiveDate,
+
+    #[diesel(sql_type=Text)]
+    tender:String,
+
+    #[diesel(sql_type=Numeric)]
+    amount:BigDecimal,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenderAmount{
+    pub tender:String,
+
+    pub tender_name:String,
+
+    pub amount:BigDecimal,
+}
+
+fn group_tenders(rows:Vec<PeriodTenderTotal>)->HashMap<NaiveDate,Vec<TenderAmount>>{
+    rows.into_iter()
+        .fold(HashMap::new(),|mut tenders:HashMap<NaiveDate,Vec<TenderAmount>>,t|{
+            tenders.entry(t.period_start).or_default().push(TenderAmount{
+                tender_name:payment_type::display_name(&t.tender).into(),
+                tender:t.tender,
+                amount:t.amount,
+            });
+            tenders
+        })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevenueSummary{
+    pub period_start:NaiveDate,
+
+    pub order_count:i64, // 不含退款记录
+
+    pub revenue:BigDecimal, // 已扣除退款
+
+    pub average_ticket:BigDecimal, // 客单价
+
+    pub tenders:Vec<TenderAmount>, // 按支付方式拆分，会员充值即储值消费
+}
+
+// 按日/周/月汇总营业额，只计已完成的订单及其退款记录，按完成时间（退款记录为退款时间）归入各期，退款记录为负数
+pub async fn get_revenue_summary(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>,
+    Query(period):Query<PeriodRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<RevenueSummary>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let period=period.period()?;
+
+    let mut totals=diesel::sql_query(
+        "SELECT date_trunc($1, o.complete_time AT TIME ZONE $2::interval)::date AS period_start, \
+            COUNT(*) FILTER (WHERE o.reversal_of IS NULL) AS count, \
+            COALESCE(SUM(o.amount),0) AS amount, \
+            COALESCE(SUM(o.amount) FILTER (WHERE o.reversal_of IS NULL),0) AS extra \
+        FROM orders o \
+        WHERE o.enabled AND o.merchant_id=$3 AND o.status IN ($4,$5) AND o.complete_time>=$6 AND o.complete_time<$7 \
+        GROUP BY 1")
+        .bind::<Text,_>(period)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<diesel::sql_types::Uuid,_>(merchant_id)
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<Text,_>(order_status::REVERSAL)
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .load::<PeriodTotal>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|t|(t.period_start,t))
+        .collect::<HashMap<_,_>>();
+    let mut tenders=group_tenders(diesel::sql_query(
+        "SELECT date_trunc($1, o.complete_time AT TIME ZONE $2::interval)::date AS period_start, \
+            p.tender, COALESCE(SUM(p.amount),0) AS amount \
+        FROM order_payments p INNER JOIN orders o ON p.order_id=o.order_id \
+        WHERE p.enabled AND o.enabled AND o.merchant_id=$3 AND o.status IN ($4,$5) AND o.complete_time>=$6 AND o.complete_time<$7 \
+        GROUP BY 1,2 ORDER BY 1,2")
+        .bind::<Text,_>(period)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<diesel::sql_types::Uuid,_>(merchant_id)
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<Text,_>(order_status::REVERSAL)
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .load::<PeriodTenderTotal>(&mut *conn)
+        .unwrap());
+
+    let (start_date,end_date)=local_date_range(&params);
+    let data=period_starts(period, start_date, end_date).into_iter()
+        .map(|period_start|{
+            let (order_count,revenue,sales)=totals.remove(&period_start)
+                .map(|t|(t.count,t.amount,t.extra))
+                .unwrap_or_default();
+            RevenueSummary{
+                period_start,
+                order_count,
+                average_ticket:if order_count>0 { (sales/BigDecimal::from(order_count)).round(2) } else { BigDecimal::default() },
+                revenue,
+                tenders:tenders.remove(&period_start).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeSummary{
+    pub period_start:NaiveDate,
+
+    pub recharge_count:i64, // 不含撤销记录
+
+    pub recharge_amount:BigDecimal, // 实付本金，已扣除撤销
+
+    pub bonus_amount:BigDecimal, // 赠送金额，已扣除撤销
+
+    pub tenders:Vec<TenderAmount>, // 实付本金按收款方式拆分
+
+    pub stored_value_consumption:BigDecimal, // 使用会员余额支付的订单及小费
+}
+
+// 按日/周/月汇总充值与储值消费
+pub async fn get_recharge_summary(
+    State(pg):State<AxumPg>,
+    Query(params):Query<TenderSummaryRequest>,
+    Query(period):Query<PeriodRequest>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+)->Result<Json<Vec<RechargeSummary>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::STATISTIC]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let period=period.period()?;
+
+    let mut totals=diesel::sql_query(
+        "SELECT date_trunc($1, r.create_time AT TIME ZONE $2::interval)::date AS period_start, \
+            COUNT(*) FILTER (WHERE r.reversal_of IS NULL) AS count, \
+            COALESCE(SUM(r.amount),0) AS amount, \
+            COALESCE(SUM(r.bonus),0) AS extra \
+        FROM recharge_records r \
+        WHERE r.enabled AND r.merchant_id=$3 AND r.create_time>=$4 AND r.create_time<$5 \
+        GROUP BY 1")
+        .bind::<Text,_>(period)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<diesel::sql_types::Uuid,_>(merchant_id)
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .load::<PeriodTotal>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|t|(t.period_start,t))
+        .collect::<HashMap<_,_>>();
+    let mut tenders=group_tenders(diesel::sql_query(
+        "SELECT date_trunc($1, r.create_time AT TIME ZONE $2::interval)::date AS period_start, \
+            r.tender, COALESCE(SUM(r.amount),0) AS amount \
+        FROM recharge_records r \
+        WHERE r.enabled AND r.merchant_id=$3 AND r.create_time>=$4 AND r.create_time<$5 \
+        GROUP BY 1,2 ORDER BY 1,2")
+        .bind::<Text,_>(period)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<diesel::sql_types::Uuid,_>(merchant_id)
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .load::<PeriodTenderTotal>(&mut *conn)
+        .unwrap());
+    // 储值消费：已完成订单按完成时间统计会员余额支付(退款为负数)，另加小费
+    let mut consumption=diesel::sql_query(
+        "SELECT period_start, $2 AS tender, COALESCE(SUM(amount),0) AS amount FROM ( \
+            SELECT date_trunc($1, o.complete_time AT TIME ZONE $3::interval)::date AS period_start, p.amount \
+            FROM order_payments p INNER JOIN orders o ON p.order_id=o.order_id \
+            WHERE p.enabled AND p.tender=$2 AND o.enabled AND o.merchant_id=$4 AND o.status IN ($5,$8) \
+                AND o.complete_time>=$6 AND o.complete_time<$7 \
+            UNION ALL \
+            SELECT date_trunc($1, t.create_time AT TIME ZONE $3::interval)::date AS period_start, t.amount \
+            FROM order_tips t \
+            WHERE t.enabled AND t.tender=$2 AND t.merchant_id=$4 AND t.create_time>=$6 AND t.create_time<$7 \
+        ) c GROUP BY 1")
+        .bind::<Text,_>(period)
+        .bind::<Text,_>(payment_type::MEMBER)
+        .bind::<Text,_>(local_utc_offset())
+        .bind::<diesel::sql_types::Uuid,_>(merchant_id)
+        .bind::<Text,_>(order_status::COMPLETED)
+        .bind::<Timestamptz,_>(params.start_date)
+        .bind::<Timestamptz,_>(params.end_date)
+        .bind::<Text,_>(order_status::REVERSAL)
+        .load::<PeriodTenderTotal>(&mut *conn)
+        .unwrap()
+        .into_iter()
+        .map(|t|(t.period_start,t.amount))
+        .collect::<HashMap<_,_>>();
+
+    let (start_date,end_date)=local_date_range(&params);
+    let data=period_starts(period, start_date, end_date).into_iter()
+        .map(|period_start|{
+            let (recharge_count,recharge_amount,bonus_amount)=totals.remove(&period_start)
+                .map(|t|(t.count,t.amount,t.extra))
+                .unwrap_or_default();
+            RechargeSummary{
+                period_start,
+                recharge_count,
+                recharge_amount,
+                bonus_amount,
+                tenders:tenders.remove(&period_start).unwrap_or_default(),
+                stored_value_consumption:consumption.remove(&period_start).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Ok(Json(data))
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_period_starts(){
+        let date=|m,d|NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+        assert_eq!(period_starts("day", date(2,27), date(3,1)), vec![date(2,27),date(2,28),date(3,1)]);
+        assert_eq!(period_starts("week", date(3,1), date(3,13)), vec![date(2,27),date(3,6),date(3,13)]);
+        assert_eq!(period_starts("month", date(1,15), date(3,1)), vec![date(1,1),date(2,1),date(3,1)]);
+    }
+}
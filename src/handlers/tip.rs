@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, Json, extract::{Path, State}};
+use axum_session_authentication_middleware::session::AuthSession;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::*,
+    authorization_policy,
+    constant::{self, order_status, ledger_type, payment_type}
+};
+use diesel::{prelude::*, select, dsl::exists};
+use crate::{models::User, axum_pg::AxumPg};
+
+use super::{TransactionError, balance_ledger::{change_balance, current_barber_id}};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderTipResponse{
+    pub barber_id:Uuid,
+
+    pub barber_name:String,
+
+    pub tender:String,
+
+    pub tender_name:String,
+
+    pub amount:BigDecimal,
+}
+
+// 按订单分组获取小费
+pub fn load_order_tips(conn:&mut PgConnection,order_ids:&[Uuid])->HashMap<Uuid,Vec<OrderTipResponse>>{
+    order_tips::table
+        .left_join(barbers::table.on(order_tips::barber_id.eq(barbers::barber_id)))
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::order_id.eq_any(order_ids))
+        .order(order_tips::id.asc())
+        .get_results::<(OrderTip,Option<Barber>)>(conn)
+        .unwrap()
+        .into_iter()
+        .fold(HashMap::new(),|mut tips:HashMap<Uuid,Vec<OrderTipResponse>>,t|{
+            tips.entry(t.0.order_id).or_default().push(OrderTipResponse{
+                barber_id:t.0.barber_id,
+                barber_name:t.1.filter(|b|b.enabled).map(|b|b.real_name).unwrap_or_else(||"-".into()),
+                tender_name:payment_type::display_name(&t.0.tender).into(),
+                tender:t.0.tender,
+                amount:t.0.amount,
+            });
+            tips
+        })
+}
+
+// 预约取消时在原订单下按负数冲销小费，退款记录在取消时所在的班次，会员余额支付的退回余额
+pub fn cancel_order_tips(conn:&mut PgConnection,merchant_id:Uuid,order:&Order,barber_id:Option<Uuid>,reason:&str)->Result<(),TransactionError>{
+    reverse_order_tips(conn, merchant_id, order, order.order_id, barber_id, reason)
+}
+
+// 订单退款时按负数冲销小费，会员余额支付的退回余额
+pub fn reverse_order_tips(conn:&mut PgConnection,merchant_id:Uuid,order:&Order,reversal_id:Uuid,barber_id:Option<Uuid>,reason:&str)->Result<(),TransactionError>{
+    let tips=order_tips::table
+        .filter(order_tips::enabled.eq(true))
+        .filter(order_tips::order_id.eq(order.order_id))
+        .order(order_tips::id.asc())
+        .get_results::<OrderTip>(conn)?;
+
+    for tip in tips.iter() {
+        let new_tip=NewOrderTip{
+            tip_id:&Uuid::new_v4(),
+            order_id:&reversal_id,
+            merchant_id:&merchant_id,
+            barber_id:&tip.barber_id,
+            tender:&tip.tender,
+            amount:&-&tip.amount,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        diesel::insert_into(order_tips::table)
+            .values(&new_tip)
+            .execute(conn)?;
+
+        if let (true,Some(member_id))=(tip.tender==payment_type::MEMBER,order.member_id) {
+            change_balance(conn, merchant_id, member_id, &tip.amount, ledger_type::REFUND, Some(reversal_id), barber_id, Some(reason))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TipRequest{
+    pub barber_id:Option<Uuid>, // 默认为订单的理发师
+
+    pub tender:String,
+
+    pub amount:BigDecimal,
+}
+
+// 为已到店或已完成的订单添加小费
+pub async fn add_tip(
+    State(pg):State<AxumPg>,
+    Path(appointment_id):Path<Uuid>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    Json(req): Json<TipRequest>
+)->Result<Json<Vec<OrderTipResponse>>,(StatusCode,String)>{
+    //检查登录&权限
+    auth.require_permissions(vec![authorization_policy::BARBER_BASE]).map_err(|e|(StatusCode::UNAUTHORIZED,e.to_string()))?;
+
+    let mut conn=pg.pool.get().unwrap();
+
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    if req.amount<=BigDecimal::zero() {
+        return Err((StatusCode::BAD_REQUEST,"小费金额必须大于0".to_string()));
+    }
+    if !payment_type::is_tender(&req.tender) || req.tender==payment_type::PACKAGE || req.tender==payment_type::POINTS {
+        return Err((StatusCode::BAD_REQUEST,format!("不支持的支付方式 {}",req.tender)));
+    }
+
+    let operator_id=current_barber_id(&mut conn, merchant_id, auth.identity.as_ref().unwrap().user_id)
+        .optional()
+        .unwrap();
+
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let order=orders::table
+            .filter(orders::enabled.eq(true))
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::order_id.eq(appointment_id))
+            .for_update()
+            .get_result::<Order>(conn)
+            .optional()?
+            .ok_or_else(||TransactionError(StatusCode::NOT_FOUND,"预约不存在".to_string()))?;
+
+        if order.status!=order_status::CHECKED_IN && order.status!=order_status::COMPLETED {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"只有已到店或已完成的订单可以添加小费".to_string()));
+        }
+        let reversed=select(exists(
+            orders::table
+            .filter(orders::merchant_id.eq(merchant_id))
+            .filter(orders::reversal_of.eq(appointment_id))
+            ))
+            .get_result::<bool>(conn)?;
+        if reversed {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"该订单已退款".to_string()));
+        }
+
+        // 小费只能给服务该订单的理发师
+        let barber_id=req.barber_id.unwrap_or(order.barber_id);
+        let served=barber_id==order.barber_id || select(exists(
+            order_lines::table
+            .filter(order_lines::enabled.eq(true))
+            .filter(order_lines::order_id.eq(appointment_id))
+            .filter(order_lines::barber_id.eq(barber_id))
+            ))
+            .get_result::<bool>(conn)?;
+        if !served {
+            return Err(TransactionError(StatusCode::BAD_REQUEST,"该理发师未服务此订单".to_string()));
+        }
+
+        if req.tender==payment_type::MEMBER {
+            let member_id=order.member_id
+                .ok_or_else(||TransactionError(StatusCode::BAD_REQUEST,"非会员不能使用会员余额支付".to_string()))?;
+            change_balance(conn, merchant_id, member_id, &-&req.amount, ledger_type::TIP, Some(appointment_id), operator_id, None)?;
+        }
+
+        let new_tip=NewOrderTip{
+            tip_id:&Uuid::new_v4(),
+            order_id:&appointment_id,
+            merchant_id:&merchant_id,
+            barber_id:&barber_id,
+            tender:&req.tender,
+            amount:&req.amount,
+            enabled:true,
+            create_time: Local::now(),
+            update_time: Local::now(),
+            data: None,
+        };
+        diesel::insert_into(order_tips::table)
+            .values(&new_tip)
+            .execute(conn)?;
+
+        Ok(())
+    })?;
+
+    let tips=load_order_tips(&mut conn, &[appointment_id])
+        .remove(&appointment_id)
+        .unwrap_or_default();
+
+    Ok(Json(tips))
+}
@@ -0,0 +1,271 @@
+use axum::{
+    body::{self, Body, Bytes, Full, HttpBody},
+    extract::State,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_session_authentication_middleware::session::AuthSession;
+use chrono::{DateTime, Duration, Local};
+use diesel::{prelude::*, sql_types::{BigInt, Text}};
+use uuid::Uuid;
+use crate::{
+    schema::*,
+    models::{IdempotencyKey, NewIdempotencyKey, User},
+    axum_pg::AxumPg,
+    authorization_policy,
+    constant,
+    handlers::TransactionError
+};
+
+pub const IDEMPOTENCY_KEY:&str="Idempotency-Key";
+pub const IDEMPOTENT_REPLAYED:&str="Idempotent-Replayed";
+
+//保存响应的时长
+const RETENTION_HOURS:i64=24;
+
+//处理中的记录超过该时长未完成，视为已中断
+const IN_FLIGHT_MINUTES:i64=5;
+
+sql_function!(fn pg_try_advisory_xact_lock(key:BigInt)->Bool);
+sql_function!(fn hashtextextended(value:Text,seed:BigInt)->BigInt);
+
+async fn read_body<B>(mut body:B)->Result<Bytes,B::Error>
+where
+    B:HttpBody<Data=Bytes>+Unpin,
+{
+    let mut bytes=Vec::new();
+    while let Some(chunk)=body.data().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(Bytes::from(bytes))
+}
+
+// 相同 key 已有记录时，请求内容不同返回 422，仍在处理中返回 409；
+// 处理中的记录超过时限说明上次处理已中断(如服务中途退出)，允许使用相同的 key 重试
+fn check_existing(existing:&IdempotencyKey,new_key:&NewIdempotencyKey,now:DateTime<Local>)->Result<(),TransactionError>{
+    if existing.request_method!=new_key.request_method
+        || existing.request_path!=new_key.request_path
+        || existing.request_body!=new_key.request_body {
+        return Err(TransactionError(StatusCode::UNPROCESSABLE_ENTITY,"Idempotency-Key 已用于其他请求".to_string()));
+    }
+    if existing.status_code.is_none() && existing.create_time>now-Duration::minutes(IN_FLIGHT_MINUTES) {
+        return Err(TransactionError(StatusCode::CONFLICT,"相同的请求正在处理中，请稍后重试".to_string()));
+    }
+
+    Ok(())
+}
+
+// 登记请求，首次请求或接管已中断的请求返回 None；相同 key 已完成则返回保存的响应，仍在处理中或请求内容不同则返回错误。
+// 同一 key 的登记通过事务级咨询锁串行执行，事务提交后即释放连接，处理请求期间不占用连接
+fn begin_request(conn:&mut PgConnection,new_key:&NewIdempotencyKey)->Result<Option<IdempotencyKey>,TransactionError>{
+    conn.transaction::<_,TransactionError,_>(|conn|{
+        let lock_key=format!("{}:{}",new_key.merchant_id,new_key.idempotency_key);
+        let locked=diesel::select(pg_try_advisory_xact_lock(hashtextextended(lock_key,0)))
+            .get_result::<bool>(conn)?;
+        if !locked {
+            return Err(TransactionError(StatusCode::CONFLICT,"相同的请求正在处理中，请稍后重试".to_string()));
+        }
+
+        diesel::delete(
+            idempotency_keys::table
+            .filter(idempotency_keys::merchant_id.eq(new_key.merchant_id))
+            .filter(idempotency_keys::expire_time.lt(Local::now()))
+        )
+        .execute(conn)?;
+
+        let query=idempotency_keys::table
+            .filter(idempotency_keys::merchant_id.eq(new_key.merchant_id))
+            .filter(idempotency_keys::idempotency_key.eq(new_key.idempotency_key));
+        let Some(existing)=query.get_result::<IdempotencyKey>(conn).optional()? else {
+            diesel::insert_into(idempotency_keys::table)
+                .values(new_key)
+                .execute(conn)?;
+            return Ok(None);
+        };
+        check_existing(&existing, new_key, new_key.create_time)?;
+        if existing.status_code.is_some() {
+            return Ok(Some(existing));
+        }
+
+        diesel::update(query)
+            .set((
+                idempotency_keys::create_time.eq(new_key.create_time),
+                idempotency_keys::expire_time.eq(new_key.expire_time)
+            ))
+            .execute(conn)?;
+
+        Ok(None)
+    })
+}
+
+// 服务端错误和 409 不保存响应，允许客户端使用相同的 key 重试
+fn is_replayable(status:StatusCode)->bool{
+    !status.is_server_error() && status!=StatusCode::CONFLICT
+}
+
+// 非 UTF-8 的响应(如 GB18030 编码的小票)无法原样保存，同样删除记录，重试时重新处理
+fn finish_request(conn:&mut PgConnection,merchant_id:Uuid,key:&str,status:StatusCode,content_type:Option<&str>,body:&[u8])->QueryResult<usize>{
+    let query=idempotency_keys::table
+        .filter(idempotency_keys::merchant_id.eq(merchant_id))
+        .filter(idempotency_keys::idempotency_key.eq(key))
+        .filter(idempotency_keys::status_code.is_null());
+
+    match std::str::from_utf8(body) {
+        Ok(body) if is_replayable(status)=>diesel::update(query)
+            .set((
+                idempotency_keys::status_code.eq(status.as_u16() as i32),
+                idempotency_keys::response_content_type.eq(content_type),
+                idempotency_keys::response_body.eq(body)
+            ))
+            .execute(conn),
+        _=>diesel::delete(query).execute(conn),
+    }
+}
+
+fn replay(record:IdempotencyKey)->Response{
+    let status=StatusCode::from_u16(record.status_code.unwrap_or_default() as u16).unwrap_or(StatusCode::OK);
+    let mut response=(status,record.response_body.unwrap_or_default()).into_response();
+    if let Some(content_type)=record.response_content_type.and_then(|c|HeaderValue::from_str(&c).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response.headers_mut().insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    response
+}
+
+// 带有 Idempotency-Key 的 POST/DELETE 请求，在保存期内重复提交时直接返回第一次的响应
+pub async fn idempotency(
+    State(pg):State<AxumPg>,
+    auth: AuthSession<AxumPg, AxumPg,User>,
+    req:Request<Body>,
+    next:Next<Body>,
+)->Response{
+    if req.method()!=Method::POST && req.method()!=Method::DELETE {
+        return next.run(req).await;
+    }
+    let key=match req.headers().get(IDEMPOTENCY_KEY).map(|v|v.to_str().map(str::trim)) {
+        None=>return next.run(req).await,
+        Some(Ok(key)) if !key.is_empty() && key.len()<=255=>key.to_string(),
+        Some(_)=>return (StatusCode::BAD_REQUEST,"Idempotency-Key 无效".to_string()).into_response(),
+    };
+    // 未登录的请求由处理函数返回错误
+    if auth.require_permissions(vec![authorization_policy::BARBER_BASE]).is_err() {
+        return next.run(req).await;
+    }
+    let merchant_id=Uuid::parse_str(auth.axum_session.lock().unwrap().get_data(constant::MERCHANT_ID)).unwrap();
+
+    let (parts,body)=req.into_parts();
+    let body=match read_body(body).await {
+        Ok(body)=>body,
+        Err(e)=>return (StatusCode::BAD_REQUEST,e.to_string()).into_response(),
+    };
+    let request_body=String::from_utf8_lossy(&body).to_string();
+    let method=parts.method.to_string();
+    let new_key=NewIdempotencyKey{
+        merchant_id:&merchant_id,
+        idempotency_key:&key,
+        request_method:&method,
+        request_path:parts.uri.path(),
+        request_body:&request_body,
+        create_time: Local::now(),
+        expire_time: Local::now()+Duration::hours(RETENTION_HOURS),
+    };
+    let result=begin_request(&mut pg.pool.get().unwrap(), &new_key);
+    match result {
+        Ok(None)=>{},
+        Ok(Some(record))=>return replay(record),
+        Err(e)=>return (e.0,e.1).into_response(),
+    }
+
+    let response=next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let (parts,body)=response.into_parts();
+    let body=read_body(body).await.unwrap_or_default();
+    let content_type=parts.headers.get(header::CONTENT_TYPE).and_then(|c|c.to_str().ok());
+    // 保存失败时仍返回本次的响应，记录保持处理中，超过时限后可使用相同的 key 重试
+    if let Err(e)=finish_request(&mut pg.pool.get().unwrap(), merchant_id, &key, parts.status, content_type, &body) {
+        tracing::error!("save idempotent response {} error: {}",key,e);
+    }
+
+    Response::from_parts(parts, body::boxed(Full::from(body)))
+}
+
+#[cfg(test)]
+mod test{
+    use axum::http::{header, StatusCode};
+    use chrono::{Duration, Local};
+    use uuid::Uuid;
+    use crate::models::{IdempotencyKey, NewIdempotencyKey};
+    use super::{IDEMPOTENT_REPLAYED, IN_FLIGHT_MINUTES, check_existing, is_replayable, replay};
+
+    fn record(request_body:&str,status_code:Option<i32>)->IdempotencyKey{
+        IdempotencyKey{
+            id:1,
+            merchant_id:Uuid::nil(),
+            idempotency_key:"key".into(),
+            request_method:"POST".into(),
+            request_path:"/appointments".into(),
+            request_body:request_body.into(),
+            status_code,
+            response_content_type:status_code.map(|_|"application/json".into()),
+            response_body:status_code.map(|_|r#"{"id":1}"#.into()),
+            create_time:Local::now(),
+            expire_time:Local::now(),
+        }
+    }
+
+    fn new_key<'a>(merchant_id:&'a Uuid,request_body:&'a str)->NewIdempotencyKey<'a>{
+        NewIdempotencyKey{
+            merchant_id,
+            idempotency_key:"key",
+            request_method:"POST",
+            request_path:"/appointments",
+            request_body,
+            create_time:Local::now(),
+            expire_time:Local::now(),
+        }
+    }
+
+    #[test]
+    fn test_replay(){
+        let merchant_id=Uuid::nil();
+        let existing=record(r#"{"amount":30}"#, Some(200));
+        assert!(check_existing(&existing, &new_key(&merchant_id, r#"{"amount":30}"#), Local::now()).is_ok());
+
+        let response=replay(existing);
+        assert_eq!(response.status(),StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE],"application/json");
+        assert_eq!(response.headers()[IDEMPOTENT_REPLAYED],"true");
+    }
+
+    #[test]
+    fn test_mismatched_request(){
+        let merchant_id=Uuid::nil();
+        let error=check_existing(&record(r#"{"amount":30}"#, Some(200)), &new_key(&merchant_id, r#"{"amount":40}"#), Local::now()).err().unwrap();
+        assert_eq!(error.0,StatusCode::UNPROCESSABLE_ENTITY);
+
+        // 请求内容不同时，即使前一个请求仍在处理中也返回 422
+        let error=check_existing(&record(r#"{"amount":30}"#, None), &new_key(&merchant_id, r#"{"amount":40}"#), Local::now()).err().unwrap();
+        assert_eq!(error.0,StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn test_in_flight(){
+        let merchant_id=Uuid::nil();
+        let error=check_existing(&record(r#"{"amount":30}"#, None), &new_key(&merchant_id, r#"{"amount":30}"#), Local::now()).err().unwrap();
+        assert_eq!(error.0,StatusCode::CONFLICT);
+
+        // 超过时限仍未完成，视为已中断，允许重试
+        let later=Local::now()+Duration::minutes(IN_FLIGHT_MINUTES+1);
+        assert!(check_existing(&record(r#"{"amount":30}"#, None), &new_key(&merchant_id, r#"{"amount":30}"#), later).is_ok());
+    }
+
+    #[test]
+    fn test_server_error_not_replayed(){
+        assert!(is_replayable(StatusCode::OK));
+        assert!(is_replayable(StatusCode::BAD_REQUEST));
+        assert!(!is_replayable(StatusCode::CONFLICT));
+        assert!(!is_replayable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_replayable(StatusCode::SERVICE_UNAVAILABLE));
+    }
+}
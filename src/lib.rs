@@ -6,6 +6,9 @@ pub mod authorization_policy;
 pub mod handlers;
 pub mod constant;
 pub mod regex_constants;
+pub mod idempotency;
+pub mod payment_provider;
+pub mod spreadsheet;
 
 pub mod my_option_date_format {
     use chrono::{DateTime, Local, TimeZone};
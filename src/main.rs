@@ -1,5 +1,5 @@
 use std::{net::SocketAddr, str::FromStr};
-use axum::{Router, routing::{get, post}, http::{HeaderValue, header, Method}};
+use axum::{Router, routing::{get, post, delete}, http::{HeaderValue, header, Method}, middleware};
 use axum_session_authentication_middleware::layer::AuthSessionLayer;
 use axum_session_middleware::{layer::AxumSessionLayer, session_store::AxumSessionStore, config::AxumSessionConfig};
 
@@ -9,17 +9,32 @@ use tracing_subscriber::{layer::SubscriberExt,util::SubscriberInitExt};
 
 use dotenvy::dotenv;
 
-use meli_backend::{ axum_pg::AxumPg, models::User, utils::get_connection_pool, handlers::*};
+use meli_backend::{ axum_pg::AxumPg, models::User, utils::get_connection_pool, handlers::*, idempotency::{self, idempotency, IDEMPOTENCY_KEY}};
 
 use appointment::*;
+use balance_ledger::*;
 use barber::*;
+use business_hour::*;
+use commission::*;
+use coupon::*;
 use identity::*;
 use login::*;
 use member::*;
+use member_level::*;
 use merchant::*;
+use payment::*;
+use points::*;
+use receipt::*;
+use recharge_tier::*;
 use register::*;
+use schedule::*;
+use service_package::*;
 use service_type::*;
+use shift::*;
 use statistic::*;
+use export::*;
+use retention::*;
+use tip::*;
 
 #[tokio::main]
 async fn main(){
@@ -55,31 +70,90 @@ async fn main(){
         .route("/merchant/current", get(get_current_merchant))
         .route("/merchant/barbers", get(get_barbers).post(add_barber))
         .route("/merchant/barber/:barber_id", get(get_barber).post(update_barber).delete(delete_barber))
+        .route("/merchant/barber/schedule/:barber_id", get(get_barber_schedule).post(update_barber_working_hours))
+        .route("/merchant/barber/schedule_overrides/:barber_id", post(add_barber_schedule_override))
+        .route("/merchant/barber/schedule_override/:override_id", delete(delete_barber_schedule_override))
+        .route("/merchant/barber/pay_setting/:barber_id", get(get_barber_pay_setting).post(update_barber_pay_setting))
 
         .route("/merchant/get_all_permissions", get(get_all_permissions))
 
+        .route("/merchant/business_hours", get(get_business_hours).post(update_business_hours))
+        .route("/merchant/closures", get(get_closures).post(add_closure))
+        .route("/merchant/closure/:closure_id", delete(delete_closure))
+        .route("/merchant/points_setting", get(get_points_setting).post(update_points_setting))
+
         .route("/members", get(get_members).post(add_member))
         .route("/member/:member_id", get(get_member).post(update_member).delete(delete_member))
         .route("/member/recharge/:member_id", post(recharge))
+        .route("/member/recharge_reversal/:recharge_record_id", post(reverse_recharge))
+        .route("/member/packages/:member_id", post(buy_package))
+        .route("/member/level/:member_id", post(set_member_level))
+        .route("/member/points/:member_id", get(get_points_by_member_id))
+        .route("/member/points_conversion/:member_id", post(convert_points))
+        .route("/member/coupons/:member_id", get(get_coupons_by_member_id))
 
         .route("/member/orders/:member_id", get(get_orders_by_member_id))
         .route("/member/recharge_records/:member_id", get(get_recharge_records_by_member_id))
+        .route("/member/balance_ledgers/:member_id", get(get_balance_ledgers_by_member_id))
+        .route("/member/balance_adjustment/:member_id", post(adjust_balance))
 
         .route("/service_types", get(get_service_types).post(add_service_type))
         .route("/service_type/:service_type_id", get(get_service_type).post(update_service_type).delete(delete_service_type))
+        .route("/recharge_tiers", get(get_recharge_tiers).post(add_recharge_tier))
+        .route("/service_packages", get(get_service_packages).post(add_service_package))
+        .route("/service_package/:package_id", delete(delete_service_package))
+        .route("/recharge_tier/:recharge_tier_id", post(update_recharge_tier).delete(delete_recharge_tier))
+        .route("/member_levels", get(get_member_levels).post(add_member_level))
+        .route("/member_level/:member_level_id", post(update_member_level).delete(delete_member_level))
+        .route("/coupons", get(get_coupons).post(add_coupon))
+        .route("/coupon/:coupon_id", delete(delete_coupon))
+        .route("/coupon/issue/:coupon_id", post(issue_coupon))
+        .route("/commission_rules", get(get_commission_rules).post(set_commission_rule))
+        .route("/commission_rule/:rule_id", delete(delete_commission_rule))
         
         .route("/appointments",get(get_appointments).post(add_appointment))
-        .route("/appointment/:appointment_id",get(get_appointment))
+        .route("/appointments/available_slots",get(get_available_slots))
+        .route("/appointment/:appointment_id",get(get_appointment).post(update_appointment).delete(cancel_appointment))
+        .route("/appointment/reversal/:appointment_id",post(reverse_order))
+        .route("/appointment/tips/:appointment_id",post(add_tip))
+        .route("/payment/charges",post(add_payment_charge))
+        .route("/payment/charge/:charge_id",get(get_payment_charge))
+        .route("/payment/charge/refund/:charge_id",post(refund_payment_charge))
+        .route("/payment/webhook/:provider",post(payment_webhook))
+        .route("/receipt/order/:order_id",get(get_order_receipt).post(print_order_receipt))
+        .route("/receipt/recharge/:recharge_record_id",get(get_recharge_receipt).post(print_recharge_receipt))
+        .route("/receipt/number/:receipt_no",get(get_receipt_by_no).post(reprint_receipt))
+        .route("/shifts",get(get_shifts).post(open_shift))
+        .route("/shifts/current",get(get_current_shift))
+        .route("/shift/:shift_id",get(get_shift))
+        .route("/shift/cash_movement/:shift_id",post(add_cash_movement))
+        .route("/shift/close/:shift_id",post(close_shift))
 
         .route("/statistic/orders",get(get_orders))
         .route("/statistic/recharge_records",get(get_recharge_records))
-
+        .route("/statistic/tender_summary",get(get_tender_summary))
+        .route("/statistic/coupon_summary",get(get_coupon_summary))
+        .route("/statistic/barber_earnings",get(get_barber_earnings))
+        .route("/statistic/payroll",get(get_payroll))
+        .route("/statistic/revenue_summary",get(get_revenue_summary))
+        .route("/statistic/recharge_summary",get(get_recharge_summary))
+        .route("/statistic/retention",get(get_retention_summary))
+        .route("/statistic/retention/cohorts",get(get_retention_cohorts))
+        .route("/statistic/at_risk_members",get(get_at_risk_members))
+        .route("/export/orders",get(export_orders))
+        .route("/export/recharge_records",get(export_recharge_records))
+        .route("/export/members",get(export_members))
+        .route("/statistic/balance_reconciliation",get(reconcile_balances))
+
+        .layer(middleware::from_fn_with_state(axum_pg.clone(), idempotency))
         .layer(CorsLayer::new()
             .allow_origin(cross_origin.parse::<HeaderValue>().unwrap(),)
             .allow_headers([
                 header::CONTENT_TYPE,
                 header::HeaderName::from_str("X-SID").unwrap(),
+                header::HeaderName::from_str(IDEMPOTENCY_KEY).unwrap(),
                 ])
+            .expose_headers([header::HeaderName::from_str(idempotency::IDEMPOTENT_REPLAYED).unwrap()])
             .allow_methods([Method::GET,Method::POST,Method::DELETE])
             .allow_credentials(true)
         )
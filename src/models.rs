@@ -1,12 +1,12 @@
 use async_trait::async_trait;
 use axum_session_authentication_middleware::{ user as auth_user,session::Authentication};
-use chrono::{Local, NaiveDate};
+use chrono::{Local, NaiveDate, NaiveTime};
 use diesel::prelude::*;
 use serde::Serialize;
 use uuid::Uuid;
 use bigdecimal::BigDecimal;
 
-use crate::{schema::*, axum_pg::AxumPg, my_date_format};
+use crate::{schema::*, axum_pg::AxumPg, my_date_format, my_option_date_format};
 
 #[derive(Queryable,Clone, Debug)]
 pub struct User{
@@ -197,6 +197,10 @@ pub struct MerchantMember{
     pub gender:Option<String>,
     pub birth_day:Option<NaiveDate>,
     pub remark:Option<String>,
+
+    pub member_level_id:Option<Uuid>, // 会员等级
+
+    pub points:i32, // 当前积分
 }
 
 #[derive(Insertable)]
@@ -430,6 +434,16 @@ pub struct RechargeRecord{
 
     #[serde(skip)]
     pub data: Option<String>,
+
+    pub reversal_of:Option<Uuid>, // 撤销记录对应的原充值记录
+
+    pub reversal_reason:Option<String>,
+
+    pub bonus:BigDecimal, // 赠送金额，amount 为实付本金
+
+    pub recharge_tier_id:Option<Uuid>,
+
+    pub tender:String, // 收款方式
 }
 
 #[derive(Insertable)]
@@ -445,6 +459,125 @@ pub struct NewRechargeRecord<'a>{
     pub create_time: chrono::DateTime<Local>,
     pub update_time: chrono::DateTime<Local>,
     pub data: Option<&'a str>,
+    pub reversal_of: Option<&'a Uuid>,
+    pub reversal_reason: Option<&'a str>,
+    pub bonus: &'a BigDecimal,
+    pub recharge_tier_id: Option<&'a Uuid>,
+    pub tender: &'a str,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeTier{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub recharge_tier_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub name: String,
+
+    pub threshold:BigDecimal, // 充值金额达到该值时赠送
+
+    pub bonus:BigDecimal,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=recharge_tiers)]
+pub struct NewRechargeTier<'a>{
+    pub recharge_tier_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub name:&'a str,
+    pub threshold:&'a BigDecimal,
+    pub bonus:&'a BigDecimal,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberLevel{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub member_level_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub name: String,
+
+    pub rank: i32, // 越大等级越高
+
+    pub discount_rate:BigDecimal, // 折扣率，如 0.9 为九折
+
+    pub recharge_threshold:Option<BigDecimal>, // 累计充值达到该值时升级
+
+    pub spend_threshold:Option<BigDecimal>, // 累计消费达到该值时升级
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_levels)]
+pub struct NewMemberLevel<'a>{
+    pub member_level_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub name:&'a str,
+    pub rank:i32,
+    pub discount_rate:&'a BigDecimal,
+    pub recharge_threshold:Option<&'a BigDecimal>,
+    pub spend_threshold:Option<&'a BigDecimal>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberLevelPrice{
+    #[serde(skip)]
+    pub id: i64,
+
+    #[serde(skip)]
+    pub member_level_id: Uuid,
+
+    pub service_type_id: Uuid,
+
+    pub price:BigDecimal,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_level_prices)]
+pub struct NewMemberLevelPrice<'a>{
+    pub member_level_id: &'a Uuid,
+    pub service_type_id: &'a Uuid,
+    pub price:&'a BigDecimal,
 }
 
 #[derive(Queryable,Serialize)]
@@ -480,7 +613,7 @@ pub struct Order{
     pub status:String,
     
     #[serde(skip)]
-    pub payment_type:String, // member / cash
+    pub payment_type:String, // member / cash / card / wechat / alipay / package / mixed
     
     #[serde(skip)]
     pub amount:BigDecimal,
@@ -499,6 +632,21 @@ pub struct Order{
 
     #[serde(skip)]
     pub data: Option<String>,
+
+    #[serde(skip)]
+    pub cancel_reason:Option<String>,
+
+    #[serde(skip)]
+    pub reversal_of:Option<Uuid>, // 退款记录对应的原订单
+
+    #[serde(skip)]
+    pub reversal_reason:Option<String>,
+
+    #[serde(skip)]
+    pub member_package_id:Option<Uuid>, // 使用套餐支付时所用的会员套餐
+
+    #[serde(skip)]
+    pub complete_time:Option<chrono::DateTime<Local>>, // 完成时间，已完成订单的退款记录为退款时间
 }
 
 #[derive(Insertable)]
@@ -513,7 +661,7 @@ pub struct NewOrder<'a>{
     pub barber_id: &'a Uuid,
     pub service_type_id:&'a Uuid,
     pub status:&'a str,
-    pub payment_type:&'a str, // member / cash
+    pub payment_type:&'a str, // member / cash / card / wechat / alipay / package / mixed
     pub amount:&'a BigDecimal,
     pub remark:Option<&'a str>,
 
@@ -521,4 +669,1066 @@ pub struct NewOrder<'a>{
     pub create_time: chrono::DateTime<Local>,
     pub update_time: chrono::DateTime<Local>,
     pub data: Option<&'a str>,
+    pub reversal_of: Option<&'a Uuid>,
+    pub reversal_reason: Option<&'a str>,
+    pub member_package_id: Option<&'a Uuid>,
+    pub complete_time: Option<chrono::DateTime<Local>>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberWorkingHour{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub working_hour_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub barber_id: Uuid,
+
+    pub weekday:i32, // 1 周一 ... 7 周日
+
+    pub start_time:NaiveTime,
+
+    pub end_time:NaiveTime,
+
+    pub kind:String, // work / break
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=barber_working_hours)]
+pub struct NewBarberWorkingHour<'a>{
+    pub working_hour_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub barber_id: &'a Uuid,
+    pub weekday:i32,
+    pub start_time:NaiveTime,
+    pub end_time:NaiveTime,
+    pub kind:&'a str,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberScheduleOverride{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub override_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub barber_id: Uuid,
+
+    pub override_date:NaiveDate,
+
+    pub is_day_off:bool,
+
+    pub start_time:Option<NaiveTime>,
+
+    pub end_time:Option<NaiveTime>,
+
+    pub remark:Option<String>,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=barber_schedule_overrides)]
+pub struct NewBarberScheduleOverride<'a>{
+    pub override_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub barber_id: &'a Uuid,
+    pub override_date:NaiveDate,
+    pub is_day_off:bool,
+    pub start_time:Option<NaiveTime>,
+    pub end_time:Option<NaiveTime>,
+    pub remark:Option<&'a str>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerchantBusinessHour{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub business_hour_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub weekday:i32, // 1 周一 ... 7 周日
+
+    pub open_time:NaiveTime,
+
+    pub close_time:NaiveTime,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=merchant_business_hours)]
+pub struct NewMerchantBusinessHour<'a>{
+    pub business_hour_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub weekday:i32,
+    pub open_time:NaiveTime,
+    pub close_time:NaiveTime,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerchantClosure{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub closure_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub closure_date:NaiveDate,
+
+    pub kind:String, // holiday / closure
+
+    pub name:Option<String>,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=merchant_closures)]
+pub struct NewMerchantClosure<'a>{
+    pub closure_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub closure_date:NaiveDate,
+    pub kind:&'a str,
+    pub name:Option<&'a str>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderLine{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub order_line_id: Uuid,
+
+    pub order_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub service_type_id: Uuid,
+
+    pub barber_id: Uuid,
+
+    pub amount:BigDecimal,
+
+    pub duration:i32, // 分钟
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+
+    pub list_price:Option<BigDecimal>, // 下单时的标价
+
+    pub price_override_reason:Option<String>, // 实收金额与标价不同时的改价原因
+
+    #[serde(skip)]
+    pub commission:Option<BigDecimal>, // 订单完成时计算的服务提成
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=order_lines)]
+pub struct NewOrderLine<'a>{
+    pub order_line_id: &'a Uuid,
+    pub order_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub service_type_id: &'a Uuid,
+    pub barber_id: &'a Uuid,
+    pub amount:&'a BigDecimal,
+    pub duration:i32,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+    pub list_price: Option<&'a BigDecimal>,
+    pub price_override_reason: Option<&'a str>,
+    pub commission: Option<&'a BigDecimal>,
+}
+
+#[derive(Queryable)]
+pub struct OrderPayment{
+    pub id: i64,
+    pub payment_id: Uuid,
+    pub order_id: Uuid,
+    pub merchant_id: Uuid,
+    pub tender: String, // member / cash / card / wechat / alipay / package
+    pub amount: BigDecimal,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=order_payments)]
+pub struct NewOrderPayment<'a>{
+    pub payment_id: &'a Uuid,
+    pub order_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub tender: &'a str,
+    pub amount: &'a BigDecimal,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberBalanceLedger{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub ledger_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub member_id: Uuid,
+
+    pub entry_type:String, // opening / recharge / order_payment / refund / adjustment
+
+    pub amount:BigDecimal,
+
+    pub balance:BigDecimal,
+
+    pub reference_id:Option<Uuid>,
+
+    pub barber_id:Option<Uuid>,
+
+    pub remark:Option<String>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_balance_ledgers)]
+pub struct NewMemberBalanceLedger<'a>{
+    pub ledger_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub member_id: &'a Uuid,
+    pub entry_type:&'a str,
+    pub amount:&'a BigDecimal,
+    pub balance:&'a BigDecimal,
+    pub reference_id:Option<&'a Uuid>,
+    pub barber_id:Option<&'a Uuid>,
+    pub remark:Option<&'a str>,
+    pub create_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServicePackage{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub package_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub name: String,
+
+    pub price:BigDecimal,
+
+    pub total_uses:Option<i32>, // 为空表示不限次数
+
+    pub valid_days:Option<i32>, // 为空表示长期有效
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=service_packages)]
+pub struct NewServicePackage<'a>{
+    pub package_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub name:&'a str,
+    pub price:&'a BigDecimal,
+    pub total_uses:Option<i32>,
+    pub valid_days:Option<i32>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable)]
+pub struct ServicePackageItem{
+    pub id: i64,
+    pub package_id: Uuid,
+    pub merchant_id: Uuid,
+    pub service_type_id: Uuid,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=service_package_items)]
+pub struct NewServicePackageItem<'a>{
+    pub package_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub service_type_id: &'a Uuid,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberPackage{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub member_package_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub member_id: Uuid,
+
+    pub package_id: Uuid,
+
+    pub remaining_uses:Option<i32>, // 为空表示不限次数
+
+    #[serde(with = "my_option_date_format")]
+    pub expire_time:Option<chrono::DateTime<Local>>, // 为空表示长期有效
+
+    pub price:BigDecimal,
+
+    pub payment_type:String, // member / cash
+
+    pub barber_id:Option<Uuid>, // 售卖套餐的理发师
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_packages)]
+pub struct NewMemberPackage<'a>{
+    pub member_package_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub member_id: &'a Uuid,
+    pub package_id: &'a Uuid,
+    pub remaining_uses:Option<i32>,
+    pub expire_time:Option<chrono::DateTime<Local>>,
+    pub price:&'a BigDecimal,
+    pub payment_type:&'a str,
+    pub barber_id: Option<&'a Uuid>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointsSetting{
+    #[serde(skip)]
+    pub id: i64,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub earn_rate:BigDecimal, // 每消费 1 元获得的积分
+
+    pub redeem_rate:BigDecimal, // 每积分抵扣的金额
+
+    pub valid_days:Option<i32>, // 积分有效天数，为空则永久有效
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=merchant_points_settings)]
+pub struct NewPointsSetting<'a>{
+    pub merchant_id: &'a Uuid,
+    pub earn_rate:&'a BigDecimal,
+    pub redeem_rate:&'a BigDecimal,
+    pub valid_days:Option<i32>,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberPointsLedger{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub ledger_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub member_id: Uuid,
+
+    pub entry_type:String, // earn / redeem / conversion / expire / refund / earn_reversal
+
+    pub points:i32,
+
+    pub balance:i32,
+
+    pub remaining:i32,
+
+    #[serde(with = "my_option_date_format")]
+    pub expire_time: Option<chrono::DateTime<Local>>,
+
+    pub reference_id:Option<Uuid>,
+
+    pub barber_id:Option<Uuid>,
+
+    pub remark:Option<String>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_points_ledgers)]
+pub struct NewMemberPointsLedger<'a>{
+    pub ledger_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub member_id: &'a Uuid,
+    pub entry_type:&'a str,
+    pub points:i32,
+    pub balance:i32,
+    pub remaining:i32,
+    pub expire_time: Option<chrono::DateTime<Local>>,
+    pub reference_id:Option<&'a Uuid>,
+    pub barber_id:Option<&'a Uuid>,
+    pub remark:Option<&'a str>,
+    pub create_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coupon{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub coupon_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub name: String,
+
+    pub code: String,
+
+    pub discount_type:String, // percentage / fixed
+
+    pub discount_value:BigDecimal, // 折扣百分比或减免金额
+
+    #[serde(with = "my_option_date_format")]
+    pub start_time: Option<chrono::DateTime<Local>>,
+
+    #[serde(with = "my_option_date_format")]
+    pub end_time: Option<chrono::DateTime<Local>>,
+
+    pub per_member_limit:Option<i32>,
+
+    pub is_public:bool,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=coupons)]
+pub struct NewCoupon<'a>{
+    pub coupon_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub name:&'a str,
+    pub code:&'a str,
+    pub discount_type:&'a str,
+    pub discount_value:&'a BigDecimal,
+    pub start_time: Option<chrono::DateTime<Local>>,
+    pub end_time: Option<chrono::DateTime<Local>>,
+    pub per_member_limit:Option<i32>,
+    pub is_public:bool,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=coupon_service_types)]
+pub struct NewCouponServiceType<'a>{
+    pub coupon_id: &'a Uuid,
+    pub service_type_id: &'a Uuid,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberCoupon{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub member_coupon_id: Uuid,
+
+    pub coupon_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub member_id: Uuid,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=member_coupons)]
+pub struct NewMemberCoupon<'a>{
+    pub member_coupon_id: &'a Uuid,
+    pub coupon_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub member_id: &'a Uuid,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=coupon_redemptions)]
+pub struct NewCouponRedemption<'a>{
+    pub redemption_id: &'a Uuid,
+    pub coupon_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub order_id: &'a Uuid,
+    pub member_id: Option<&'a Uuid>,
+    pub discount:&'a BigDecimal,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderTip{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub tip_id: Uuid,
+
+    pub order_id: Uuid,
+
+    pub merchant_id: Uuid,
+
+    pub barber_id: Uuid,
+
+    pub tender:String,
+
+    pub amount:BigDecimal,
+
+    #[serde(skip)]
+    pub enabled:bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=order_tips)]
+pub struct NewOrderTip<'a>{
+    pub tip_id: &'a Uuid,
+    pub order_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub barber_id: &'a Uuid,
+    pub tender:&'a str,
+    pub amount:&'a BigDecimal,
+    pub enabled:bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable)]
+pub struct IdempotencyKey{
+    pub id: i64,
+    pub merchant_id: Uuid,
+    pub idempotency_key: String,
+    pub request_method: String,
+    pub request_path: String,
+    pub request_body: String,
+    pub status_code: Option<i32>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<String>,
+    pub create_time: chrono::DateTime<Local>,
+    pub expire_time: chrono::DateTime<Local>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=idempotency_keys)]
+pub struct NewIdempotencyKey<'a>{
+    pub merchant_id: &'a Uuid,
+    pub idempotency_key: &'a str,
+    pub request_method: &'a str,
+    pub request_path: &'a str,
+    pub request_body: &'a str,
+    pub create_time: chrono::DateTime<Local>,
+    pub expire_time: chrono::DateTime<Local>,
+}
+
+#[derive(Queryable)]
+pub struct Receipt{
+    pub id: i64,
+    pub receipt_id: Uuid,
+    pub merchant_id: Uuid,
+    pub receipt_no: i64,
+    pub receipt_type: String, // order / recharge
+    pub reference_id: Uuid,
+    pub print_count: i32,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=receipts)]
+pub struct NewReceipt<'a>{
+    pub receipt_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub receipt_no: i64,
+    pub receipt_type: &'a str,
+    pub reference_id: &'a Uuid,
+    pub print_count: i32,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shift{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub shift_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub status: String, // open / closed
+
+    pub opening_float: BigDecimal, // 备用金
+
+    pub opened_by: Option<Uuid>,
+
+    #[serde(with = "my_date_format")]
+    pub open_time: chrono::DateTime<Local>,
+
+    pub closed_by: Option<Uuid>,
+
+    #[serde(with = "my_option_date_format")]
+    pub close_time: Option<chrono::DateTime<Local>>,
+
+    pub expected_cash: Option<BigDecimal>,
+
+    pub counted_cash: Option<BigDecimal>,
+
+    pub cash_difference: Option<BigDecimal>, // 实际现金 - 应有现金
+
+    pub remark: Option<String>,
+
+    #[serde(skip)]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=shifts)]
+pub struct NewShift<'a>{
+    pub shift_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub status: &'a str,
+    pub opening_float: &'a BigDecimal,
+    pub opened_by: Option<&'a Uuid>,
+    pub open_time: chrono::DateTime<Local>,
+    pub remark: Option<&'a str>,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftCashMovement{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub movement_id: Uuid,
+
+    pub shift_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub movement_type: String, // in / out
+
+    pub amount: BigDecimal,
+
+    pub reason: String,
+
+    pub barber_id: Option<Uuid>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=shift_cash_movements)]
+pub struct NewShiftCashMovement<'a>{
+    pub movement_id: &'a Uuid,
+    pub shift_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub movement_type: &'a str,
+    pub amount: &'a BigDecimal,
+    pub reason: &'a str,
+    pub barber_id: Option<&'a Uuid>,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentCharge{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub charge_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub provider: String,
+
+    pub provider_charge_id: Option<String>, // 渠道交易号
+
+    pub tender: String,
+
+    pub amount: BigDecimal,
+
+    pub refunded_amount: BigDecimal,
+
+    pub status: String, // pending / succeeded / failed
+
+    pub failure_reason: Option<String>,
+
+    pub order_id: Option<Uuid>, // 使用该笔收款支付的订单
+
+    pub barber_id: Option<Uuid>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=payment_charges)]
+pub struct NewPaymentCharge<'a>{
+    pub charge_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub provider: &'a str,
+    pub tender: &'a str,
+    pub amount: &'a BigDecimal,
+    pub refunded_amount: &'a BigDecimal,
+    pub status: &'a str,
+    pub barber_id: Option<&'a Uuid>,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRefund{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub refund_id: Uuid,
+
+    pub charge_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub provider_refund_id: Option<String>,
+
+    pub amount: BigDecimal,
+
+    pub reason: String,
+
+    pub status: String, // pending / succeeded / failed
+
+    pub failure_reason: Option<String>,
+
+    pub barber_id: Option<Uuid>,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=payment_refunds)]
+pub struct NewPaymentRefund<'a>{
+    pub refund_id: &'a Uuid,
+    pub charge_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub amount: &'a BigDecimal,
+    pub reason: &'a str,
+    pub status: &'a str,
+    pub barber_id: Option<&'a Uuid>,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommissionRule{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub rule_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub barber_id: Option<Uuid>, // 为空时适用于全部理发师
+
+    pub service_type_id: Option<Uuid>, // 为空时适用于全部服务
+
+    pub rule_type: String, // percentage / fixed
+
+    pub value: BigDecimal,
+
+    #[serde(skip)]
+    pub enabled: bool,
+
+    #[serde(with = "my_date_format")]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=commission_rules)]
+pub struct NewCommissionRule<'a>{
+    pub rule_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub barber_id: Option<&'a Uuid>,
+    pub service_type_id: Option<&'a Uuid>,
+    pub rule_type: &'a str,
+    pub value: &'a BigDecimal,
+    pub enabled: bool,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
+}
+
+#[derive(Queryable,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarberPaySetting{
+    #[serde(skip)]
+    pub id: i64,
+
+    pub barber_id: Uuid,
+
+    #[serde(skip)]
+    pub merchant_id: Uuid,
+
+    pub base_wage: BigDecimal, // 每个结算周期的底薪
+
+    pub recharge_rate: BigDecimal, // 充值提成百分比
+
+    #[serde(skip)]
+    pub create_time: chrono::DateTime<Local>,
+
+    #[serde(with = "my_date_format")]
+    pub update_time: chrono::DateTime<Local>,
+
+    #[serde(skip)]
+    pub data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=barber_pay_settings)]
+pub struct NewBarberPaySetting<'a>{
+    pub barber_id: &'a Uuid,
+    pub merchant_id: &'a Uuid,
+    pub base_wage: &'a BigDecimal,
+    pub recharge_rate: &'a BigDecimal,
+    pub create_time: chrono::DateTime<Local>,
+    pub update_time: chrono::DateTime<Local>,
+    pub data: Option<&'a str>,
 }
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use bigdecimal::BigDecimal;
+use uuid::Uuid;
+
+mod mock;
+
+pub use mock::MockPaymentProvider;
+
+// 发起收款，charge_id 为本系统的支付单号，作为渠道的商户订单号
+pub struct ChargeRequest<'a>{
+    pub charge_id:Uuid,
+    pub tender:&'a str,
+    pub amount:&'a BigDecimal,
+    pub auth_code:Option<&'a str>, // 顾客出示的付款码或刷卡信息
+    pub notify_url:String, // 渠道异步回调地址
+}
+
+// 渠道受理结果，status 为 pending 时等待回调确认
+pub struct ProviderCharge{
+    pub provider_charge_id:String,
+    pub status:String,
+    pub failure_reason:Option<String>,
+}
+
+// 渠道回调，校验签名后解析得到
+pub struct CallbackEvent{
+    pub charge_id:Uuid,
+    pub provider_charge_id:String,
+    pub status:String,
+    pub amount:BigDecimal,
+    pub failure_reason:Option<String>,
+}
+
+pub struct RefundRequest<'a>{
+    pub refund_id:Uuid,
+    pub provider_charge_id:&'a str,
+    pub amount:&'a BigDecimal,
+    pub reason:&'a str,
+}
+
+pub struct ProviderRefund{
+    pub provider_refund_id:String,
+    pub status:String,
+    pub failure_reason:Option<String>,
+}
+
+// 刷卡、扫码等支付渠道
+#[async_trait]
+pub trait PaymentProvider:Send+Sync{
+    fn name(&self)->&'static str;
+
+    // 渠道支持的支付方式，如 card / wechat / alipay
+    fn supports(&self,tender:&str)->bool;
+
+    async fn create_charge(&self,req:ChargeRequest<'_>)->anyhow::Result<ProviderCharge>;
+
+    // 校验回调签名并解析，校验失败返回错误
+    fn verify_callback(&self,headers:&HeaderMap,body:&[u8])->anyhow::Result<CallbackEvent>;
+
+    async fn refund(&self,req:RefundRequest<'_>)->anyhow::Result<ProviderRefund>;
+}
+
+// 按名称获取已配置的支付渠道
+pub fn provider(name:&str)->Option<Box<dyn PaymentProvider>>{
+    match name {
+        MockPaymentProvider::NAME=>MockPaymentProvider::from_env().map(|p|Box::new(p) as Box<dyn PaymentProvider>),
+        _=>None,
+    }
+}
+
+pub fn notify_url(provider:&str)->String{
+    let base_url=std::env::var("PAYMENT_NOTIFY_BASE_URL").unwrap_or_else(|_|"http://127.0.0.1:3000".into());
+    format!("{}/payment/webhook/{provider}",base_url.trim_end_matches('/'))
+}
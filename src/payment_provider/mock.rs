@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use bigdecimal::BigDecimal;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, time::{sleep, Duration}};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::constant::{charge_status, payment_type};
+
+use super::{CallbackEvent, ChargeRequest, PaymentProvider, ProviderCharge, ProviderRefund, RefundRequest};
+
+const SIGNATURE_HEADER:&str="X-Mock-Signature";
+const CALLBACK_DELAY_SECONDS:u64=1;
+const DELAYED_CALLBACK_SECONDS:u64=5;
+
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MockCallback{
+    charge_id:Uuid,
+    provider_charge_id:String,
+    status:String,
+    amount:BigDecimal,
+    failure_reason:Option<String>,
+}
+
+// 模拟支付渠道，不连接真实网关，按付款码模拟支付结果并回调 notify_url
+pub struct MockPaymentProvider{
+    secret:String,
+}
+
+impl MockPaymentProvider{
+    pub const NAME:&'static str="mock";
+
+    pub fn new(secret:String)->Self{
+        MockPaymentProvider{secret}
+    }
+
+    // 设置 MOCK_PAYMENT_SECRET 后启用，生产环境不启用
+    pub fn from_env()->Option<Self>{
+        let secret=std::env::var("MOCK_PAYMENT_SECRET")
+            .ok()
+            .filter(|s|!s.is_empty())?;
+        if std::env::var("MELI").as_deref()==Ok("PROD") {
+            tracing::warn!("MOCK_PAYMENT_SECRET is ignored in PROD");
+            return None;
+        }
+        Some(Self::new(secret))
+    }
+}
+
+fn mac(secret:&str,body:&[u8])->Hmac<Sha256>{
+    let mut mac=Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac
+}
+
+// 以密钥对请求体做 HMAC-SHA256 签名，十六进制编码
+fn sign(secret:&str,body:&[u8])->String{
+    hex::encode(mac(secret, body).finalize().into_bytes())
+}
+
+fn verify_signature(secret:&str,body:&[u8],signature:&str)->bool{
+    hex::decode(signature)
+        .map(|signature|mac(secret, body).verify_slice(&signature).is_ok())
+        .unwrap_or(false)
+}
+
+// 付款码以 fail 开头时支付失败，以 delay 开头时先返回处理中、稍后回调支付成功，其余立即成功
+fn simulate(auth_code:Option<&str>)->(&'static str,Option<String>,bool){
+    match auth_code.unwrap_or_default() {
+        code if code.starts_with("fail")=>(charge_status::FAILED,Some("模拟支付失败".into()),false),
+        code if code.starts_with("delay")=>(charge_status::SUCCEEDED,None,true),
+        _=>(charge_status::SUCCEEDED,None,false),
+    }
+}
+
+// 解析 http://host:port/path，返回 (host:port, host, path)
+fn split_url(url:&str)->Option<(String,String,String)>{
+    let rest=url.strip_prefix("http://")?;
+    let (authority,path)=match rest.find('/') {
+        Some(i)=>(&rest[..i],&rest[i..]),
+        None=>(rest,"/"),
+    };
+    let address=if authority.contains(':') { authority.to_string() } else { format!("{authority}:80") };
+    Some((address,authority.to_string(),path.to_string()))
+}
+
+async fn send_callback(url:String,signature:String,body:String,delay:u64)->anyhow::Result<()>{
+    sleep(Duration::from_secs(delay)).await;
+
+    let (address,host,path)=split_url(&url).ok_or_else(||anyhow::anyhow!("不支持的回调地址 {url}"))?;
+    let mut stream=TcpStream::connect(address).await?;
+    let request=format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\n{SIGNATURE_HEADER}: {signature}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response=String::new();
+    stream.read_to_string(&mut response).await?;
+    if !response.starts_with("HTTP/1.1 200") {
+        anyhow::bail!("回调失败 {}",response.lines().next().unwrap_or_default());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl PaymentProvider for MockPaymentProvider{
+    fn name(&self)->&'static str{
+        Self::NAME
+    }
+
+    fn supports(&self,tender:&str)->bool{
+        matches!(tender,payment_type::CARD|payment_type::WECHAT|payment_type::ALIPAY)
+    }
+
+    async fn create_charge(&self,req:ChargeRequest<'_>)->anyhow::Result<ProviderCharge>{
+        let provider_charge_id=format!("mock_{}",Uuid::new_v4().simple());
+        let (status,failure_reason,delayed)=simulate(req.auth_code);
+
+        let callback=serde_json::to_string(&MockCallback{
+            charge_id:req.charge_id,
+            provider_charge_id:provider_charge_id.clone(),
+            status:status.into(),
+            amount:req.amount.clone(),
+            failure_reason:failure_reason.clone(),
+        })?;
+        let delay=if delayed { DELAYED_CALLBACK_SECONDS } else { CALLBACK_DELAY_SECONDS };
+        let (url,signature)=(req.notify_url,sign(&self.secret, callback.as_bytes()));
+        tokio::spawn(async move {
+            if let Err(e)=send_callback(url, signature, callback, delay).await {
+                tracing::warn!("mock payment callback error: {}",e);
+            }
+        });
+
+        Ok(ProviderCharge{
+            provider_charge_id,
+            status:if delayed { charge_status::PENDING.into() } else { status.into() },
+            failure_reason,
+        })
+    }
+
+    fn verify_callback(&self,headers:&HeaderMap,body:&[u8])->anyhow::Result<CallbackEvent>{
+        let signature=headers.get(SIGNATURE_HEADER).and_then(|v|v.to_str().ok()).unwrap_or_default();
+        if !verify_signature(&self.secret, body, signature) {
+            anyhow::bail!("回调签名无效");
+        }
+        let callback=serde_json::from_slice::<MockCallback>(body)?;
+        Ok(CallbackEvent{
+            charge_id:callback.charge_id,
+            provider_charge_id:callback.provider_charge_id,
+            status:callback.status,
+            amount:callback.amount,
+            failure_reason:callback.failure_reason,
+        })
+    }
+
+    async fn refund(&self,req:RefundRequest<'_>)->anyhow::Result<ProviderRefund>{
+        Ok(ProviderRefund{
+            provider_refund_id:format!("mock_refund_{}",req.refund_id.simple()),
+            status:charge_status::SUCCEEDED.into(),
+            failure_reason:None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_simulate(){
+        assert_eq!(simulate(None).0, charge_status::SUCCEEDED);
+        assert_eq!(simulate(Some("fail-001")).0, charge_status::FAILED);
+        assert!(simulate(Some("delay-001")).2);
+    }
+
+    #[test]
+    fn test_split_url(){
+        assert_eq!(
+            split_url("http://127.0.0.1:3000/payment/webhook/mock"),
+            Some(("127.0.0.1:3000".into(),"127.0.0.1:3000".into(),"/payment/webhook/mock".into()))
+        );
+        assert_eq!(split_url("https://example.com/"), None);
+    }
+
+    #[test]
+    fn test_verify_callback(){
+        let provider=MockPaymentProvider::new("secret".into());
+        let body=br#"{"chargeId":"67e55044-10b1-426f-9247-bb680e5fe0c8","providerChargeId":"mock_1","status":"succeeded","amount":"30","failureReason":null}"#;
+        let mut headers=HeaderMap::new();
+        headers.insert(SIGNATURE_HEADER, sign("secret", body).parse().unwrap());
+        let event=provider.verify_callback(&headers, body).unwrap();
+        assert_eq!(event.status, charge_status::SUCCEEDED);
+
+        // 篡改请求体、使用其他密钥或缺少签名都校验失败
+        let tampered=br#"{"chargeId":"67e55044-10b1-426f-9247-bb680e5fe0c8","providerChargeId":"mock_1","status":"succeeded","amount":"0.01","failureReason":null}"#;
+        assert!(provider.verify_callback(&headers, tampered).is_err());
+        headers.insert(SIGNATURE_HEADER, sign("other", body).parse().unwrap());
+        assert!(provider.verify_callback(&headers, body).is_err());
+        assert!(provider.verify_callback(&HeaderMap::new(), body).is_err());
+    }
+}
@@ -1,5 +1,53 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    barber_pay_settings (id) {
+        id -> Int8,
+        barber_id -> Uuid,
+        merchant_id -> Uuid,
+        base_wage -> Numeric,
+        recharge_rate -> Numeric,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    barber_schedule_overrides (id) {
+        id -> Int8,
+        override_id -> Uuid,
+        merchant_id -> Uuid,
+        barber_id -> Uuid,
+        override_date -> Date,
+        is_day_off -> Bool,
+        start_time -> Nullable<Time>,
+        end_time -> Nullable<Time>,
+        remark -> Nullable<Text>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    barber_working_hours (id) {
+        id -> Int8,
+        working_hour_id -> Uuid,
+        merchant_id -> Uuid,
+        barber_id -> Uuid,
+        weekday -> Int4,
+        start_time -> Time,
+        end_time -> Time,
+        kind -> Varchar,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     barbers (id) {
         id -> Int8,
@@ -16,6 +64,82 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    commission_rules (id) {
+        id -> Int8,
+        rule_id -> Uuid,
+        merchant_id -> Uuid,
+        barber_id -> Nullable<Uuid>,
+        service_type_id -> Nullable<Uuid>,
+        rule_type -> Varchar,
+        value -> Numeric,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    coupon_redemptions (id) {
+        id -> Int8,
+        redemption_id -> Uuid,
+        coupon_id -> Uuid,
+        merchant_id -> Uuid,
+        order_id -> Uuid,
+        member_id -> Nullable<Uuid>,
+        discount -> Numeric,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    coupon_service_types (id) {
+        id -> Int8,
+        coupon_id -> Uuid,
+        service_type_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    coupons (id) {
+        id -> Int8,
+        coupon_id -> Uuid,
+        merchant_id -> Uuid,
+        name -> Varchar,
+        code -> Varchar,
+        discount_type -> Varchar,
+        discount_value -> Numeric,
+        start_time -> Nullable<Timestamptz>,
+        end_time -> Nullable<Timestamptz>,
+        per_member_limit -> Nullable<Int4>,
+        is_public -> Bool,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (id) {
+        id -> Int8,
+        merchant_id -> Uuid,
+        idempotency_key -> Varchar,
+        request_method -> Varchar,
+        request_path -> Varchar,
+        request_body -> Text,
+        status_code -> Nullable<Int4>,
+        response_content_type -> Nullable<Varchar>,
+        response_body -> Nullable<Text>,
+        create_time -> Timestamptz,
+        expire_time -> Timestamptz,
+    }
+}
+
 diesel::table! {
     login_infos (id) {
         id -> Int8,
@@ -29,6 +153,131 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    member_balance_ledgers (id) {
+        id -> Int8,
+        ledger_id -> Uuid,
+        merchant_id -> Uuid,
+        member_id -> Uuid,
+        entry_type -> Varchar,
+        amount -> Numeric,
+        balance -> Numeric,
+        reference_id -> Nullable<Uuid>,
+        barber_id -> Nullable<Uuid>,
+        remark -> Nullable<Text>,
+        create_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    member_coupons (id) {
+        id -> Int8,
+        member_coupon_id -> Uuid,
+        coupon_id -> Uuid,
+        merchant_id -> Uuid,
+        member_id -> Uuid,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    member_level_prices (id) {
+        id -> Int8,
+        member_level_id -> Uuid,
+        service_type_id -> Uuid,
+        price -> Numeric,
+    }
+}
+
+diesel::table! {
+    member_levels (id) {
+        id -> Int8,
+        member_level_id -> Uuid,
+        merchant_id -> Uuid,
+        name -> Varchar,
+        rank -> Int4,
+        discount_rate -> Numeric,
+        recharge_threshold -> Nullable<Numeric>,
+        spend_threshold -> Nullable<Numeric>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    member_packages (id) {
+        id -> Int8,
+        member_package_id -> Uuid,
+        merchant_id -> Uuid,
+        member_id -> Uuid,
+        package_id -> Uuid,
+        remaining_uses -> Nullable<Int4>,
+        expire_time -> Nullable<Timestamptz>,
+        price -> Numeric,
+        payment_type -> Varchar,
+        barber_id -> Nullable<Uuid>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    member_points_ledgers (id) {
+        id -> Int8,
+        ledger_id -> Uuid,
+        merchant_id -> Uuid,
+        member_id -> Uuid,
+        entry_type -> Varchar,
+        points -> Int4,
+        balance -> Int4,
+        remaining -> Int4,
+        expire_time -> Nullable<Timestamptz>,
+        reference_id -> Nullable<Uuid>,
+        barber_id -> Nullable<Uuid>,
+        remark -> Nullable<Text>,
+        create_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    merchant_business_hours (id) {
+        id -> Int8,
+        business_hour_id -> Uuid,
+        merchant_id -> Uuid,
+        weekday -> Int4,
+        open_time -> Time,
+        close_time -> Time,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    merchant_closures (id) {
+        id -> Int8,
+        closure_id -> Uuid,
+        merchant_id -> Uuid,
+        closure_date -> Date,
+        kind -> Varchar,
+        name -> Nullable<Varchar>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     merchant_members (id) {
         id -> Int8,
@@ -44,6 +293,22 @@ diesel::table! {
         gender -> Nullable<Varchar>,
         birth_day -> Nullable<Date>,
         remark -> Nullable<Text>,
+        member_level_id -> Nullable<Uuid>,
+        points -> Int4,
+    }
+}
+
+diesel::table! {
+    merchant_points_settings (id) {
+        id -> Int8,
+        merchant_id -> Uuid,
+        earn_rate -> Numeric,
+        redeem_rate -> Numeric,
+        valid_days -> Nullable<Int4>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
     }
 }
 
@@ -63,6 +328,57 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    order_lines (id) {
+        id -> Int8,
+        order_line_id -> Uuid,
+        order_id -> Uuid,
+        merchant_id -> Uuid,
+        service_type_id -> Uuid,
+        barber_id -> Uuid,
+        amount -> Numeric,
+        duration -> Int4,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+        list_price -> Nullable<Numeric>,
+        price_override_reason -> Nullable<Text>,
+        commission -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    order_payments (id) {
+        id -> Int8,
+        payment_id -> Uuid,
+        order_id -> Uuid,
+        merchant_id -> Uuid,
+        tender -> Varchar,
+        amount -> Numeric,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    order_tips (id) {
+        id -> Int8,
+        tip_id -> Uuid,
+        order_id -> Uuid,
+        merchant_id -> Uuid,
+        barber_id -> Uuid,
+        tender -> Varchar,
+        amount -> Numeric,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     orders (id) {
         id -> Int8,
@@ -82,6 +398,11 @@ diesel::table! {
         create_time -> Timestamptz,
         update_time -> Timestamptz,
         data -> Nullable<Text>,
+        cancel_reason -> Nullable<Text>,
+        reversal_of -> Nullable<Uuid>,
+        reversal_reason -> Nullable<Text>,
+        member_package_id -> Nullable<Uuid>,
+        complete_time -> Nullable<Timestamptz>,
     }
 }
 
@@ -97,6 +418,44 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    payment_charges (id) {
+        id -> Int8,
+        charge_id -> Uuid,
+        merchant_id -> Uuid,
+        provider -> Varchar,
+        provider_charge_id -> Nullable<Varchar>,
+        tender -> Varchar,
+        amount -> Numeric,
+        refunded_amount -> Numeric,
+        status -> Varchar,
+        failure_reason -> Nullable<Text>,
+        order_id -> Nullable<Uuid>,
+        barber_id -> Nullable<Uuid>,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    payment_refunds (id) {
+        id -> Int8,
+        refund_id -> Uuid,
+        charge_id -> Uuid,
+        merchant_id -> Uuid,
+        provider_refund_id -> Nullable<Varchar>,
+        amount -> Numeric,
+        reason -> Text,
+        status -> Varchar,
+        failure_reason -> Nullable<Text>,
+        barber_id -> Nullable<Uuid>,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     permissions (id) {
         id -> Int8,
@@ -111,6 +470,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    receipts (id) {
+        id -> Int8,
+        receipt_id -> Uuid,
+        merchant_id -> Uuid,
+        receipt_no -> Int8,
+        receipt_type -> Varchar,
+        reference_id -> Uuid,
+        print_count -> Int4,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     recharge_records (id) {
         id -> Int8,
@@ -123,6 +497,26 @@ diesel::table! {
         create_time -> Timestamptz,
         update_time -> Timestamptz,
         data -> Nullable<Text>,
+        reversal_of -> Nullable<Uuid>,
+        reversal_reason -> Nullable<Text>,
+        bonus -> Numeric,
+        recharge_tier_id -> Nullable<Uuid>,
+        tender -> Varchar,
+    }
+}
+
+diesel::table! {
+    recharge_tiers (id) {
+        id -> Int8,
+        recharge_tier_id -> Uuid,
+        merchant_id -> Uuid,
+        name -> Varchar,
+        threshold -> Numeric,
+        bonus -> Numeric,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
     }
 }
 
@@ -141,6 +535,35 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    service_package_items (id) {
+        id -> Int8,
+        package_id -> Uuid,
+        merchant_id -> Uuid,
+        service_type_id -> Uuid,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    service_packages (id) {
+        id -> Int8,
+        package_id -> Uuid,
+        merchant_id -> Uuid,
+        name -> Varchar,
+        price -> Numeric,
+        total_uses -> Nullable<Int4>,
+        valid_days -> Nullable<Int4>,
+        enabled -> Bool,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     service_types (id) {
         id -> Int8,
@@ -170,6 +593,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    shift_cash_movements (id) {
+        id -> Int8,
+        movement_id -> Uuid,
+        shift_id -> Uuid,
+        merchant_id -> Uuid,
+        movement_type -> Varchar,
+        amount -> Numeric,
+        reason -> Text,
+        barber_id -> Nullable<Uuid>,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    shifts (id) {
+        id -> Int8,
+        shift_id -> Uuid,
+        merchant_id -> Uuid,
+        status -> Varchar,
+        opening_float -> Numeric,
+        opened_by -> Nullable<Uuid>,
+        open_time -> Timestamptz,
+        closed_by -> Nullable<Uuid>,
+        close_time -> Nullable<Timestamptz>,
+        expected_cash -> Nullable<Numeric>,
+        counted_cash -> Nullable<Numeric>,
+        cash_difference -> Nullable<Numeric>,
+        remark -> Nullable<Text>,
+        create_time -> Timestamptz,
+        update_time -> Timestamptz,
+        data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int8,
@@ -185,16 +645,44 @@ diesel::table! {
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    barber_pay_settings,
+    barber_schedule_overrides,
+    barber_working_hours,
     barbers,
+    commission_rules,
+    coupon_redemptions,
+    coupon_service_types,
+    coupons,
+    idempotency_keys,
     login_infos,
+    member_balance_ledgers,
+    member_coupons,
+    member_level_prices,
+    member_levels,
+    member_packages,
+    member_points_ledgers,
+    merchant_business_hours,
+    merchant_closures,
     merchant_members,
+    merchant_points_settings,
     merchants,
+    order_lines,
+    order_payments,
+    order_tips,
     orders,
     password_login_providers,
+    payment_charges,
+    payment_refunds,
     permissions,
+    receipts,
     recharge_records,
+    recharge_tiers,
     roles,
+    service_package_items,
+    service_packages,
     service_types,
     sessions,
+    shift_cash_movements,
+    shifts,
     users,
 );
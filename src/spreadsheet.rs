@@ -0,0 +1,358 @@
+// 逐行生成 CSV / XLSX，每次只返回新增的字节，便于边查询边输出
+
+pub enum Cell{
+    Text(String),
+    Number(String), // 金额、数量等，XLSX 中按数值写入
+}
+
+impl From<String> for Cell{
+    fn from(s:String)->Self{
+        Cell::Text(s)
+    }
+}
+
+impl From<&str> for Cell{
+    fn from(s:&str)->Self{
+        Cell::Text(s.into())
+    }
+}
+
+pub trait SheetWriter:Send{
+    fn begin(&mut self,headers:&[&str])->Vec<u8>;
+
+    fn row(&mut self,cells:&[Cell])->Vec<u8>;
+
+    fn finish(&mut self)->Vec<u8>;
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ExportFormat{
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat{
+    pub fn parse(format:Option<&str>)->Option<Self>{
+        match format.unwrap_or("csv") {
+            "csv"=>Some(ExportFormat::Csv),
+            "xlsx"=>Some(ExportFormat::Xlsx),
+            _=>None,
+        }
+    }
+
+    pub fn extension(&self)->&'static str{
+        match self {
+            ExportFormat::Csv=>"csv",
+            ExportFormat::Xlsx=>"xlsx",
+        }
+    }
+
+    pub fn content_type(&self)->&'static str{
+        match self {
+            ExportFormat::Csv=>"text/csv; charset=utf-8",
+            ExportFormat::Xlsx=>"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    pub fn writer(&self,sheet_name:&str)->Box<dyn SheetWriter>{
+        match self {
+            ExportFormat::Csv=>Box::new(CsvWriter),
+            ExportFormat::Xlsx=>Box::new(XlsxWriter::new(sheet_name)),
+        }
+    }
+}
+
+pub struct CsvWriter;
+
+fn csv_field(value:&str,out:&mut String){
+    // 以 = + - @ 开头的文本会被表格软件当作公式，加 ' 前缀
+    let value=if value.len()>1 && value.starts_with(['=','+','-','@']) { format!("'{value}") } else { value.to_string() };
+    if value.contains([',','"','\r','\n']) {
+        out.push('"');
+        out.push_str(&value.replace('"',"\"\""));
+        out.push('"');
+    } else {
+        out.push_str(&value);
+    }
+}
+
+impl SheetWriter for CsvWriter{
+    fn begin(&mut self,headers:&[&str])->Vec<u8>{
+        // 带 BOM，Excel 打开时才能正确识别 UTF-8 中文
+        let mut bytes="\u{feff}".as_bytes().to_vec();
+        bytes.extend(self.row(&headers.iter().map(|&h|Cell::from(h)).collect::<Vec<_>>()));
+        bytes
+    }
+
+    fn row(&mut self,cells:&[Cell])->Vec<u8>{
+        let mut line=String::new();
+        for (i,cell) in cells.iter().enumerate() {
+            if i>0 {
+                line.push(',');
+            }
+            match cell {
+                Cell::Text(s)=>csv_field(s, &mut line),
+                Cell::Number(n)=>line.push_str(n),
+            }
+        }
+        line.push_str("\r\n");
+        line.into_bytes()
+    }
+
+    fn finish(&mut self)->Vec<u8>{
+        Vec::new()
+    }
+}
+
+const CRC32_TABLE:[u32;256]={
+    let mut table=[0u32;256];
+    let mut i=0;
+    while i<256 {
+        let mut c=i as u32;
+        let mut k=0;
+        while k<8 {
+            c=if c&1==1 { 0xEDB88320^(c>>1) } else { c>>1 };
+            k+=1;
+        }
+        table[i]=c;
+        i+=1;
+    }
+    table
+};
+
+fn crc32_update(crc:u32,bytes:&[u8])->u32{
+    !bytes.iter().fold(!crc,|c,&b|CRC32_TABLE[((c^b as u32)&0xff) as usize]^(c>>8))
+}
+
+fn xml_escape(value:&str)->String{
+    let mut out=String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&'=>out.push_str("&amp;"),
+            '<'=>out.push_str("&lt;"),
+            '>'=>out.push_str("&gt;"),
+            '"'=>out.push_str("&quot;"),
+            '\t'|'\n'|'\r'=>out.push(c),
+            c if (c as u32)<0x20=>{}, // XML 不允许的控制字符
+            c=>out.push(c),
+        }
+    }
+    out
+}
+
+struct ZipEntry{
+    name:&'static str,
+    crc:u32,
+    size:u32,
+    offset:u32,
+    streamed:bool,
+}
+
+const ZIP_VERSION:u16=20;
+const ZIP_FLAG_DATA_DESCRIPTOR:u16=0x08;
+const ZIP_DOS_DATE:u16=(1<<5)|1; // 1980-01-01
+
+// XLSX 为不压缩的 zip 包，工作表使用 inlineStr 并通过数据描述符在末尾写入 CRC 和长度，无需缓存全部内容
+pub struct XlsxWriter{
+    sheet_name:String,
+    offset:u32,
+    entries:Vec<ZipEntry>,
+    sheet_crc:u32,
+    sheet_size:u32,
+}
+
+impl XlsxWriter{
+    pub fn new(sheet_name:&str)->Self{
+        XlsxWriter{
+            // 工作表名称最长 31 个字符
+            sheet_name:sheet_name.chars().filter(|c|!"[]:*?/\\".contains(*c)).take(31).collect(),
+            offset:0,
+            entries:Vec::new(),
+            sheet_crc:0,
+            sheet_size:0,
+        }
+    }
+
+    fn local_header(&mut self,name:&'static str,crc:u32,size:u32,streamed:bool)->Vec<u8>{
+        let mut bytes=Vec::new();
+        bytes.extend(0x04034b50u32.to_le_bytes());
+        bytes.extend(ZIP_VERSION.to_le_bytes());
+        bytes.extend((if streamed { ZIP_FLAG_DATA_DESCRIPTOR } else { 0 }).to_le_bytes());
+        bytes.extend(0u16.to_le_bytes()); // 不压缩
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend(ZIP_DOS_DATE.to_le_bytes());
+        bytes.extend(crc.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        bytes.extend((name.len() as u16).to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend(name.as_bytes());
+        self.entries.push(ZipEntry{name,crc,size,offset:self.offset,streamed});
+        self.offset+=bytes.len() as u32;
+        bytes
+    }
+
+    fn file(&mut self,name:&'static str,content:&str)->Vec<u8>{
+        let mut bytes=self.local_header(name, crc32_update(0, content.as_bytes()), content.len() as u32, false);
+        bytes.extend(content.as_bytes());
+        self.offset+=content.len() as u32;
+        bytes
+    }
+
+    fn sheet_data(&mut self,content:String)->Vec<u8>{
+        self.sheet_crc=crc32_update(self.sheet_crc, content.as_bytes());
+        self.sheet_size+=content.len() as u32;
+        self.offset+=content.len() as u32;
+        content.into_bytes()
+    }
+}
+
+impl SheetWriter for XlsxWriter{
+    fn begin(&mut self,headers:&[&str])->Vec<u8>{
+        let mut bytes=self.file("[Content_Types].xml", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+            r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+            r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+            r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+            r#"<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+            r#"</Types>"#,
+        ));
+        bytes.extend(self.file("_rels/.rels", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>"#,
+            r#"</Relationships>"#,
+        )));
+        let workbook=format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+                r#"<sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets>"#,
+                r#"</workbook>"#,
+            ),
+            xml_escape(&self.sheet_name)
+        );
+        bytes.extend(self.file("xl/workbook.xml", &workbook));
+        bytes.extend(self.file("xl/_rels/workbook.xml.rels", concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>"#,
+            r#"</Relationships>"#,
+        )));
+
+        bytes.extend(self.local_header("xl/worksheets/sheet1.xml", 0, 0, true));
+        bytes.extend(self.sheet_data(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+        ).into()));
+        bytes.extend(self.row(&headers.iter().map(|&h|Cell::from(h)).collect::<Vec<_>>()));
+        bytes
+    }
+
+    fn row(&mut self,cells:&[Cell])->Vec<u8>{
+        let mut row=String::from("<row>");
+        for cell in cells {
+            match cell {
+                Cell::Text(s)=>{
+                    row.push_str(r#"<c t="inlineStr"><is><t xml:space="preserve">"#);
+                    row.push_str(&xml_escape(s));
+                    row.push_str("</t></is></c>");
+                },
+                Cell::Number(n)=>{
+                    row.push_str("<c><v>");
+                    row.push_str(&xml_escape(n));
+                    row.push_str("</v></c>");
+                },
+            }
+        }
+        row.push_str("</row>");
+        self.sheet_data(row)
+    }
+
+    fn finish(&mut self)->Vec<u8>{
+        let mut bytes=self.sheet_data("</sheetData></worksheet>".into());
+
+        // 数据描述符，补写工作表的 CRC 和长度
+        let (crc,size)=(self.sheet_crc,self.sheet_size);
+        let sheet=self.entries.last_mut().unwrap();
+        sheet.crc=crc;
+        sheet.size=size;
+        bytes.extend(0x08074b50u32.to_le_bytes());
+        bytes.extend(crc.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        self.offset+=16;
+
+        // 中央目录
+        let directory_offset=self.offset;
+        let mut directory=Vec::new();
+        for entry in &self.entries {
+            directory.extend(0x02014b50u32.to_le_bytes());
+            directory.extend(ZIP_VERSION.to_le_bytes());
+            directory.extend(ZIP_VERSION.to_le_bytes());
+            directory.extend((if entry.streamed { ZIP_FLAG_DATA_DESCRIPTOR } else { 0 }).to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(ZIP_DOS_DATE.to_le_bytes());
+            directory.extend(entry.crc.to_le_bytes());
+            directory.extend(entry.size.to_le_bytes());
+            directory.extend(entry.size.to_le_bytes());
+            directory.extend((entry.name.len() as u16).to_le_bytes());
+            directory.extend([0u8;12]); // extra、comment 长度，磁盘号，内部、外部属性
+            directory.extend(entry.offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+        bytes.extend(&directory);
+
+        bytes.extend(0x06054b50u32.to_le_bytes());
+        bytes.extend([0u8;4]);
+        bytes.extend((self.entries.len() as u16).to_le_bytes());
+        bytes.extend((self.entries.len() as u16).to_le_bytes());
+        bytes.extend((directory.len() as u32).to_le_bytes());
+        bytes.extend(directory_offset.to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn test_csv(){
+        let mut writer=CsvWriter;
+        let mut bytes=writer.begin(&["姓名","金额"]);
+        bytes.extend(writer.row(&["张三, \"小张\"".into(),Cell::Number("-12.50".into())]));
+        bytes.extend(writer.row(&["=1+1".into(),Cell::Number("3".into())]));
+        bytes.extend(writer.finish());
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "\u{feff}姓名,金额\r\n\"张三, \"\"小张\"\"\",-12.50\r\n'=1+1,3\r\n"
+        );
+    }
+
+    #[test]
+    fn test_crc32(){
+        assert_eq!(crc32_update(0, b"123456789"), 0xCBF43926);
+        assert_eq!(crc32_update(crc32_update(0, b"12345"), b"6789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_xlsx_layout(){
+        let mut writer=XlsxWriter::new("订单");
+        let mut bytes=writer.begin(&["金额"]);
+        bytes.extend(writer.row(&[Cell::Number("1.00".into())]));
+        bytes.extend(writer.finish());
+
+        // 中央目录结束记录：5 个文件，目录偏移与实际一致
+        let end=&bytes[bytes.len()-22..];
+        assert_eq!(&end[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10],end[11]]), 5);
+        let directory_size=u32::from_le_bytes(end[12..16].try_into().unwrap()) as usize;
+        let directory_offset=u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(directory_offset+directory_size, bytes.len()-22);
+        assert_eq!(&bytes[directory_offset..directory_offset+4], &0x02014b50u32.to_le_bytes());
+    }
+}